@@ -156,6 +156,82 @@ jobs:
 
       - name: Run audit
         run: cargo audit
+
+  llvm-lines:
+    name: LLVM Lines
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v4
+
+      - name: Install Rust
+        uses: dtolnay/rust-toolchain@stable
+
+      - name: Cache dependencies
+        uses: Swatinem/rust-cache@v2
+
+      # Only for projects with the budget script from the templates
+      - name: Install cargo-llvm-lines
+        if: hashFiles('ci/check_llvm_lines.sh') != ''
+        run: cargo install cargo-llvm-lines
+
+      - name: Check LLVM IR line budget
+        if: hashFiles('ci/check_llvm_lines.sh') != ''
+        run: ./ci/check_llvm_lines.sh
+
+  throughput:
+    name: Streaming Throughput
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v4
+
+      - name: Install Rust
+        uses: dtolnay/rust-toolchain@stable
+
+      - name: Cache dependencies
+        uses: Swatinem/rust-cache@v2
+
+      # Only for projects with the streaming benchmark from the templates;
+      # fails under 100 MB/s or if 100 MB streams slower than 10 MB
+      - name: Check streaming throughput
+        if: hashFiles('ci/check_throughput.sh') != ''
+        run: |
+          cargo bench --bench streaming_bench
+          ./ci/check_throughput.sh
+
+  instructions:
+    name: Instruction Counts
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v4
+
+      - name: Install Rust
+        uses: dtolnay/rust-toolchain@stable
+
+      - name: Cache dependencies
+        uses: Swatinem/rust-cache@v2
+
+      # Only for projects with the iai-callgrind benchmarks from the templates
+      - name: Install Valgrind and iai-callgrind-runner
+        if: hashFiles('benches/iai_baseline.json') != ''
+        run: |
+          sudo apt-get update && sudo apt-get install -y valgrind
+          cargo install iai-callgrind-runner --version 0.14.2
+
+      # Fails if any count rises more than 2% above benches/iai_baseline.json;
+      # benchmarks with a null baseline are reported but not checked
+      - name: Compare instruction counts with baseline
+        if: hashFiles('benches/iai_baseline.json') != ''
+        run: |
+          cargo bench --bench iai_calculator -- --output-format=json > iai.json
+          jq -rs --slurpfile baseline benches/iai_baseline.json '
+            map({name: "\(.function_name)/\(.id)",
+                 count: (.callgrind_summary.callgrind_run.total.summary.Ir.metrics
+                         | .Left // .Both[0])})
+            | map(.baseline = $baseline[0][.name])
+            | (.[] | "\(.name): \(.count) instructions (baseline \(.baseline))"),
+              (map(select(.baseline != null and .count > .baseline * 1.02))
+               | if length > 0 then error("more than 2% over baseline: \(map(.name))")
+                 else empty end)' iai.json
 WORKFLOW_EOF
 
 echo "✓ Created CI workflow"
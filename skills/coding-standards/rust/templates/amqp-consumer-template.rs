@@ -0,0 +1,440 @@
+//! RabbitMQ consumer template
+//!
+//! Demonstrates:
+//! - Consuming a queue with lapin
+//! - Acknowledging only after a message has been handled
+//! - Rejecting failed messages without requeue so RabbitMQ routes them to
+//!   a dead-letter exchange
+//! - Reconnecting with backoff when the broker drops the connection
+//! - Tests against a throwaway broker started with testcontainers
+//!
+//! Add to Cargo.toml:
+//! [dependencies]
+//! anyhow = "1.0"
+//! futures-util = "0.3"
+//! lapin = "2.5"
+//! tokio = { version = "1.0", features = ["full"] }
+//! tracing = "0.1"
+//!
+//! [dev-dependencies]
+//! testcontainers-modules = { version = "0.11", features = ["rabbitmq"] }
+//!
+//! The tests need a running Docker daemon and are ignored by default; run
+//! them with `cargo test -- --ignored`.
+
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use futures_util::StreamExt;
+use lapin::message::Delivery;
+use lapin::options::{
+    BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions,
+    ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions,
+};
+use lapin::types::{AMQPValue, FieldTable};
+use lapin::{Channel, Connection, ConnectionProperties, Consumer, ExchangeKind};
+use tracing::{debug, info, info_span, warn, Instrument};
+
+/// Appended to the queue name to name its dead-letter exchange
+pub const DLX_SUFFIX: &str = ".dlx";
+
+/// Appended to the queue name to name the queue bound to the dead-letter
+/// exchange
+pub const DLQ_SUFFIX: &str = ".dlq";
+
+/// Unacknowledged messages the broker may push ahead of the handler
+const PREFETCH: u16 = 16;
+
+/// Reconnect attempts before `consume` gives up
+const RECONNECT_ATTEMPTS: u32 = 5;
+
+/// Delay before the first reconnect attempt; doubles after each failure
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+
+/// What happened to a message that was handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    /// Handled successfully and acknowledged
+    Acked,
+    /// Handler failed; rejected without requeue, so the broker moved it to
+    /// the dead-letter exchange
+    Rejected,
+}
+
+/// Consumes a queue and feeds each message to a handler
+///
+/// `new` declares the queue along with a fanout exchange `<queue>.dlx` and
+/// a queue `<queue>.dlq` bound to it. Messages the handler fails on are
+/// rejected and end up in `<queue>.dlq`, where RabbitMQ's `x-death` header
+/// records why.
+pub struct AmqpProcessor {
+    url: String,
+    queue: String,
+    session: Session,
+}
+
+/// Connection-scoped state, rebuilt on every reconnect
+struct Session {
+    connection: Connection,
+    // Held so the channel outlives the consumer reading from it
+    _channel: Channel,
+    consumer: Consumer,
+}
+
+impl AmqpProcessor {
+    /// Connects to `url`, declares the queue topology and starts consuming
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the broker is unreachable or rejects the
+    /// declarations, e.g. because `queue` exists with other arguments
+    pub async fn new(url: &str, queue: &str) -> Result<Self> {
+        let session = Session::open(url, queue).await?;
+        Ok(Self {
+            url: url.to_string(),
+            queue: queue.to_string(),
+            session,
+        })
+    }
+
+    /// Name of the queue rejected messages end up in
+    pub fn dlq(&self) -> String {
+        format!("{}{}", self.queue, DLQ_SUFFIX)
+    }
+
+    /// Handles messages until the handler's queue can no longer be consumed
+    ///
+    /// A dropped connection is re-established with exponential backoff;
+    /// messages that were delivered but not yet acknowledged are redelivered
+    /// by the broker.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error once reconnecting has failed `RECONNECT_ATTEMPTS`
+    /// times, or if acknowledging a message fails on a live connection
+    pub async fn consume<F>(&mut self, handler: F) -> Result<()>
+    where
+        F: Fn(String) -> Result<String>,
+    {
+        info!(
+            "Consuming {} (dead letters go to {})",
+            self.queue,
+            self.dlq()
+        );
+        loop {
+            match self.process_next(&handler).await {
+                Ok(_) => {}
+                Err(e) if !self.session.connection.status().connected() => {
+                    warn!("Connection lost: {:#}", e);
+                    self.reconnect().await?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Waits for the next message, handles it and settles it
+    pub async fn process_next<F>(&mut self, handler: &F) -> Result<Disposition>
+    where
+        F: Fn(String) -> Result<String>,
+    {
+        let delivery = match self.session.consumer.next().await {
+            Some(delivery) => delivery.context("Failed to receive message")?,
+            None => bail!("Consumer for {} was cancelled", self.queue),
+        };
+        let span = info_span!("message", delivery_tag = delivery.delivery_tag);
+        handle(delivery, handler).instrument(span).await
+    }
+
+    async fn reconnect(&mut self) -> Result<()> {
+        let mut delay = RECONNECT_DELAY;
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            match Session::open(&self.url, &self.queue).await {
+                Ok(session) => {
+                    info!("Reconnected after {} attempt(s)", attempt);
+                    self.session = session;
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!("Reconnect attempt {} failed: {:#}", attempt, e);
+                    delay *= 2;
+                }
+            }
+        }
+        bail!("Gave up reconnecting after {} attempts", RECONNECT_ATTEMPTS)
+    }
+}
+
+impl Session {
+    async fn open(url: &str, queue: &str) -> Result<Self> {
+        let connection = Connection::connect(url, ConnectionProperties::default())
+            .await
+            .context("Cannot connect to AMQP broker")?;
+        let channel = connection
+            .create_channel()
+            .await
+            .context("Cannot open channel")?;
+
+        declare_topology(&channel, queue).await?;
+        channel
+            .basic_qos(PREFETCH, BasicQosOptions::default())
+            .await
+            .context("Cannot set prefetch")?;
+
+        let consumer = channel
+            .basic_consume(
+                queue,
+                "",
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .context(format!("Cannot consume from {}", queue))?;
+
+        Ok(Self {
+            connection,
+            _channel: channel,
+            consumer,
+        })
+    }
+}
+
+/// Declares `queue`, its dead-letter exchange and the dead-letter queue
+///
+/// Declarations are idempotent, so this also runs on every reconnect.
+async fn declare_topology(channel: &Channel, queue: &str) -> Result<()> {
+    let dlx = format!("{}{}", queue, DLX_SUFFIX);
+    let dlq = format!("{}{}", queue, DLQ_SUFFIX);
+    let durable = QueueDeclareOptions {
+        durable: true,
+        ..QueueDeclareOptions::default()
+    };
+
+    channel
+        .exchange_declare(
+            &dlx,
+            ExchangeKind::Fanout,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..ExchangeDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .context(format!("Cannot declare exchange {}", dlx))?;
+    channel
+        .queue_declare(&dlq, durable, FieldTable::default())
+        .await
+        .context(format!("Cannot declare queue {}", dlq))?;
+    channel
+        .queue_bind(
+            &dlq,
+            &dlx,
+            "",
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .context(format!("Cannot bind {} to {}", dlq, dlx))?;
+
+    let mut arguments = FieldTable::default();
+    arguments.insert(
+        "x-dead-letter-exchange".into(),
+        AMQPValue::LongString(dlx.into()),
+    );
+    channel
+        .queue_declare(queue, durable, arguments)
+        .await
+        .context(format!("Cannot declare queue {}", queue))?;
+    Ok(())
+}
+
+async fn handle<F>(delivery: Delivery, handler: &F) -> Result<Disposition>
+where
+    F: Fn(String) -> Result<String>,
+{
+    let result = String::from_utf8(delivery.data.clone())
+        .context("Message is not valid UTF-8")
+        .and_then(handler);
+
+    match result {
+        Ok(output) => {
+            debug!("Processed message into {} bytes", output.len());
+            delivery
+                .ack(BasicAckOptions::default())
+                .await
+                .context("Failed to acknowledge message")?;
+            Ok(Disposition::Acked)
+        }
+        Err(e) => {
+            warn!("Processing failed, rejecting: {:#}", e);
+            delivery
+                .nack(BasicNackOptions {
+                    requeue: false,
+                    ..BasicNackOptions::default()
+                })
+                .await
+                .context("Failed to reject message")?;
+            Ok(Disposition::Rejected)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lapin::options::{BasicGetOptions, BasicPublishOptions};
+    use lapin::BasicProperties;
+    use testcontainers_modules::rabbitmq::RabbitMq;
+    use testcontainers_modules::testcontainers::core::ExecCommand;
+    use testcontainers_modules::testcontainers::runners::AsyncRunner;
+    use testcontainers_modules::testcontainers::ContainerAsync;
+
+    const QUEUE: &str = "input";
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    async fn broker() -> (ContainerAsync<RabbitMq>, String) {
+        let node = RabbitMq::default().start().await.unwrap();
+        let port = node.get_host_port_ipv4(5672).await.unwrap();
+        (node, format!("amqp://127.0.0.1:{}", port))
+    }
+
+    async fn channel(url: &str) -> Channel {
+        let connection = Connection::connect(url, ConnectionProperties::default())
+            .await
+            .unwrap();
+        connection.create_channel().await.unwrap()
+    }
+
+    async fn publish(url: &str, payload: &str) {
+        channel(url)
+            .await
+            .basic_publish(
+                "",
+                QUEUE,
+                BasicPublishOptions::default(),
+                payload.as_bytes(),
+                BasicProperties::default(),
+            )
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+
+    /// Takes one message off `queue`, waiting for it to arrive
+    async fn get(url: &str, queue: &str) -> Option<Vec<u8>> {
+        let channel = channel(url).await;
+        for _ in 0..50 {
+            let message = channel
+                .basic_get(queue, BasicGetOptions { no_ack: true })
+                .await
+                .unwrap();
+            if let Some(message) = message {
+                return Some(message.delivery.data);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        None
+    }
+
+    /// Ready messages in `queue`; unacked messages return to the queue once
+    /// their consumer is dropped, so this also catches missing acks
+    async fn message_count(url: &str, queue: &str) -> u32 {
+        // Give the broker a moment to requeue deliveries of a closed consumer
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let queue = channel(url)
+            .await
+            .queue_declare(
+                queue,
+                QueueDeclareOptions {
+                    passive: true,
+                    ..QueueDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .unwrap();
+        queue.message_count()
+    }
+
+    async fn next<F>(processor: &mut AmqpProcessor, handler: F) -> Result<Disposition>
+    where
+        F: Fn(String) -> Result<String>,
+    {
+        tokio::time::timeout(TIMEOUT, processor.process_next(&handler))
+            .await
+            .expect("no message received")
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_success_acks_message() {
+        let (_node, url) = broker().await;
+        let mut processor = AmqpProcessor::new(&url, QUEUE).await.unwrap();
+        publish(&url, "hello").await;
+
+        let disposition = next(&mut processor, |input| Ok(input.to_uppercase())).await;
+
+        assert_eq!(disposition.unwrap(), Disposition::Acked);
+        drop(processor);
+        assert_eq!(message_count(&url, QUEUE).await, 0);
+        assert_eq!(
+            message_count(&url, &format!("{}{}", QUEUE, DLQ_SUFFIX)).await,
+            0
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_failure_goes_to_dead_letter_exchange() {
+        let (_node, url) = broker().await;
+        let mut processor = AmqpProcessor::new(&url, QUEUE).await.unwrap();
+        publish(&url, "hello").await;
+
+        let disposition = next(&mut processor, |_| bail!("downstream unavailable")).await;
+
+        assert_eq!(disposition.unwrap(), Disposition::Rejected);
+        assert_eq!(
+            get(&url, &processor.dlq()).await.as_deref(),
+            Some(&b"hello"[..])
+        );
+        drop(processor);
+        assert_eq!(message_count(&url, QUEUE).await, 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_consume_recovers_from_dropped_connection() {
+        let (node, url) = broker().await;
+        let mut processor = AmqpProcessor::new(&url, QUEUE).await.unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let consumer = tokio::spawn(async move {
+            processor
+                .consume(move |input| {
+                    tx.send(input.clone())?;
+                    Ok(input)
+                })
+                .await
+        });
+
+        publish(&url, "before").await;
+        let received = tokio::time::timeout(TIMEOUT, rx.recv()).await.unwrap();
+        assert_eq!(received.as_deref(), Some("before"));
+
+        node.exec(ExecCommand::new([
+            "rabbitmqctl",
+            "close_all_connections",
+            "test",
+        ]))
+        .await
+        .unwrap();
+        publish(&url, "after").await;
+
+        let received = tokio::time::timeout(TIMEOUT, rx.recv()).await.unwrap();
+        assert_eq!(received.as_deref(), Some("after"));
+        assert!(!consumer.is_finished());
+        consumer.abort();
+    }
+}
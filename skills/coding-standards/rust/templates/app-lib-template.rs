@@ -0,0 +1,9698 @@
+//! Application library template
+//!
+//! Save as `src/lib.rs` of the binary crate; `main-template.rs` is the thin
+//! `src/main.rs` wrapper around it. Keeping `App` in a library lets
+//! integration tests and other crates drive a full run without spawning
+//! the binary.
+//!
+//! Demonstrates:
+//! - CLI argument definitions with clap
+//! - Streaming, multi-file processing with structured logging
+//! - Error handling with anyhow
+//! - Optional WebSocket server mode (see websocket-template.rs)
+//! - Optional HTTP REST API mode (see http-server-template.rs)
+//! - Crash-safe output files written via temp file and atomic rename
+//! - sed-style in-place editing, confirmed interactively for large batches
+//! - Refusing to clobber existing outputs unless `--force` is given
+//! - Creating missing output directories on request (`--create-dirs`)
+//! - Naming outputs from a pattern such as
+//!   `--output-template "{dir}/{stem}.{transform}.{ext}"`
+//! - Transparent gzip decompression and compression with flate2
+//! - Decoding Latin-1 and UTF-16 inputs, and encoding outputs, with
+//!   encoding_rs (`--encoding`, `--output-encoding`)
+//! - Splitting outputs into numbered parts listed in a manifest
+//!   (`--split-lines`, `--split-bytes`)
+//! - Machine-readable JSON/YAML result records (`--format`)
+//! - grep-style line filtering and counting
+//! - Dropping repeated lines by hash, optionally within an LRU window
+//!   (`--dedup`, `--dedup-window`)
+//! - Sorting and deduplicating the transformed lines (`--sort`, `--dedupe`)
+//! - Turkish and ASCII-only case rules (`--case-locale`)
+//! - Dry runs that print a unified diff (`--diff`)
+//! - Line ending, byte order mark, and final newline normalization
+//! - Layered TOML configuration files (`--config base.toml prod.toml`) that
+//!   default the flags not given, with `[profile.<name>]` overrides per
+//!   environment (`--profile`), scaffolded by the `init` subcommand and
+//!   checked by `config validate`
+//! - SHA-256 input verification and `sha256sum`-compatible output checksums
+//! - Progress bars with indicatif that stay clear of log output
+//! - Stopping cleanly on Ctrl-C, without leaving temp files behind
+//! - Resuming an interrupted batch from a checkpoint file (`--checkpoint`)
+//! - Per-input size limits and timeouts (`--max-file-size 10MB`, `--timeout 30s`)
+//! - Retrying reads and writes that fail transiently (`--io-retries`)
+//! - A read-through cache of whole files, keyed by path and modification time
+//! - Shell completion and man page subcommands (clap_complete, clap_mangen)
+//! - A `stats` subcommand counting lines, words, characters and bytes in
+//!   one streaming pass, and fields with a sniffed separator (`--separator`)
+//! - A JSON report of every input's outcome (`--report`)
+//! - Results alone on stdout; logs and the run summary (`--summary`) on stderr
+//! - A run ID and per-file spans on every log event, as text or JSON
+//!   (`--log-format json`)
+//! - Transforming only the lines of a time range (`--since`/`--until`)
+//!
+//! Add to Cargo.toml:
+//! [dependencies]
+//! anyhow = "1.0"
+//! chrono = "0.4"
+//! clap = { version = "4.0", features = ["derive", "env"] }
+//! clap_complete = "4.0"
+//! clap_mangen = "0.2"
+//! console = "0.15"
+//! ctrlc = "3.4"
+//! encoding_rs = "0.8"
+//! flate2 = "1.0"
+//! foldhash = "0.1"
+//! hashlink = "0.10"
+//! indicatif = "0.17"
+//! my_lib = { path = "../my_lib" }
+//! rayon = "1.0"
+//! regex = "1.0"
+//! serde = { version = "1.0", features = ["derive"] }
+//! serde_json = "1.0"
+//! serde_yaml = "0.9"
+//! sha2 = "0.10"
+//! similar = "2.0"
+//! tempfile = "3.8"
+//! thiserror = "1.0"
+//! toml = "0.8"
+//! tracing = "0.1"
+//! tracing-subscriber = { version = "0.3", features = ["json"] }
+//! ulid = "1"
+//! unicode-segmentation = "1.10"
+//!
+//! [dev-dependencies]
+//! proptest = "1.0"
+//!
+//! Server modes add the dependencies listed in their own module templates.
+
+pub mod http;
+pub mod websocket;
+
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::hash::BuildHasher;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Seek, Write};
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, FixedOffset, Local};
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand, ValueEnum};
+use encoding_rs::{DecoderResult, EncoderResult};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use foldhash::fast::RandomState;
+use hashlink::LruCache;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use my_lib::LibError;
+use rayon::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use thiserror::Error;
+use tracing::{debug, error, info, info_span, warn, Span};
+use tracing_subscriber::fmt::MakeWriter;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// CLI application
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+#[command(group(ArgGroup::new("time_range").args(["since", "until"]).multiple(true)))]
+#[command(group(ArgGroup::new("file_output").args(["output", "out_dir", "output_template"])))]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
+pub struct Args {
+    /// Input file paths (`-` reads stdin)
+    #[arg(short, long, required_unless_present_any = ["websocket", "http_port"], num_args = 1..)]
+    pub input: Vec<String>,
+
+    /// Output file path (single input only)
+    #[arg(short, long, conflicts_with = "out_dir")]
+    pub output: Option<String>,
+
+    /// Directory receiving one output file per input
+    #[arg(long)]
+    pub out_dir: Option<String>,
+
+    /// Output path for each input, built from the placeholders {dir},
+    /// {stem}, {ext}, {transform}, {date} (YYYY-MM-DD) and {hash8} (first 8
+    /// hex digits of the output's SHA-256, file name only)
+    #[arg(long, value_name = "TEMPLATE")]
+    pub output_template: Option<OutputTemplate>,
+
+    /// Create the output's directory, and any missing parents, if needed
+    #[arg(long)]
+    pub create_dirs: bool,
+
+    /// Input compression [default: gzip for `.gz` files and data starting
+    /// with the gzip magic bytes, otherwise none]
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub input_compression: Option<Compression>,
+
+    /// Output compression [default: gzip for `.gz` files, otherwise none]
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub output_compression: Option<Compression>,
+
+    /// Character encoding of the inputs; `auto` goes by a byte order mark,
+    /// then takes UTF-8, UTF-16 or Latin-1, whichever the start fits
+    #[arg(long, value_enum, value_name = "ENCODING", default_value_t = EncodingChoice::Auto)]
+    pub encoding: EncodingChoice,
+
+    /// Replace bytes the input encoding cannot decode with U+FFFD instead
+    /// of failing
+    #[arg(long)]
+    pub lossy: bool,
+
+    /// Character encoding of the outputs
+    #[arg(long, value_enum, value_name = "ENCODING", default_value_t = TextEncoding::Utf8)]
+    pub output_encoding: TextEncoding,
+
+    /// Write each output as parts `<name>.part0001`, `<name>.part0002`, ...
+    /// of at most this many lines, listed in `<name>.manifest.json`
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "file_output",
+        conflicts_with_all = ["split_bytes", "in_place", "count"]
+    )]
+    pub split_lines: Option<u64>,
+
+    /// Like `--split-lines`, but parts of at most this size before
+    /// compression, e.g. `100MB`; parts only end between lines, so a longer
+    /// line gets a part of its own
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        requires = "file_output",
+        conflicts_with_all = ["in_place", "count"]
+    )]
+    pub split_bytes: Option<u64>,
+
+    /// Replace output files that already exist
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// Keep the previous contents of each output file, or the original
+    /// input with `--in-place`, as `<name><SUFFIX>` [default suffix: .bak]
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ".bak"
+    )]
+    pub backup: Option<String>,
+
+    /// Overwrite each input with its output, keeping the original at
+    /// `<name><SUFFIX>` if a suffix is given (e.g. `--in-place=.orig`)
+    #[arg(
+        short = 'I',
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        conflicts_with_all = ["output", "out_dir", "output_template"]
+    )]
+    pub in_place: Option<String>,
+
+    /// Ask before `--in-place` modifies more than this many files
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    pub confirm_threshold: usize,
+
+    /// Modify files in place without asking, however many there are;
+    /// needed for large batches when stdin is not a terminal
+    #[arg(short = 'y', long)]
+    pub yes: bool,
+
+    /// Number of files to process concurrently [default: 0, every logical core]
+    ///
+    /// Workers run in a pool of their own; rayon's global pool is left to
+    /// the embedding process
+    #[arg(short, long, visible_alias = "threads", value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Record each completed input in this file; a rerun with the same file
+    /// skips the inputs it lists whose contents have not changed since
+    #[arg(long, value_name = "PATH", conflicts_with = "diff")]
+    pub checkpoint: Option<String>,
+
+    /// Truncate the `--checkpoint` file and process every input again
+    #[arg(long, requires = "checkpoint")]
+    pub no_resume: bool,
+
+    /// Skip inputs larger than this as stored on disk, e.g. `10MB` or
+    /// `1.5GiB`; stdin is not limited
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_file_size: Option<u64>,
+
+    /// Fail inputs over `--max-file-size` instead of skipping them
+    #[arg(long, requires = "max_file_size")]
+    pub strict_limits: bool,
+
+    /// Give up on an input still being processed after this long, e.g.
+    /// `30s` or `2m`; checked between lines, and before and after a
+    /// transform of the whole input at once
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub timeout: Option<Duration>,
+
+    /// Retry a file read or output write up to this many times when it
+    /// fails transiently (interrupted, would block or timed out); other
+    /// errors fail at once
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub io_retries: u32,
+
+    /// Wait this long before each I/O retry, e.g. `100ms`
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "100ms")]
+    pub io_retry_delay: Duration,
+
+    /// Fail an input as soon as one of its lines is longer than this, e.g.
+    /// `64KiB`, instead of buffering the whole line; checked when inputs
+    /// are processed line by line
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "1MiB")]
+    pub max_line_length: u64,
+
+    /// Transform to apply
+    #[arg(short, long, value_enum, default_value_t = Mode::Upper)]
+    pub mode: Mode,
+
+    /// Case rules for `--mode upper` and `--mode lower`
+    #[arg(long, value_enum, value_name = "LOCALE", default_value_t = CaseLocale::Default)]
+    pub case_locale: CaseLocale,
+
+    /// How results are reported on stdout
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Only keep lines matching this regular expression; the rest are
+    /// dropped before the transform runs
+    #[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+    pub grep: Option<Regex>,
+
+    /// Line terminator written for each line
+    #[arg(long, value_enum, default_value_t = Newline::Preserve)]
+    pub newline: Newline,
+
+    /// Drop a UTF-8 byte order mark from the start of each input
+    #[arg(long)]
+    pub strip_bom: bool,
+
+    /// Whether the output ends with a line terminator; by default it does
+    /// if the input did, on stdout and in files alike
+    #[arg(long, value_enum, default_value_t = FinalNewline::Preserve)]
+    pub final_newline: FinalNewline,
+
+    /// End the output without a line terminator; same as
+    /// `--final-newline strip`
+    #[arg(long, conflicts_with = "final_newline")]
+    pub no_trailing_newline: bool,
+
+    /// Keep the lines `--grep` does not match instead
+    #[arg(long, requires = "grep")]
+    pub invert_match: bool,
+
+    /// Drop each line that repeats an earlier line of the same input,
+    /// keeping the first in place; lines are compared without terminators
+    #[arg(long)]
+    pub dedup: bool,
+
+    /// Only compare lines with the last N distinct lines, so memory stays
+    /// bounded on endless streams
+    #[arg(long, value_name = "N", requires = "dedup")]
+    pub dedup_window: Option<NonZeroUsize>,
+
+    /// Sort the transformed lines; lines that compare equal keep their
+    /// input order
+    #[arg(long)]
+    pub sort: bool,
+
+    /// How `--sort` compares lines [default: lexical]
+    #[arg(long, value_enum, value_name = "MODE", requires = "sort")]
+    pub sort_mode: Option<SortMode>,
+
+    /// Drop repeats from the transformed lines, after `--sort` when both
+    /// are given; unlike `--dedup`, lines are compared as transformed
+    #[arg(long, conflicts_with = "count")]
+    pub dedupe: bool,
+
+    /// Which repeats `--dedupe` drops [default: adjacent]
+    #[arg(long, value_enum, value_name = "MODE", requires = "dedupe")]
+    pub dedupe_mode: Option<DedupeMode>,
+
+    /// Memory `--dedupe-mode global` may use to remember the lines it has
+    /// seen, e.g. `1GiB`; an input that needs more fails
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "256MiB")]
+    pub dedupe_memory: u64,
+
+    /// Only transform lines timestamped at or after this RFC 3339 time
+    /// (e.g. 2024-05-01T00:00:00Z); other lines are copied unchanged
+    #[arg(long, value_name = "TIME", value_parser = DateTime::parse_from_rfc3339)]
+    pub since: Option<DateTime<FixedOffset>>,
+
+    /// Only transform lines timestamped at or before this RFC 3339 time
+    #[arg(long, value_name = "TIME", value_parser = DateTime::parse_from_rfc3339)]
+    pub until: Option<DateTime<FixedOffset>>,
+
+    /// Regular expression locating each line's timestamp: its first capture
+    /// group, or the whole match [default: the first whitespace-separated
+    /// field]
+    #[arg(long, value_name = "REGEX", value_parser = Regex::new, requires = "time_range")]
+    pub timestamp_field: Option<Regex>,
+
+    /// Drop lines outside `--since`/`--until` instead of copying them
+    #[arg(long, requires = "time_range")]
+    pub drop_out_of_range: bool,
+
+    /// What to do with lines that have no parseable timestamp
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ACTION",
+        default_value_t = Untimed::Error,
+        requires = "time_range"
+    )]
+    pub untimed_lines: Untimed,
+
+    /// Output only the number of lines kept, and inside `--since`/`--until`
+    /// if given, instead of the transformed text
+    #[arg(long)]
+    pub count: bool,
+
+    /// Print a unified diff of what would change instead of writing it;
+    /// exits 1 if any file would change
+    #[arg(long, conflicts_with_all = ["output", "out_dir", "output_template", "count"])]
+    pub diff: bool,
+
+    /// Colorize `--diff` output, error messages and logs
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Refuse to process the input unless its SHA-256 digest is this one
+    #[arg(long, value_name = "SHA256HEX", value_parser = parse_sha256)]
+    pub verify_input: Option<String>,
+
+    /// Write the SHA-256 digest of each output to `<output>.sha256`, in
+    /// `sha256sum` format; for stdout it goes to stderr instead
+    #[arg(long, conflicts_with = "diff")]
+    pub emit_checksum: bool,
+
+    /// When the run ends, write a JSON report of every input's outcome to
+    /// this file (`-` for stdout), even if the run failed or was interrupted
+    #[arg(long, value_name = "PATH")]
+    pub report: Option<String>,
+
+    /// Show progress on stderr: bytes read for a single input, files done
+    /// for several
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ProgressChoice::Auto)]
+    pub progress: ProgressChoice,
+
+    /// When to log how many inputs succeeded, were skipped or failed once
+    /// the run ends; `auto` does for several inputs
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = SummaryChoice::Auto)]
+    pub summary: SummaryChoice,
+
+    /// Verbose mode
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Write logs as text or as one JSON object per line
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Show the full cause chain of an error instead of a short hint
+    #[arg(long)]
+    pub debug_errors: bool,
+
+    /// When the run fails, also print the platform, version, effective
+    /// configuration and resolved paths, for attaching to a bug report;
+    /// file contents are never included
+    #[arg(long)]
+    pub diagnostics: bool,
+
+    /// Abort the process as soon as a panic is logged instead of unwinding
+    #[arg(long)]
+    pub abort_on_panic: bool,
+
+    /// Panic right after startup, to check how panics are reported
+    #[arg(long, hide = true)]
+    pub debug_panic: bool,
+
+    /// Configuration files, merged left to right so later files override
+    /// keys set by earlier ones
+    #[arg(short, long, num_args = 1.., default_value = "config.toml")]
+    pub config: Vec<String>,
+
+    /// Apply the `[profile.<NAME>]` table of the config files over the
+    /// rest of them, e.g. `dev` or `prod`
+    #[arg(long, value_name = "NAME", env = PROFILE_VAR)]
+    pub profile: Option<String>,
+
+    /// Hide file paths and input previews in log output
+    #[arg(long)]
+    pub redact: bool,
+
+    /// Serve MyLib over WebSocket on this address instead of processing files
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["input", "output", "out_dir"])]
+    pub websocket: Option<SocketAddr>,
+
+    /// Serve the REST API on this port instead of processing files
+    #[arg(
+        long,
+        value_name = "PORT",
+        conflicts_with_all = ["input", "output", "out_dir", "websocket"]
+    )]
+    pub http_port: Option<u16>,
+
+    /// Origins allowed to call the REST API from a browser, comma-separated
+    /// (`*` allows any)
+    #[arg(
+        long,
+        value_name = "ORIGINS",
+        value_delimiter = ',',
+        requires = "http_port"
+    )]
+    pub cors_origins: Vec<String>,
+
+    /// Require this key in the `X-API-Key` header of REST API requests
+    #[arg(long, value_name = "KEY", requires = "http_port")]
+    pub api_key: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+/// Subcommands that do something other than transform their inputs
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Commands {
+    /// Print a completion script for SHELL to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print a man page in roff format to stdout
+    Man,
+    /// Write a commented config file to start from
+    Init {
+        /// Where to write the file
+        #[arg(long, default_value = "config.toml")]
+        path: PathBuf,
+        /// List every setting with its default instead of a minimal file
+        #[arg(long)]
+        full: bool,
+        /// Overwrite the file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Count the lines, words, characters and bytes of each input
+    Stats {
+        /// Input file paths (`-` reads stdin); gzip is decompressed first
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Print a table, or a record per input
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+        /// Also count fields split by SEP: a character, `tab`, or `auto` to
+        /// pick tab, comma or pipe from the first lines of each input
+        #[arg(long, value_name = "SEP")]
+        separator: Option<Separator>,
+    },
+    /// Work with config files
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+/// What the `config` subcommand does
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum ConfigAction {
+    /// Check config files without running, listing every problem found
+    Validate {
+        /// Configuration files, merged left to right as a run merges them
+        #[arg(short, long, num_args = 1.., default_value = "config.toml")]
+        config: Vec<String>,
+        /// Also check the `[profile.<NAME>]` table applied as a run would
+        #[arg(long, value_name = "NAME", env = PROFILE_VAR)]
+        profile: Option<String>,
+        /// Fail on unknown keys instead of warning about them
+        #[arg(long)]
+        strict: bool,
+    },
+}
+
+impl Commands {
+    /// Writes the completion script, man page, statistics or config report
+    /// to `out`, or the config file for `init`
+    ///
+    /// All are generated from [`Args`], so they always list the current
+    /// flags and the values of enum options such as `--mode`.
+    pub fn run(self, out: &mut dyn Write) -> Result<()> {
+        let mut command = Args::command();
+        match self {
+            Commands::Completions { shell } => {
+                let name = command.get_name().to_string();
+                clap_complete::generate(shell, &mut command, name, out);
+            }
+            Commands::Man => clap_mangen::Man::new(command).render(out)?,
+            Commands::Init { path, full, force } => {
+                let mut file = match OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .create_new(!force)
+                    .open(&path)
+                {
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                        return Err(AppError::ConfigExists(path.display().to_string()).into())
+                    }
+                    opened => opened.context(format!("Cannot write file: {}", path.display()))?,
+                };
+                file.write_all(render_config(full).as_bytes())?;
+                writeln!(out, "Wrote {}", path.display())?;
+            }
+            Commands::Stats {
+                inputs,
+                format,
+                separator,
+            } => {
+                let app = App::new(Config::default());
+                let mut records = Vec::with_capacity(inputs.len());
+                for input in inputs {
+                    let stats = app
+                        .open_input(&input)
+                        .and_then(|reader| Ok(TextStats::read(reader, separator)?))
+                        .context(format!("Cannot read file: {}", input))?;
+                    records.push(StatsRecord { input, stats });
+                }
+                match format {
+                    OutputFormat::Text => write_stats_table(&records, out)?,
+                    format => format.write_records(&records, out)?,
+                }
+            }
+            Commands::Config {
+                action:
+                    ConfigAction::Validate {
+                        config,
+                        profile,
+                        strict,
+                    },
+            } => {
+                let report = validate_config(&config, profile.as_deref(), strict);
+                for problem in &report.problems {
+                    writeln!(out, "{}", problem)?;
+                }
+                writeln!(out, "{}", report.summary(&config))?;
+                let errors = report.errors();
+                if errors > 0 {
+                    return Err(AppError::InvalidConfig(errors).into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Settings `init` writes, each with its default as TOML; the ones marked
+/// `false` are unset by default and written commented out, with an example
+const CONFIG_KEYS: [(&str, &str, bool); 10] = [
+    ("mode", "\"upper\"", true),
+    ("format", "\"text\"", true),
+    ("jobs", "0", true),
+    ("progress", "\"auto\"", true),
+    ("create-dirs", "false", true),
+    ("split-lines", "100000", false),
+    ("split-bytes", "\"100MB\"", false),
+    ("max-file-size", "\"10MB\"", false),
+    ("max-line-length", "\"1MiB\"", true),
+    ("timeout", "\"30s\"", false),
+];
+
+/// Text of the config file `init` writes: only `mode` unless `full`
+///
+/// Each setting is described by the help of its flag, so the comments
+/// follow the CLI.
+fn render_config(full: bool) -> String {
+    let command = Args::command();
+    let mut text = format!(
+        "# Configuration for {}\n\
+         #\n\
+         # Each setting is the default for the flag of the same name; a flag\n\
+         # given on the command line still wins.\n",
+        command.get_name()
+    );
+    if !full {
+        text.push_str("# `init --full` writes every setting with its default.\n");
+    }
+    let keys = if full {
+        &CONFIG_KEYS[..]
+    } else {
+        &CONFIG_KEYS[..1]
+    };
+    for (key, value, set) in keys {
+        let help = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(*key))
+            .and_then(|arg| arg.get_help())
+            .map_or_else(String::new, ToString::to_string);
+        text.push('\n');
+        for line in wrap_comment(&help, 76) {
+            text.push_str(&format!("# {}\n", line));
+        }
+        if *set {
+            text.push_str(&format!("{} = {}\n", key, value));
+        } else {
+            text.push_str(&format!(
+                "# Unset by default, e.g.:\n# {} = {}\n",
+                key, value
+            ));
+        }
+    }
+    text
+}
+
+/// Splits `text` into lines of at most `width` characters at spaces
+fn wrap_comment(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.len() + 1 + word.len() <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    lines
+}
+
+/// Counts the `stats` subcommand reports for one input
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct TextStats {
+    /// Line terminators, plus one for a last line without one
+    pub lines: u64,
+    /// Words as found by Unicode word boundaries (UAX #29); punctuation
+    /// and whitespace are not words
+    pub words: u64,
+    pub chars: u64,
+    pub bytes: u64,
+    /// Characters in the longest line, excluding its terminator
+    pub longest_line: u64,
+    pub encoding: Encoding,
+    /// Fields in the non-empty lines, when counted with a [`Separator`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<u64>,
+    /// The separator the fields were split by; none when `auto` found none,
+    /// so each non-empty line is one field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separator: Option<char>,
+}
+
+impl TextStats {
+    /// Counts everything `reader` yields, holding one buffer of it at a time,
+    /// and its fields too if given a `separator`
+    pub fn read(mut reader: impl Read, separator: Option<Separator>) -> io::Result<Self> {
+        let mut counter =
+            separator.map_or_else(StatsCounter::default, StatsCounter::with_separator);
+        let mut buf = vec![0; STREAM_BUFFER_SIZE];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => return Ok(counter.finish()),
+                Ok(read) => counter.update(&buf[..read]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// How an input appears to be encoded, judged from its bytes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Encoding {
+    /// Only 7-bit bytes, which includes an empty input
+    #[default]
+    #[serde(rename = "ascii")]
+    Ascii,
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// UTF-8 starting with a byte order mark
+    #[serde(rename = "utf-8-bom")]
+    Utf8Bom,
+    /// A UTF-16 byte order mark; the other counts read the input as UTF-8,
+    /// so they mean little
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    /// Not valid UTF-8; each invalid sequence counts as one U+FFFD
+    #[serde(rename = "unknown")]
+    Unknown,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Ascii => "ascii",
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Unknown => "unknown",
+        })
+    }
+}
+
+/// What splits the lines of an input into fields for `stats --separator`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Separator {
+    /// Sniffed from the input, see [`sniff_separator`]
+    Auto,
+    Char(char),
+}
+
+impl FromStr for Separator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (s, chars.next(), chars.next()) {
+            ("auto", ..) => Ok(Separator::Auto),
+            ("tab" | "\\t", ..) => Ok(Separator::Char('\t')),
+            (_, Some(c), None) if c != '\n' && c != '\r' => Ok(Separator::Char(c)),
+            _ => Err(format!(
+                "invalid separator {:?}; use auto, tab or a single character",
+                s
+            )),
+        }
+    }
+}
+
+/// Separators `--separator auto` chooses from, most preferred first
+const SEPARATOR_CANDIDATES: [char; 3] = ['\t', ',', '|'];
+
+/// Non-empty lines at the start of an input that `auto` sniffs
+const SNIFF_LINES: usize = 5;
+
+/// Picks the index of the candidate most likely to separate fields, given
+/// how many of each candidate every sampled line holds
+///
+/// A separator shows up on every line of a table, and usually the same
+/// number of times, while a comma or pipe inside a text field comes and
+/// goes. So a candidate missing from any sampled line is out; of the rest,
+/// one with the same count on every line beats one whose count varies,
+/// then the higher smallest count wins, then the earlier candidate. A
+/// quoted CSV field holding a comma makes that line's count vary, but the
+/// comma still wins over candidates absent from some lines.
+pub fn sniff_separator(sample: &[Vec<u64>]) -> Option<usize> {
+    let first = sample.first()?;
+    (0..first.len())
+        .filter(|&i| sample.iter().all(|line| line[i] > 0))
+        .max_by_key(|&i| {
+            let consistent = sample.iter().all(|line| line[i] == first[i]);
+            let fewest = sample.iter().map(|line| line[i]).min();
+            (consistent, fewest, cmp::Reverse(i))
+        })
+}
+
+/// Builds [`TextStats`] from an input fed in chunks of any size
+///
+/// A chunk can end inside a UTF-8 sequence, a word or a CRLF, so whatever
+/// might continue into the next chunk is carried over: the counts never
+/// depend on where the chunks split. Only the text after the last
+/// whitespace is held back, so memory is bounded by the chunk size and the
+/// longest word.
+#[derive(Debug, Default)]
+pub struct StatsCounter {
+    stats: TextStats,
+    /// The first bytes of the input, enough to recognize a byte order mark
+    head: Vec<u8>,
+    /// An incomplete UTF-8 sequence at the end of the last chunk
+    partial: Vec<u8>,
+    /// Decoded text not counted yet, from the last whitespace on
+    pending: String,
+    /// Characters of the current line so far
+    line_chars: u64,
+    /// The last character counted was a `\r`, which ends the line if a
+    /// `\n` follows and belongs to it otherwise
+    pending_cr: bool,
+    non_ascii: bool,
+    invalid: bool,
+    /// Separators counted for fields, each with its total so far; empty
+    /// unless counting fields
+    separators: Vec<(char, u64)>,
+    /// Pick one of `separators` by [`sniff_separator`] at the end
+    sniff: bool,
+    /// Count of each separator on the current line
+    line_separators: Vec<u64>,
+    /// `line_separators` of the first [`SNIFF_LINES`] non-empty lines
+    sample: Vec<Vec<u64>>,
+    non_empty_lines: u64,
+}
+
+impl StatsCounter {
+    /// Counter that also counts fields split by `separator`
+    ///
+    /// For `auto`, every candidate is counted through the whole input, so
+    /// the choice made at the end needs no second pass.
+    pub fn with_separator(separator: Separator) -> Self {
+        let candidates = match separator {
+            Separator::Auto => SEPARATOR_CANDIDATES.to_vec(),
+            Separator::Char(c) => vec![c],
+        };
+        Self {
+            line_separators: vec![0; candidates.len()],
+            separators: candidates.into_iter().map(|c| (c, 0)).collect(),
+            sniff: separator == Separator::Auto,
+            ..Self::default()
+        }
+    }
+
+    /// Counts the next chunk of the input
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.stats.bytes += chunk.len() as u64;
+        let wanted = 3_usize.saturating_sub(self.head.len()).min(chunk.len());
+        self.head.extend_from_slice(&chunk[..wanted]);
+
+        let mut bytes = std::mem::take(&mut self.partial);
+        bytes.extend_from_slice(chunk);
+        let mut rest = bytes.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(text) => {
+                    self.pending.push_str(text);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    self.pending
+                        .push_str(std::str::from_utf8(valid).expect("valid up to the error"));
+                    let Some(len) = e.error_len() else {
+                        // Cut short by the chunk boundary, not invalid
+                        self.partial.extend_from_slice(after);
+                        break;
+                    };
+                    self.invalid = true;
+                    self.pending.push(char::REPLACEMENT_CHARACTER);
+                    rest = &after[len..];
+                }
+            }
+        }
+
+        // A word cannot run on past whitespace
+        if let Some((start, space)) = self
+            .pending
+            .char_indices()
+            .rfind(|(_, c)| c.is_whitespace())
+        {
+            let end = start + space.len_utf8();
+            let complete = std::mem::take(&mut self.pending);
+            self.pending.push_str(&complete[end..]);
+            self.count(&complete[..end]);
+        }
+    }
+
+    /// Counts whatever is still held back and returns the totals
+    pub fn finish(mut self) -> TextStats {
+        if !self.partial.is_empty() {
+            self.invalid = true;
+            self.pending.push(char::REPLACEMENT_CHARACTER);
+        }
+        let rest = std::mem::take(&mut self.pending);
+        self.count(&rest);
+        if self.pending_cr {
+            self.line_chars += 1;
+        }
+        if self.line_chars > 0 {
+            self.stats.lines += 1;
+            self.stats.longest_line = self.stats.longest_line.max(self.line_chars);
+            self.end_fields_line();
+        }
+        if !self.separators.is_empty() {
+            let chosen = if self.sniff {
+                sniff_separator(&self.sample)
+            } else {
+                Some(0)
+            };
+            let (separator, count) = chosen.map_or((None, 0), |i| {
+                let (separator, count) = self.separators[i];
+                (Some(separator), count)
+            });
+            self.stats.separator = separator;
+            self.stats.fields = Some(self.non_empty_lines + count);
+        }
+        self.stats.encoding = match self.head.as_slice() {
+            [0xff, 0xfe, ..] => Encoding::Utf16Le,
+            [0xfe, 0xff, ..] => Encoding::Utf16Be,
+            _ if self.invalid => Encoding::Unknown,
+            [0xef, 0xbb, 0xbf, ..] => Encoding::Utf8Bom,
+            _ if self.non_ascii => Encoding::Utf8,
+            _ => Encoding::Ascii,
+        };
+        self.stats
+    }
+
+    fn count(&mut self, text: &str) {
+        self.non_ascii |= !text.is_ascii();
+        self.stats.words += text.unicode_words().count() as u64;
+        for c in text.chars() {
+            self.stats.chars += 1;
+            for (i, (separator, total)) in self.separators.iter_mut().enumerate() {
+                if c == *separator {
+                    *total += 1;
+                    self.line_separators[i] += 1;
+                }
+            }
+            if c == '\n' {
+                self.stats.lines += 1;
+                self.stats.longest_line = self.stats.longest_line.max(self.line_chars);
+                if self.line_chars > 0 {
+                    self.end_fields_line();
+                }
+                self.line_chars = 0;
+                self.pending_cr = false;
+                continue;
+            }
+            if std::mem::replace(&mut self.pending_cr, c == '\r') {
+                self.line_chars += 1;
+            }
+            if c != '\r' {
+                self.line_chars += 1;
+            }
+        }
+    }
+
+    /// Closes a non-empty line for field counting, sampling it for `auto`
+    fn end_fields_line(&mut self) {
+        self.non_empty_lines += 1;
+        if self.sniff && self.sample.len() < SNIFF_LINES {
+            self.sample.push(self.line_separators.clone());
+        }
+        self.line_separators.fill(0);
+    }
+}
+
+/// One input's line in `stats --format json` or `yaml`
+#[derive(Debug, Serialize)]
+struct StatsRecord {
+    input: String,
+    #[serde(flatten)]
+    stats: TextStats,
+}
+
+/// Writes `records` as a table like `wc` prints, numbers right-aligned
+///
+/// The `fields` and `separator` columns are only there when fields were
+/// counted.
+fn write_stats_table(records: &[StatsRecord], out: &mut dyn Write) -> io::Result<()> {
+    let with_fields = records.iter().any(|record| record.stats.fields.is_some());
+    let mut headers = vec![
+        "lines", "words", "chars", "bytes", "longest", "encoding", "input",
+    ];
+    if with_fields {
+        headers.insert(5, "fields");
+        headers.insert(6, "separator");
+    }
+    let numbers = if with_fields { 6 } else { 5 };
+    let rows: Vec<Vec<String>> = records
+        .iter()
+        .map(|record| {
+            let stats = &record.stats;
+            let mut row = vec![
+                stats.lines.to_string(),
+                stats.words.to_string(),
+                stats.chars.to_string(),
+                stats.bytes.to_string(),
+                stats.longest_line.to_string(),
+                stats.encoding.to_string(),
+                record.input.clone(),
+            ];
+            if with_fields {
+                let separator = match stats.separator {
+                    Some('\t') => "tab".to_string(),
+                    Some(c) => c.to_string(),
+                    None => "none".to_string(),
+                };
+                let fields = stats.fields.unwrap_or_default().to_string();
+                row.splice(5..5, [fields, separator]);
+            }
+            row
+        })
+        .collect();
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let headers: Vec<String> = headers.into_iter().map(str::to_string).collect();
+    for row in std::iter::once(&headers).chain(&rows) {
+        let mut line = String::new();
+        for (column, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            let width = *width;
+            if column < numbers {
+                line.push_str(&format!("{:>width$}  ", cell));
+            } else {
+                line.push_str(&format!("{:<width$}  ", cell));
+            }
+        }
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    out.flush()
+}
+
+/// Number of characters shown in input previews
+const PREVIEW_CHARS: usize = 40;
+
+/// Capacity of the read and write buffers used when streaming
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Input path that reads stdin
+const STDIN: &str = "-";
+
+/// UTF-8 byte order mark, as decoded text
+const BOM: char = '\u{feff}';
+
+/// First two bytes of every gzip member (RFC 1952, section 2.3.1)
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Compression of an input or output stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// Plain, uncompressed data
+    None,
+    /// gzip (RFC 1952)
+    Gzip,
+}
+
+impl Compression {
+    /// Guesses the compression from a file name: `.gz` means gzip
+    pub fn detect(path: &str) -> Self {
+        if Path::new(path).extension().is_some_and(|ext| ext == "gz") {
+            Compression::Gzip
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Recognizes compressed data by its leading magic bytes
+    pub fn sniff(head: &[u8]) -> Self {
+        if head.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// A character encoding inputs are decoded from or outputs encoded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum TextEncoding {
+    #[default]
+    #[value(name = "utf-8")]
+    Utf8,
+    /// ISO-8859-1, read as its windows-1252 superset like browsers do
+    Latin1,
+    #[value(name = "utf-16le")]
+    Utf16Le,
+    #[value(name = "utf-16be")]
+    Utf16Be,
+}
+
+impl TextEncoding {
+    fn codec(self) -> &'static encoding_rs::Encoding {
+        match self {
+            TextEncoding::Utf8 => encoding_rs::UTF_8,
+            TextEncoding::Latin1 => encoding_rs::WINDOWS_1252,
+            TextEncoding::Utf16Le => encoding_rs::UTF_16LE,
+            TextEncoding::Utf16Be => encoding_rs::UTF_16BE,
+        }
+    }
+
+    /// Guesses the encoding of the data starting with `head`
+    ///
+    /// A byte order mark decides. Without one, data with a zero byte in
+    /// most of every other position, as ASCII text has in UTF-16, is taken
+    /// as UTF-16, and data that is valid UTF-8 so far as UTF-8. Anything
+    /// else is Latin-1, which decodes every byte, so a wrong guess garbles
+    /// characters but never fails.
+    pub fn sniff(head: &[u8]) -> Self {
+        match encoding_rs::Encoding::for_bom(head) {
+            Some((codec, _)) if codec == encoding_rs::UTF_16LE => return TextEncoding::Utf16Le,
+            Some((codec, _)) if codec == encoding_rs::UTF_16BE => return TextEncoding::Utf16Be,
+            Some(_) => return TextEncoding::Utf8,
+            None => {}
+        }
+        let pairs = &head[..head.len().min(1024) & !1];
+        let zeros_at = |parity| {
+            pairs
+                .iter()
+                .skip(parity)
+                .step_by(2)
+                .filter(|&&b| b == 0)
+                .count()
+        };
+        let (even, odd) = (zeros_at(0), zeros_at(1));
+        let half = pairs.len() / 2;
+        if half > 0 && odd * 2 > half && even == 0 {
+            return TextEncoding::Utf16Le;
+        }
+        if half > 0 && even * 2 > half && odd == 0 {
+            return TextEncoding::Utf16Be;
+        }
+        match std::str::from_utf8(head) {
+            Ok(_) => TextEncoding::Utf8,
+            // An error without a length is a sequence cut off by the end
+            Err(e) if e.error_len().is_none() => TextEncoding::Utf8,
+            Err(_) => TextEncoding::Latin1,
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no variant is skipped");
+        f.write_str(value.get_name())
+    }
+}
+
+/// How `--encoding` decides the encoding of each input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum EncodingChoice {
+    /// Detected per input; see [`TextEncoding::sniff`]
+    #[default]
+    Auto,
+    #[value(name = "utf-8")]
+    Utf8,
+    Latin1,
+    #[value(name = "utf-16le")]
+    Utf16Le,
+    #[value(name = "utf-16be")]
+    Utf16Be,
+}
+
+impl EncodingChoice {
+    /// The encoding every input is read as, or `None` to detect it
+    pub fn forced(self) -> Option<TextEncoding> {
+        match self {
+            EncodingChoice::Auto => None,
+            EncodingChoice::Utf8 => Some(TextEncoding::Utf8),
+            EncodingChoice::Latin1 => Some(TextEncoding::Latin1),
+            EncodingChoice::Utf16Le => Some(TextEncoding::Utf16Le),
+            EncodingChoice::Utf16Be => Some(TextEncoding::Utf16Be),
+        }
+    }
+}
+
+/// Where `--split-lines` or `--split-bytes` end a part of an output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitLimit {
+    /// At most this many lines per part
+    Lines(u64),
+    /// At most this many bytes per part, before compression
+    Bytes(u64),
+}
+
+/// A value an [`OutputTemplate`] fills in for each input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    /// The input's directory, `.` for a bare file name
+    Dir,
+    /// The input's file name without its extension
+    Stem,
+    /// The input's extension without the dot, empty if it has none
+    Ext,
+    /// The `--mode` name
+    Transform,
+    /// The local date the run started, as YYYY-MM-DD
+    Date,
+    /// The first 8 hex digits of the output's SHA-256 digest
+    Hash8,
+}
+
+impl Placeholder {
+    const ALL: [Placeholder; 6] = [
+        Placeholder::Dir,
+        Placeholder::Stem,
+        Placeholder::Ext,
+        Placeholder::Transform,
+        Placeholder::Date,
+        Placeholder::Hash8,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Placeholder::Dir => "dir",
+            Placeholder::Stem => "stem",
+            Placeholder::Ext => "ext",
+            Placeholder::Transform => "transform",
+            Placeholder::Date => "date",
+            Placeholder::Hash8 => "hash8",
+        }
+    }
+}
+
+/// `{hash8}` as it stays in an expanded path until the output is written
+const HASH8_MARKER: &str = "{hash8}";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplateSegment {
+    Text(String),
+    Field(Placeholder),
+}
+
+/// Output path pattern given to `--output-template`
+///
+/// Parsing checks every placeholder, so a typo fails before any input is
+/// read. `{hash8}` is only known once the output has been written, so it
+/// may appear in the file name but not in a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    segments: Vec<TemplateSegment>,
+}
+
+impl OutputTemplate {
+    /// Whether outputs are named by their digest
+    pub fn uses_hash(&self) -> bool {
+        self.segments
+            .contains(&TemplateSegment::Field(Placeholder::Hash8))
+    }
+
+    /// Output path for `input`, with `{hash8}` left in place
+    pub fn expand(&self, input: &Path, transform: &str, date: &str) -> Result<String> {
+        let name = |part: Option<&OsStr>| {
+            part.map_or_else(String::new, |s| s.to_string_lossy().into_owned())
+        };
+        if input.file_name().is_none() {
+            bail!("Input has no file name: {}", input.display());
+        }
+        let mut path = String::new();
+        for segment in &self.segments {
+            match segment {
+                TemplateSegment::Text(text) => path.push_str(text),
+                TemplateSegment::Field(field) => path.push_str(&match field {
+                    Placeholder::Dir => match input.parent() {
+                        Some(dir) if !dir.as_os_str().is_empty() => {
+                            dir.to_string_lossy().into_owned()
+                        }
+                        _ => ".".to_string(),
+                    },
+                    Placeholder::Stem => name(input.file_stem()),
+                    Placeholder::Ext => name(input.extension()),
+                    Placeholder::Transform => transform.to_string(),
+                    Placeholder::Date => date.to_string(),
+                    Placeholder::Hash8 => HASH8_MARKER.to_string(),
+                }),
+            }
+        }
+        Ok(path)
+    }
+}
+
+impl FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                segments.push(TemplateSegment::Text(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed {{ in output template {:?}", s))?;
+            let name = &rest[open + 1..open + close];
+            let field = Placeholder::ALL
+                .into_iter()
+                .find(|field| field.name() == name)
+                .ok_or_else(|| {
+                    let known: Vec<_> = Placeholder::ALL
+                        .iter()
+                        .map(|field| format!("{{{}}}", field.name()))
+                        .collect();
+                    format!("unknown placeholder {{{}}}; use {}", name, known.join(", "))
+                })?;
+            segments.push(TemplateSegment::Field(field));
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(TemplateSegment::Text(rest.to_string()));
+        }
+
+        let after_hash = segments
+            .iter()
+            .skip_while(|segment| **segment != TemplateSegment::Field(Placeholder::Hash8));
+        for segment in after_hash {
+            match segment {
+                TemplateSegment::Text(text) if text.contains('/') => {}
+                TemplateSegment::Field(Placeholder::Dir) => {}
+                _ => continue,
+            }
+            return Err("{hash8} can only appear in the file name, not a directory".to_string());
+        }
+        if segments.is_empty() {
+            return Err("output template is empty".to_string());
+        }
+        Ok(Self { segments })
+    }
+}
+
+/// `path` with `{hash8}` in its file name replaced by `hash8`
+fn fill_hash8(path: &Path, hash8: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(name.replace(HASH8_MARKER, hash8))
+}
+
+/// How a run reports its results on stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// Transformed text only
+    #[default]
+    Text,
+    /// A [`FileRecord`] per input; an array of them for several inputs
+    Json,
+    /// A [`FileRecord`] YAML document per input
+    Yaml,
+}
+
+impl OutputFormat {
+    /// Writes `records` to `out` in this structured format
+    ///
+    /// A single record is written as a JSON object and several as an array;
+    /// YAML gets one document per record. Text format writes nothing.
+    fn write_records<T: Serialize>(self, records: &[T], mut out: impl Write) -> Result<()> {
+        match self {
+            OutputFormat::Text => return Ok(()),
+            OutputFormat::Json => {
+                match records {
+                    [record] => serde_json::to_writer_pretty(&mut out, record)?,
+                    _ => serde_json::to_writer_pretty(&mut out, records)?,
+                }
+                writeln!(out)?;
+            }
+            OutputFormat::Yaml => {
+                for record in records {
+                    writeln!(out, "---")?;
+                    serde_yaml::to_writer(&mut out, record)?;
+                }
+            }
+        }
+        out.flush().context("Failed to write results")
+    }
+}
+
+/// When to draw progress bars
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProgressChoice {
+    /// Only when stderr is a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ProgressChoice {
+    fn draw_target(self) -> ProgressDrawTarget {
+        match self {
+            // Hides itself when stderr is not a terminal
+            ProgressChoice::Auto => ProgressDrawTarget::stderr(),
+            ProgressChoice::Always => ProgressDrawTarget::term_like_with_hz(
+                Box::new(console::Term::buffered_stderr()),
+                20,
+            ),
+            ProgressChoice::Never => ProgressDrawTarget::hidden(),
+        }
+    }
+}
+
+/// When to log the end-of-run summary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SummaryChoice {
+    /// Only when there are several inputs
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Line terminator written for each line of output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Newline {
+    /// Keep each line's own terminator
+    #[default]
+    Preserve,
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    Crlf,
+}
+
+impl Newline {
+    /// Terminator that replaces `ending`; an unterminated line stays so
+    fn convert(self, ending: &'static str) -> &'static str {
+        match self {
+            _ if ending.is_empty() => ending,
+            Newline::Preserve => ending,
+            Newline::Lf => "\n",
+            Newline::Crlf => "\r\n",
+        }
+    }
+
+    /// Rewrites every line terminator in `text`
+    fn convert_all(self, text: &str) -> String {
+        text.split_inclusive('\n')
+            .map(|line| {
+                let (content, ending) = split_line_ending(line);
+                content.to_string() + self.convert(ending)
+            })
+            .collect()
+    }
+}
+
+/// What happens to the line terminator at the very end of the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FinalNewline {
+    /// Leave the last line as the input had it
+    #[default]
+    Preserve,
+    /// Terminate the last line if it is not already
+    Ensure,
+    /// Remove the last line's terminator
+    Strip,
+}
+
+impl FinalNewline {
+    /// Terminator to end the output with, given the last line's own and the
+    /// one used before it
+    fn resolve(self, last: &'static str, previous: &'static str) -> &'static str {
+        match self {
+            FinalNewline::Preserve => last,
+            FinalNewline::Ensure if last.is_empty() => previous,
+            FinalNewline::Ensure => last,
+            FinalNewline::Strip => "",
+        }
+    }
+}
+
+/// Timestamp field used when `--timestamp-field` is not given
+const DEFAULT_TIMESTAMP_FIELD: &str = r"^\S+";
+
+/// Window of time whose lines are transformed, from `--since`/`--until`
+///
+/// Whole-input modes such as `Sort` reorder lines, so they only accept a
+/// range that drops the lines outside it.
+#[derive(Debug, Clone)]
+pub struct TimeRange {
+    /// Earliest selected timestamp, inclusive
+    pub since: Option<DateTime<FixedOffset>>,
+    /// Latest selected timestamp, inclusive
+    pub until: Option<DateTime<FixedOffset>>,
+    /// Locates a line's timestamp: the first capture group, or the whole
+    /// match if the pattern has none
+    pub field: Regex,
+    /// Drop lines outside the range instead of copying them unchanged
+    pub drop_out_of_range: bool,
+    /// Handling of lines without a parseable timestamp
+    pub untimed: Untimed,
+}
+
+impl TimeRange {
+    /// Range reading each line's timestamp from its first field
+    pub fn new(since: Option<DateTime<FixedOffset>>, until: Option<DateTime<FixedOffset>>) -> Self {
+        Self {
+            since,
+            until,
+            field: Regex::new(DEFAULT_TIMESTAMP_FIELD).expect("valid timestamp pattern"),
+            drop_out_of_range: false,
+            untimed: Untimed::default(),
+        }
+    }
+
+    /// Returns whether the line's timestamp falls in the range, or `None` if
+    /// the line has no RFC 3339 timestamp where `field` looks
+    pub fn contains_line(&self, line: &str) -> Option<bool> {
+        let captures = self.field.captures(line)?;
+        let text = captures.get(1).or_else(|| captures.get(0))?.as_str();
+        let time = DateTime::parse_from_rfc3339(text).ok()?;
+        Some(
+            self.since.is_none_or(|since| time >= since)
+                && self.until.is_none_or(|until| time <= until),
+        )
+    }
+}
+
+/// What happens to a line without a parseable timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Untimed {
+    /// Fail the input, naming the line
+    #[default]
+    Error,
+    /// Copy the line unchanged
+    Pass,
+}
+
+/// Which earlier lines `--dedup` compares each line with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupScope {
+    /// Every earlier line of the input
+    All,
+    /// The last this many distinct lines, least recently seen forgotten first
+    Window(NonZeroUsize),
+}
+
+/// The lines of one input that `--dedup` has let through
+///
+/// Only a 64-bit hash of each line is kept, so memory does not grow with
+/// line length. Two different lines sharing a hash is vanishingly unlikely,
+/// but would drop the second.
+struct SeenLines {
+    state: RandomState,
+    hashes: SeenHashes,
+}
+
+enum SeenHashes {
+    Off,
+    All(HashSet<u64, RandomState>),
+    /// Seeing a line again makes it the most recent
+    Window(LruCache<u64, ()>),
+}
+
+impl SeenLines {
+    fn new(scope: Option<DedupScope>) -> Self {
+        let hashes = match scope {
+            None => SeenHashes::Off,
+            Some(DedupScope::All) => SeenHashes::All(HashSet::default()),
+            Some(DedupScope::Window(size)) => SeenHashes::Window(LruCache::new(size.get())),
+        };
+        Self {
+            state: RandomState::default(),
+            hashes,
+        }
+    }
+
+    /// Records `line`, returning true unless it was seen already
+    fn first_sight(&mut self, line: &str) -> bool {
+        match &mut self.hashes {
+            SeenHashes::Off => true,
+            SeenHashes::All(hashes) => hashes.insert(self.state.hash_one(line)),
+            SeenHashes::Window(hashes) => hashes.insert(self.state.hash_one(line), ()).is_none(),
+        }
+    }
+}
+
+/// How `--sort` orders lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SortMode {
+    /// By Unicode code point
+    #[default]
+    Lexical,
+    /// By the number each line starts with, e.g. `-2.5` or `10`; lines
+    /// without one sort first
+    Numeric,
+    /// Runs of digits compared by value, so `file2` sorts before `file10`
+    Natural,
+}
+
+impl SortMode {
+    /// Compares two lines, excluding their terminators
+    pub fn compare(self, a: &str, b: &str) -> cmp::Ordering {
+        match self {
+            SortMode::Lexical => a.cmp(b),
+            SortMode::Numeric => match (leading_number(a), leading_number(b)) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            },
+            SortMode::Natural => natural_cmp(a, b),
+        }
+    }
+}
+
+/// The number `line` starts with after any whitespace, read as `sort -n`
+/// reads it
+fn leading_number(line: &str) -> Option<f64> {
+    let line = line.trim_start();
+    let sign = usize::from(line.starts_with(['-', '+']));
+    let mut seen_dot = false;
+    let end = line[sign..]
+        .find(|c: char| match c {
+            '.' => std::mem::replace(&mut seen_dot, true),
+            _ => !c.is_ascii_digit(),
+        })
+        .map_or(line.len(), |end| sign + end);
+    line[..end].parse().ok()
+}
+
+/// Compares like `ls -v`: runs of ASCII digits by value, everything else
+/// by code point
+fn natural_cmp(mut a: &str, mut b: &str) -> cmp::Ordering {
+    fn split_digits(s: &str) -> (&str, &str) {
+        s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+    }
+
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            // A prefix sorts first
+            return a.len().cmp(&b.len());
+        };
+        let ordering = if x.is_ascii_digit() && y.is_ascii_digit() {
+            let (digits_a, rest_a) = split_digits(a);
+            let (digits_b, rest_b) = split_digits(b);
+            (a, b) = (rest_a, rest_b);
+            let (digits_a, digits_b) = (
+                digits_a.trim_start_matches('0'),
+                digits_b.trim_start_matches('0'),
+            );
+            digits_a
+                .len()
+                .cmp(&digits_b.len())
+                .then_with(|| digits_a.cmp(digits_b))
+        } else {
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+            x.cmp(&y)
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
+
+/// Which repeated lines `--dedupe` drops
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum DedupeMode {
+    /// Lines equal to the line kept just before them, like `uniq`
+    #[default]
+    Adjacent,
+    /// Lines equal to any line kept before them
+    Global,
+}
+
+/// Memory charged against `--dedupe-memory` for each distinct line: its
+/// hash plus the hash set's own overhead
+const DEDUPE_BYTES_PER_LINE: u64 = 16;
+
+/// The `--dedupe` stage, fed the transformed lines in output order
+enum Dedupe {
+    Off,
+    /// The last line kept
+    Adjacent(Option<String>),
+    Global {
+        seen: SeenLines,
+        distinct: u64,
+        memory: Option<u64>,
+    },
+}
+
+impl Dedupe {
+    fn new(mode: Option<DedupeMode>, memory: Option<u64>) -> Self {
+        match mode {
+            None => Dedupe::Off,
+            Some(DedupeMode::Adjacent) => Dedupe::Adjacent(None),
+            Some(DedupeMode::Global) => Dedupe::Global {
+                seen: SeenLines::new(Some(DedupScope::All)),
+                distinct: 0,
+                memory,
+            },
+        }
+    }
+
+    /// Returns true if `line` is kept
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::DedupeMemoryExceeded`] once global dedupe has
+    /// seen more distinct lines than `memory` holds, rather than letting
+    /// the set grow without bound
+    fn keep(&mut self, line: &str) -> Result<bool> {
+        match self {
+            Dedupe::Off => Ok(true),
+            Dedupe::Adjacent(previous) => {
+                if previous.as_deref() == Some(line) {
+                    return Ok(false);
+                }
+                let previous = previous.get_or_insert_with(String::new);
+                previous.clear();
+                previous.push_str(line);
+                Ok(true)
+            }
+            Dedupe::Global {
+                seen,
+                distinct,
+                memory,
+            } => {
+                if !seen.first_sight(line) {
+                    return Ok(false);
+                }
+                *distinct += 1;
+                match *memory {
+                    Some(limit) if *distinct * DEDUPE_BYTES_PER_LINE > limit => {
+                        Err(AppError::DedupeMemoryExceeded { limit }.into())
+                    }
+                    _ => Ok(true),
+                }
+            }
+        }
+    }
+}
+
+/// What happens to one input line, after `--grep`, `--dedup` and
+/// `--since`/`--until`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineAction {
+    Transform,
+    /// Copy the line unchanged
+    Copy,
+    Drop,
+}
+
+/// Line endings and byte order mark found in an input
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TextLayout {
+    /// The input starts with a UTF-8 byte order mark
+    pub bom: bool,
+    pub crlf_lines: u64,
+    pub lf_lines: u64,
+    /// The last line is terminated
+    pub final_newline: bool,
+}
+
+impl TextLayout {
+    /// Inspects a whole input
+    pub fn detect(input: &str) -> Self {
+        let mut layout = Self {
+            bom: input.starts_with(BOM),
+            ..Self::default()
+        };
+        for line in input.split_inclusive('\n') {
+            layout.record_line(split_line_ending(line).1);
+        }
+        layout
+    }
+
+    fn record_line(&mut self, ending: &str) {
+        match ending {
+            "\r\n" => self.crlf_lines += 1,
+            "\n" => self.lf_lines += 1,
+            _ => {}
+        }
+        self.final_newline = !ending.is_empty();
+    }
+}
+
+/// How log events are written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per event, with the fields of its spans, for log
+    /// aggregation
+    Json,
+}
+
+/// When to color terminal output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ColorChoice {
+    /// Only when the output is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolves `Auto` against the current stdout
+    pub fn enabled(self) -> bool {
+        self.enabled_for(&io::stdout())
+    }
+
+    /// Resolves `Auto` against `stream`, e.g. stderr for error messages
+    pub fn enabled_for(self, stream: &impl IsTerminal) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                !no_color_requested(std::env::var_os("NO_COLOR")) && stream.is_terminal()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Whether a `NO_COLOR` value asks for plain output: any value but an empty
+/// one does (<https://no-color.org>)
+fn no_color_requested(value: Option<OsString>) -> bool {
+    value.is_some_and(|value| !value.is_empty())
+}
+
+/// Text transform applied to the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Convert to uppercase
+    #[default]
+    Upper,
+    /// Convert to lowercase
+    Lower,
+    /// Reverse the characters of each line
+    Reverse,
+    /// Strip leading and trailing whitespace from each line
+    TrimLines,
+    /// Sort lines lexically
+    Sort,
+}
+
+impl Mode {
+    /// Every mode, in declaration order
+    pub fn all() -> &'static [Mode] {
+        &[
+            Mode::Upper,
+            Mode::Lower,
+            Mode::Reverse,
+            Mode::TrimLines,
+            Mode::Sort,
+        ]
+    }
+
+    /// Name accepted by `--mode` and [`Mode::from_str`]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Upper => "upper",
+            Mode::Lower => "lower",
+            Mode::Reverse => "reverse",
+            Mode::TrimLines => "trim-lines",
+            Mode::Sort => "sort",
+        }
+    }
+
+    /// Returns true if applying the transform to its own output changes
+    /// nothing
+    ///
+    /// `Sort` is left out: `str::lines` keeps a lone `\r`, and joining the
+    /// sorted lines can pair it with a `\n` that a second pass then reads as
+    /// a CRLF break.
+    pub fn is_idempotent(self) -> bool {
+        matches!(self, Mode::Upper | Mode::Lower | Mode::TrimLines)
+    }
+
+    /// Returns true if the transform cannot run one line at a time
+    pub fn requires_whole_input(self) -> bool {
+        matches!(self, Mode::Sort)
+    }
+
+    /// Transforms a single line, excluding its terminator
+    pub fn apply_line(self, line: &str) -> String {
+        self.apply_line_in(line, CaseLocale::Default).into_owned()
+    }
+
+    /// Transforms a single line with the case rules of `case`
+    ///
+    /// The line is borrowed back when `case` can tell nothing changes.
+    pub fn apply_line_in(self, line: &str, case: CaseLocale) -> Cow<'_, str> {
+        match self {
+            Mode::Upper => case.to_upper(line),
+            Mode::Lower => case.to_lower(line),
+            Mode::Reverse => Cow::Owned(line.chars().rev().collect()),
+            Mode::TrimLines => Cow::Borrowed(line.trim()),
+            Mode::Sort => Cow::Borrowed(line),
+        }
+    }
+
+    /// Transforms the whole input, preserving line terminators
+    pub fn apply(self, input: &str) -> String {
+        self.apply_in(input, CaseLocale::Default)
+    }
+
+    /// Transforms the whole input with the case rules of `case`
+    pub fn apply_in(self, input: &str, case: CaseLocale) -> String {
+        if self == Mode::Sort {
+            let mut lines: Vec<&str> = input.lines().collect();
+            lines.sort_unstable();
+            let mut output = lines.join("\n");
+            if input.ends_with('\n') {
+                output.push('\n');
+            }
+            return output;
+        }
+
+        input
+            .split_inclusive('\n')
+            .map(|line| {
+                let (content, ending) = split_line_ending(line);
+                self.apply_line_in(content, case) + ending
+            })
+            .collect()
+    }
+}
+
+/// Case rules for the `upper` and `lower` modes
+///
+/// Only the Turkish dotted and dotless i are special-cased; full locale
+/// rules, e.g. from ICU, are out of scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum CaseLocale {
+    /// Unicode's locale-independent mappings, as `str::to_uppercase` does
+    #[default]
+    Default,
+    /// Unicode mappings, except i and ı uppercase to İ and I, and İ and I
+    /// lowercase to i and ı
+    Turkish,
+    /// Only ASCII letters change case; everything else is copied unchanged
+    Ascii,
+}
+
+impl CaseLocale {
+    /// Uppercases `text`
+    ///
+    /// `Ascii` borrows `text` back, without allocating, when it has no
+    /// lowercase ASCII letter.
+    pub fn to_upper(self, text: &str) -> Cow<'_, str> {
+        match self {
+            CaseLocale::Default => Cow::Owned(text.to_uppercase()),
+            CaseLocale::Turkish => Cow::Owned(map_turkish(
+                text,
+                |c| (c == 'i').then_some('İ'),
+                str::to_uppercase,
+            )),
+            CaseLocale::Ascii if !text.bytes().any(|b| b.is_ascii_lowercase()) => {
+                Cow::Borrowed(text)
+            }
+            CaseLocale::Ascii => Cow::Owned(text.to_ascii_uppercase()),
+        }
+    }
+
+    /// Lowercases `text`
+    ///
+    /// `Ascii` borrows `text` back, without allocating, when it has no
+    /// uppercase ASCII letter.
+    pub fn to_lower(self, text: &str) -> Cow<'_, str> {
+        match self {
+            CaseLocale::Default => Cow::Owned(text.to_lowercase()),
+            CaseLocale::Turkish => Cow::Owned(map_turkish(
+                text,
+                |c| match c {
+                    'I' => Some('ı'),
+                    'İ' => Some('i'),
+                    _ => None,
+                },
+                str::to_lowercase,
+            )),
+            CaseLocale::Ascii if !text.bytes().any(|b| b.is_ascii_uppercase()) => {
+                Cow::Borrowed(text)
+            }
+            CaseLocale::Ascii => Cow::Owned(text.to_ascii_lowercase()),
+        }
+    }
+}
+
+/// Maps the characters `special` has a replacement for, and every run of
+/// text between them with `rest`
+///
+/// Mapping runs rather than single characters keeps `rest`'s
+/// context-dependent rules, such as the final sigma of `str::to_lowercase`.
+fn map_turkish(text: &str, special: fn(char) -> Option<char>, rest: fn(&str) -> String) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if let Some(mapped) = special(c) {
+            output.push_str(&rest(&text[start..i]));
+            output.push(mapped);
+            start = i + c.len_utf8();
+        }
+    }
+    output.push_str(&rest(&text[start..]));
+    output
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Mode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Mode::all()
+            .iter()
+            .copied()
+            .find(|mode| mode.as_str() == s)
+            .ok_or_else(|| ParseModeError(s.to_string()))
+    }
+}
+
+impl TryFrom<&str> for Mode {
+    type Error = ParseModeError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A string that names no [`Mode`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid mode '{0}', expected one of: {}", mode_names())]
+pub struct ParseModeError(pub String);
+
+fn mode_names() -> String {
+    let names: Vec<_> = Mode::all().iter().map(|mode| mode.as_str()).collect();
+    names.join(", ")
+}
+
+/// Splits a line into its content and terminator (`\r\n`, `\n`, or none)
+fn split_line_ending(line: &str) -> (&str, &'static str) {
+    if let Some(content) = line.strip_suffix("\r\n") {
+        (content, "\r\n")
+    } else if let Some(content) = line.strip_suffix('\n') {
+        (content, "\n")
+    } else {
+        (line, "")
+    }
+}
+
+/// Counters reported by a streaming run
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStats {
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Largest line buffer capacity held at any point
+    pub peak_buffer: usize,
+    /// What the input looked like before normalization
+    pub layout: TextLayout,
+}
+
+/// Wrapper that hides a value from log output
+///
+/// Both `Debug` and `Display` print `<redacted>`, so the wrapper can be
+/// used with either `%` or `?` in tracing fields.
+pub struct Redacted<T>(pub T);
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// Log value that is shown or redacted depending on `--redact`
+enum Sensitive<T> {
+    Shown(T),
+    Hidden(Redacted<T>),
+}
+
+impl<T: fmt::Display> fmt::Display for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sensitive::Shown(value) => value.fmt(f),
+            Sensitive::Hidden(value) => fmt::Display::fmt(value, f),
+        }
+    }
+}
+
+/// Application configuration
+#[derive(Debug, Default)]
+pub struct Config {
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    pub out_dir: Option<String>,
+    /// Pattern naming each input's output file
+    pub output_template: Option<OutputTemplate>,
+    /// Create missing output directories instead of failing
+    pub create_dirs: bool,
+    /// Compression of every input; `None` detects it per file name
+    pub input_compression: Option<Compression>,
+    /// Compression of every output; `None` detects it per file name
+    pub output_compression: Option<Compression>,
+    /// Character encoding of every input
+    pub encoding: EncodingChoice,
+    /// Replace undecodable input, and output the output encoding lacks,
+    /// instead of failing
+    pub lossy: bool,
+    /// Character encoding of every output
+    pub output_encoding: TextEncoding,
+    /// Write each output as numbered parts of this size
+    pub split: Option<SplitLimit>,
+    /// Replace existing output files instead of refusing to write them
+    pub force: bool,
+    /// Copy an existing output file to `<name><suffix>` before replacing it
+    pub backup: Option<String>,
+    /// Write results back over each input; a non-empty suffix keeps the
+    /// original at `<input><suffix>`
+    pub in_place: Option<String>,
+    /// In-place runs over more files than this ask first; `None` never asks
+    pub confirm_threshold: Option<usize>,
+    /// Skip the `confirm_threshold` question, answering yes
+    pub yes: bool,
+    /// Worker threads for multi-file runs; 0 means one per logical core
+    pub jobs: usize,
+    /// File recording completed inputs, so a rerun can skip them
+    pub checkpoint: Option<String>,
+    /// Start `checkpoint` afresh instead of resuming from it
+    pub no_resume: bool,
+    /// Largest input, in bytes as stored, that is processed
+    pub max_file_size: Option<u64>,
+    /// Inputs over `max_file_size` fail instead of being skipped
+    pub strict_limits: bool,
+    /// Longest time one input may take before it fails
+    pub timeout: Option<Duration>,
+    /// How reads of input files and writes of the output are retried
+    pub io_retry: RetryPolicy,
+    /// Longest line, in bytes without its terminator, that streaming reads
+    pub max_line_length: Option<u64>,
+    /// TOML files layered by [`Config::load_settings`]
+    pub config_paths: Vec<String>,
+    /// Name of the `[profile.<name>]` table [`Config::apply_profile`] uses
+    pub profile: Option<String>,
+    /// Dotted paths of the values that profile set, e.g. `processor.threads`
+    pub profile_overrides: Vec<String>,
+    pub mode: Mode,
+    /// Case rules for the `upper` and `lower` modes
+    pub case_locale: CaseLocale,
+    /// Report results as text or as structured records
+    pub format: OutputFormat,
+    /// Drop lines that don't match before transforming
+    pub grep: Option<Regex>,
+    /// Drop the lines `grep` matches instead
+    pub invert_match: bool,
+    /// Drop lines repeating an earlier line in this scope
+    pub dedup: Option<DedupScope>,
+    /// Order the transformed lines this way
+    pub sort: Option<SortMode>,
+    /// Drop repeats among the transformed lines, after `sort`
+    pub dedupe: Option<DedupeMode>,
+    /// Memory `DedupeMode::Global` may use, in bytes; `None` is no limit
+    pub dedupe_memory: Option<u64>,
+    /// Only transform the lines timestamped inside this range
+    pub time_range: Option<TimeRange>,
+    /// Line terminator for each line, applied as the input is read
+    pub newline: Newline,
+    /// Drop a byte order mark at the start of each input
+    pub strip_bom: bool,
+    /// Terminator handling at the end of each output
+    pub final_newline: FinalNewline,
+    /// Output the number of kept lines instead of the transformed text
+    pub count: bool,
+    /// Print a unified diff per input instead of writing any output
+    pub diff: bool,
+    /// Expected SHA-256 of the single input, as lowercase hex
+    pub verify_input: Option<String>,
+    /// Write a `sha256sum` line for each output
+    pub emit_checksum: bool,
+    /// Where to write the [`RunReport`], `-` meaning stdout
+    pub report: Option<String>,
+    /// Whether `diff` output is colored
+    pub color: ColorChoice,
+    /// When progress bars are drawn on stderr
+    pub progress: ProgressChoice,
+    /// When the end-of-run summary is logged
+    pub summary: SummaryChoice,
+    pub redact: bool,
+    /// Browser origins allowed by the HTTP server's CORS policy
+    pub cors_origins: Vec<String>,
+    /// Key the HTTP server requires in `X-API-Key`, if any
+    pub api_key: Option<Redacted<String>>,
+}
+
+impl Config {
+    /// Builds the configuration from parsed command line arguments
+    pub fn from_args(args: Args) -> Self {
+        Self {
+            inputs: args.input,
+            output: args.output,
+            out_dir: args.out_dir,
+            output_template: args.output_template,
+            create_dirs: args.create_dirs,
+            input_compression: args.input_compression,
+            output_compression: args.output_compression,
+            encoding: args.encoding,
+            lossy: args.lossy,
+            output_encoding: args.output_encoding,
+            split: args
+                .split_lines
+                .map(SplitLimit::Lines)
+                .or(args.split_bytes.map(SplitLimit::Bytes)),
+            force: args.force,
+            backup: args.backup,
+            in_place: args.in_place,
+            confirm_threshold: Some(args.confirm_threshold),
+            yes: args.yes,
+            jobs: args.jobs.unwrap_or(0),
+            checkpoint: args.checkpoint,
+            no_resume: args.no_resume,
+            max_file_size: args.max_file_size,
+            strict_limits: args.strict_limits,
+            timeout: args.timeout,
+            io_retry: RetryPolicy {
+                retries: args.io_retries,
+                delay: args.io_retry_delay,
+            },
+            max_line_length: Some(args.max_line_length),
+            config_paths: args.config,
+            profile: args.profile,
+            profile_overrides: Vec::new(),
+            mode: args.mode,
+            case_locale: args.case_locale,
+            format: args.format,
+            grep: args.grep,
+            invert_match: args.invert_match,
+            dedup: args.dedup.then(|| {
+                args.dedup_window
+                    .map_or(DedupScope::All, DedupScope::Window)
+            }),
+            sort: args.sort.then(|| args.sort_mode.unwrap_or_default()),
+            dedupe: args.dedupe.then(|| args.dedupe_mode.unwrap_or_default()),
+            dedupe_memory: Some(args.dedupe_memory),
+            time_range: (args.since.is_some() || args.until.is_some()).then(|| {
+                let range = TimeRange::new(args.since, args.until);
+                TimeRange {
+                    field: args.timestamp_field.unwrap_or(range.field),
+                    drop_out_of_range: args.drop_out_of_range,
+                    untimed: args.untimed_lines,
+                    ..range
+                }
+            }),
+            newline: args.newline,
+            strip_bom: args.strip_bom,
+            final_newline: if args.no_trailing_newline {
+                FinalNewline::Strip
+            } else {
+                args.final_newline
+            },
+            count: args.count,
+            diff: args.diff,
+            color: args.color,
+            progress: args.progress,
+            summary: args.summary,
+            verify_input: args.verify_input,
+            emit_checksum: args.emit_checksum,
+            report: args.report,
+            redact: args.redact,
+            cors_origins: args.cors_origins,
+            api_key: args.api_key.map(Redacted),
+        }
+    }
+
+    /// Reads every file in `config_paths` and deep-merges them in order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the file if one cannot be read or is not
+    /// valid TOML
+    pub fn load_settings(&self) -> Result<toml::Table> {
+        let mut settings = toml::Table::new();
+        for path in &self.config_paths {
+            let text =
+                fs::read_to_string(path).context(format!("Cannot read config file: {}", path))?;
+            let layer: toml::Table = text
+                .parse()
+                .context(format!("Invalid TOML in config file: {}", path))?;
+            debug!("Merging config file: {}", path);
+            merge_settings(&mut settings, layer);
+        }
+        Ok(settings)
+    }
+
+    /// Merges the `[profile.<name>]` table named by `profile` over the rest
+    /// of `settings`, noting the values it set in `profile_overrides`
+    ///
+    /// Profiles are applied after every file is merged, so a profile in an
+    /// earlier file still overrides the base settings of a later one. The
+    /// `profile` table is removed either way and never reaches [`Settings`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::UnknownProfile`] if no config file defines the
+    /// profile, or an error if a profile is not a table
+    pub fn apply_profile(&mut self, settings: &mut toml::Table) -> Result<()> {
+        let profiles = match settings.remove(PROFILE_KEY) {
+            None => toml::Table::new(),
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => bail!("Invalid profile in config file: use [profile.<name>] tables"),
+        };
+        let Some(name) = &self.profile else {
+            return Ok(());
+        };
+        let overlay = match profiles.get(name) {
+            Some(toml::Value::Table(overlay)) => overlay.clone(),
+            Some(_) => bail!(
+                "Invalid profile in config file: [profile.{}] is not a table",
+                name
+            ),
+            None => {
+                return Err(AppError::UnknownProfile {
+                    name: name.clone(),
+                    available: profiles.keys().cloned().collect(),
+                }
+                .into())
+            }
+        };
+        debug!("Applying config profile: {}", name);
+        self.profile_overrides = value_paths(&overlay);
+        merge_settings(settings, overlay);
+        Ok(())
+    }
+
+    /// Applies config file `settings` to the options the command line left
+    /// alone
+    ///
+    /// `from_cli` tells whether the `Args` field with the given name was
+    /// given on the command line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the key if a size or duration is invalid, or
+    /// the keys if two settings that exclude each other are both set
+    pub fn apply_settings(
+        &mut self,
+        settings: &Settings,
+        from_cli: impl Fn(&str) -> bool,
+    ) -> Result<()> {
+        let unset = |id: &str| !from_cli(id);
+        if let Some(mode) = settings.mode.filter(|_| unset("mode")) {
+            self.mode = mode;
+        }
+        if let Some(format) = settings.format.filter(|_| unset("format")) {
+            self.format = format;
+        }
+        if let Some(jobs) = settings.jobs.filter(|_| unset("jobs")) {
+            self.jobs = jobs;
+        }
+        if let Some(progress) = settings.progress.filter(|_| unset("progress")) {
+            self.progress = progress;
+        }
+        if let Some(create_dirs) = settings.create_dirs.filter(|_| unset("create_dirs")) {
+            self.create_dirs = create_dirs;
+        }
+        if let Some((key, other)) = settings.conflicts().first() {
+            bail!("Config file sets both {} and {}; keep one", key, other);
+        }
+        if unset("split_lines") && unset("split_bytes") {
+            if let Some(lines) = settings.split_lines {
+                if lines == 0 {
+                    bail!("Invalid split-lines in config file: must be at least 1");
+                }
+                self.split = Some(SplitLimit::Lines(lines));
+            }
+            if let Some(size) = settings.split_bytes.as_deref() {
+                self.split = Some(SplitLimit::Bytes(parse_setting(
+                    "split-bytes",
+                    size,
+                    parse_size,
+                )?));
+            }
+        }
+        if let Some(size) = settings
+            .max_file_size
+            .as_deref()
+            .filter(|_| unset("max_file_size"))
+        {
+            self.max_file_size = Some(parse_setting("max-file-size", size, parse_size)?);
+        }
+        if let Some(size) = settings
+            .max_line_length
+            .as_deref()
+            .filter(|_| unset("max_line_length"))
+        {
+            self.max_line_length = Some(parse_setting("max-line-length", size, parse_size)?);
+        }
+        if let Some(limit) = settings.timeout.as_deref().filter(|_| unset("timeout")) {
+            self.timeout = Some(parse_setting("timeout", limit, parse_duration)?);
+        }
+        Ok(())
+    }
+}
+
+/// Options a config file can set
+///
+/// Keys are named after flags, and each one only takes effect when its flag
+/// is not given on the command line (see [`Config::apply_settings`]). Keys
+/// this app does not know are kept aside so they can be reported.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Settings {
+    pub mode: Option<Mode>,
+    pub format: Option<OutputFormat>,
+    pub jobs: Option<usize>,
+    pub progress: Option<ProgressChoice>,
+    pub create_dirs: Option<bool>,
+    pub split_lines: Option<u64>,
+    /// A size as `--split-bytes` takes it, e.g. `100MB`
+    pub split_bytes: Option<String>,
+    /// A size as `--max-file-size` takes it, e.g. `10MB`
+    pub max_file_size: Option<String>,
+    /// A size as `--max-line-length` takes it, e.g. `64KiB`
+    pub max_line_length: Option<String>,
+    /// A duration as `--timeout` takes it, e.g. `30s`
+    pub timeout: Option<String>,
+    #[serde(flatten, skip_serializing)]
+    unknown: toml::Table,
+}
+
+impl Settings {
+    /// Reads settings from merged config files (see [`Config::load_settings`])
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a known key has a value of the wrong type
+    pub fn from_table(table: toml::Table) -> Result<Self> {
+        toml::Value::Table(table)
+            .try_into()
+            .context("Invalid setting in config file")
+    }
+
+    /// Top-level keys that are not settings, e.g. misspelled ones
+    pub fn unknown_keys(&self) -> Vec<&str> {
+        self.unknown.keys().map(String::as_str).collect()
+    }
+
+    /// Pairs of keys that are both set but exclude each other, as
+    /// `--split-lines` and `--split-bytes` do on the command line
+    pub fn conflicts(&self) -> Vec<(&'static str, &'static str)> {
+        let mut conflicts = Vec::new();
+        if self.split_lines.is_some() && self.split_bytes.is_some() {
+            conflicts.push(("split-lines", "split-bytes"));
+        }
+        conflicts
+    }
+}
+
+/// Parses a size or duration `value` given for config `key`
+fn parse_setting<T>(key: &str, value: &str, parse: fn(&str) -> Result<T, String>) -> Result<T> {
+    parse(value).map_err(|e| anyhow::anyhow!("Invalid {} in config file: {}", key, e))
+}
+
+/// Environment variable naming the profile when `--profile` is not given
+pub const PROFILE_VAR: &str = "APP_PROFILE";
+
+/// Config file table holding a table of overrides per profile
+const PROFILE_KEY: &str = "profile";
+
+/// Dotted paths of the values in `table`, descending into nested tables
+fn value_paths(table: &toml::Table) -> Vec<String> {
+    let mut paths = Vec::new();
+    for (key, value) in table {
+        match value {
+            toml::Value::Table(nested) => paths.extend(
+                value_paths(nested)
+                    .into_iter()
+                    .map(|path| format!("{}.{}", key, path)),
+            ),
+            _ => paths.push(key.clone()),
+        }
+    }
+    paths
+}
+
+/// Merges `overlay` into `base`
+///
+/// Tables present in both are merged key by key, recursively; any other
+/// value from `overlay`, arrays included, replaces the one in `base`.
+pub fn merge_settings(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(nested)) => {
+                merge_settings(existing, nested)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// A problem `config validate` found in a config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    pub path: String,
+    /// 1-based line of the key, or of the syntax error
+    pub line: Option<usize>,
+    /// The top-level key at fault, if the problem is with one key
+    pub key: Option<String>,
+    pub message: String,
+    /// Reported without failing validation, as an unknown key is without
+    /// `--strict`
+    pub warning: bool,
+}
+
+impl fmt::Display for ConfigProblem {
+    /// `<path>:<line>: <error|warning>: <key>: <message>`, leaving out the
+    /// line and key when there are none
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        let level = if self.warning { "warning" } else { "error" };
+        write!(f, ": {}: ", level)?;
+        if let Some(key) = &self.key {
+            write!(f, "{}: ", key)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Everything `config validate` found in a set of config files
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigReport {
+    pub problems: Vec<ConfigProblem>,
+    /// Known settings in the merged files
+    pub settings: usize,
+}
+
+impl ConfigReport {
+    /// Problems that make the config invalid
+    pub fn errors(&self) -> usize {
+        self.problems.iter().filter(|p| !p.warning).count()
+    }
+
+    /// Last line of the report, e.g. `config.toml: valid (3 setting(s))`
+    pub fn summary(&self, paths: &[String]) -> String {
+        let errors = self.errors();
+        let warnings = self.problems.len() - errors;
+        let mut summary = if errors == 0 {
+            format!("{}: valid ({} setting(s)", paths.join(", "), self.settings)
+        } else {
+            format!("{}: invalid ({} error(s)", paths.join(", "), errors)
+        };
+        if warnings > 0 {
+            summary.push_str(&format!(", {} warning(s)", warnings));
+        }
+        summary.push(')');
+        summary
+    }
+}
+
+/// Checks the config files in `paths` as a run would load them, with
+/// `profile` applied, without stopping at the first problem
+///
+/// Each file is checked on its own for TOML syntax, and each key in it,
+/// those of every `[profile.<name>]` table included, for its type and
+/// value, so every broken key is reported with its line. Settings that
+/// exclude each other are then checked across the merged files and
+/// profile, and the files are finally loaded by [`Config::load_settings`]
+/// and applied, so nothing a run would reject passes. Unknown keys are
+/// warnings unless `strict`.
+pub fn validate_config(paths: &[String], profile: Option<&str>, strict: bool) -> ConfigReport {
+    let mut report = ConfigReport::default();
+    let problem = |path: &str, line, key: Option<&str>, message: String| ConfigProblem {
+        path: path.to_string(),
+        line,
+        key: key.map(str::to_string),
+        message,
+        warning: false,
+    };
+    // Where each key was last set, as (layer, path, line); the profile is
+    // applied after every file, as a later layer
+    let mut locations: HashMap<String, (usize, &str, usize)> = HashMap::new();
+    let mut merged = toml::Table::new();
+    let mut profile_layer = Vec::new();
+    let mut profile_names = HashSet::new();
+    for (index, path) in paths.iter().enumerate() {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                report
+                    .problems
+                    .push(problem(path, None, None, format!("cannot read: {}", e)));
+                continue;
+            }
+        };
+        let line_at = |offset: usize| text[..offset].matches('\n').count() + 1;
+        let mut keys: BTreeMap<toml::Spanned<String>, toml::Value> = match toml::from_str(&text) {
+            Ok(keys) => keys,
+            Err(e) => {
+                let line = e.span().map(|span| line_at(span.start));
+                report
+                    .problems
+                    .push(problem(path, line, None, e.message().to_string()));
+                continue;
+            }
+        };
+        // (profile, key, value) for each key, the profile's name if it is
+        // in one
+        let mut entries = Vec::new();
+        if let Some((key, _)) = keys.remove_entry(PROFILE_KEY) {
+            // Parsed again to learn where each key in the profiles is
+            match toml::from_str::<ProfileSpans>(&text) {
+                Ok(spans) => {
+                    for (name, table) in spans.profile {
+                        profile_names.insert(name.clone());
+                        for (key, value) in table {
+                            entries.push((Some(name.clone()), key, value));
+                        }
+                    }
+                }
+                Err(_) => {
+                    let line = line_at(key.span().start);
+                    let message = "use [profile.<name>] tables".to_string();
+                    report
+                        .problems
+                        .push(problem(path, Some(line), Some(PROFILE_KEY), message));
+                }
+            }
+        }
+        entries.extend(keys.into_iter().map(|(key, value)| (None, key, value)));
+        entries.sort_by_key(|(_, key, _)| key.span().start);
+
+        for (name, key, value) in entries {
+            let line = line_at(key.span().start);
+            let key = key.into_inner();
+            let shown = match &name {
+                Some(name) => format!("{}.{}.{}", PROFILE_KEY, name, key),
+                None => key.clone(),
+            };
+            let layer = toml::Table::from_iter([(key.clone(), value)]);
+            let checked = Settings::from_table(layer.clone()).and_then(|settings| {
+                Config::default().apply_settings(&settings, |_| false)?;
+                Ok(settings)
+            });
+            match checked {
+                Ok(settings) => {
+                    if !settings.unknown.is_empty() {
+                        report.problems.push(ConfigProblem {
+                            warning: !strict,
+                            ..problem(path, Some(line), Some(&shown), "unknown key".to_string())
+                        });
+                    }
+                    match name {
+                        None => {
+                            locations.insert(key, (index, path, line));
+                            merge_settings(&mut merged, layer);
+                        }
+                        Some(name) if Some(name.as_str()) == profile => {
+                            profile_layer.push((
+                                key,
+                                layer,
+                                (paths.len() + index, path.as_str(), line),
+                            ));
+                        }
+                        Some(_) => {}
+                    }
+                }
+                Err(e) => {
+                    // Only the first line; serde adds one naming the key
+                    let cause = e.root_cause().to_string();
+                    let message = cause.lines().next().unwrap_or_default().to_string();
+                    report
+                        .problems
+                        .push(problem(path, Some(line), Some(&shown), message));
+                }
+            }
+        }
+    }
+
+    if let Some(name) = profile.filter(|name| !profile_names.contains(*name)) {
+        let mut available: Vec<String> = profile_names.into_iter().collect();
+        available.sort_unstable();
+        let message = AppError::UnknownProfile {
+            name: name.to_string(),
+            available,
+        }
+        .to_string();
+        report
+            .problems
+            .push(problem(&paths.join(", "), None, None, message));
+    }
+    for (key, layer, location) in profile_layer {
+        locations.insert(key, location);
+        merge_settings(&mut merged, layer);
+    }
+
+    // Every key left was accepted on its own, so they merge cleanly
+    let known = merged.len();
+    let settings = Settings::from_table(merged).unwrap_or_default();
+    report.settings = known - settings.unknown.len();
+    for (key, other) in settings.conflicts() {
+        // Reported where the second of the two is set
+        let (_, path, line) = cmp::max(locations[key], locations[other]);
+        let message = format!("cannot be set together with {}", other);
+        report
+            .problems
+            .push(problem(path, Some(line), Some(key), message));
+    }
+
+    if report.errors() == 0 {
+        let mut config = Config {
+            config_paths: paths.to_vec(),
+            profile: profile.map(str::to_string),
+            ..Config::default()
+        };
+        let loaded = config.load_settings().and_then(|mut table| {
+            config.apply_profile(&mut table)?;
+            let settings = Settings::from_table(table)?;
+            Config::default().apply_settings(&settings, |_| false)
+        });
+        if let Err(e) = loaded {
+            report
+                .problems
+                .push(problem(&paths.join(", "), None, None, format!("{:#}", e)));
+        }
+    }
+    report
+}
+
+/// The `[profile.<name>]` tables of a config file, with where each key is
+#[derive(Debug, Deserialize)]
+struct ProfileSpans {
+    #[serde(default)]
+    profile: BTreeMap<String, BTreeMap<toml::Spanned<String>, toml::Value>>,
+}
+
+/// Errors with a dedicated process exit status or a hint for the user
+#[derive(Debug, Error)]
+pub enum AppError {
+    /// An input file does not exist
+    #[error("input file not found: {path}")]
+    InputNotFound {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// An output file exists and `--force` was not given
+    #[error("output exists, pass --force to overwrite: {0}")]
+    OutputExists(String),
+
+    /// An output's directory is missing and `--create-dirs` was not given
+    #[error("output directory does not exist: {0} (use --create-dirs)")]
+    OutputDirMissing(String),
+
+    /// A batch completed, but some inputs were skipped for existing outputs
+    #[error("{skipped} of {total} outputs exist and were skipped, pass --force to overwrite")]
+    OutputsSkipped { skipped: usize, total: usize },
+
+    /// `--diff` found inputs the transform would change
+    #[error("{0} file(s) would change")]
+    WouldChange(usize),
+
+    /// The input's digest differs from the one given to `--verify-input`
+    #[error("checksum mismatch for {path}: expected sha256 {expected}, got {actual}")]
+    ChecksumMismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+
+    /// The run was interrupted, e.g. by Ctrl-C, before every input was done
+    #[error("interrupted: {completed} file(s) completed, {aborted} aborted")]
+    Interrupted { completed: usize, aborted: usize },
+
+    /// The `--checkpoint` file exists but is not one this app wrote
+    #[error("checkpoint file is corrupt: {path} (line {line})")]
+    CorruptCheckpoint { path: String, line: usize },
+
+    /// An input is over `--max-file-size`; it is skipped unless `strict`
+    #[error("input is {size} bytes, over the --max-file-size limit of {limit}: {path}")]
+    InputTooLarge {
+        path: String,
+        size: u64,
+        limit: u64,
+        strict: bool,
+    },
+
+    /// A batch completed, but some inputs were skipped for their size
+    #[error("{skipped} of {total} inputs are over --max-file-size and were skipped")]
+    InputsTooLarge { skipped: usize, total: usize },
+
+    /// Processing an input took longer than `--timeout`
+    #[error("timed out after {0:?}")]
+    Timeout(Duration),
+
+    /// `init` would overwrite a config file and `--force` was not given
+    #[error("config file exists, pass --force to overwrite: {0}")]
+    ConfigExists(String),
+
+    /// A line of an input is longer than `--max-line-length`
+    #[error("line starting at byte {offset} is over the --max-line-length limit of {limit} bytes")]
+    LineTooLong { offset: u64, limit: u64 },
+
+    /// `--dedupe-mode global` saw more distinct lines than `--dedupe-memory`
+    /// can remember
+    #[error("distinct lines are over the --dedupe-memory limit of {limit} bytes")]
+    DedupeMemoryExceeded { limit: u64 },
+
+    /// `--in-place` was about to modify more than `--confirm-threshold`
+    /// files and was not confirmed, by `--yes` or at the prompt
+    #[error("not modifying {files} files in place without confirmation")]
+    NotConfirmed { files: usize },
+
+    /// `--output-template` gives several inputs the same output path
+    #[error("inputs {} would all write to {output}", .inputs.join(", "))]
+    OutputCollision { output: String, inputs: Vec<String> },
+
+    /// `config validate` found this many errors, already listed on stdout
+    #[error("config has {0} error(s)")]
+    InvalidConfig(usize),
+
+    /// `--profile` names a profile no config file defines
+    #[error("unknown config profile {name}; available: {}", profile_list(.available))]
+    UnknownProfile {
+        name: String,
+        available: Vec<String>,
+    },
+}
+
+/// `available` for [`AppError::UnknownProfile`]: the names, or `none`
+fn profile_list(available: &[String]) -> String {
+    if available.is_empty() {
+        "none".to_string()
+    } else {
+        available.join(", ")
+    }
+}
+
+impl AppError {
+    /// Process exit status for this error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            AppError::OutputExists(_)
+            | AppError::ConfigExists(_)
+            | AppError::UnknownProfile { .. }
+            | AppError::OutputsSkipped { .. }
+            | AppError::InputTooLarge { strict: false, .. }
+            | AppError::InputsTooLarge { .. }
+            | AppError::NotConfirmed { .. } => 2,
+            AppError::ChecksumMismatch { .. } => 3,
+            // Same as timeout(1)
+            AppError::Timeout(_) => 124,
+            // Shells report a process killed by SIGINT as 128 + 2
+            AppError::Interrupted { .. } => 130,
+            // Same contract as `diff -q`
+            AppError::WouldChange(_)
+            | AppError::InputNotFound { .. }
+            | AppError::OutputDirMissing(_)
+            | AppError::CorruptCheckpoint { .. }
+            | AppError::InputTooLarge { strict: true, .. }
+            | AppError::LineTooLong { .. }
+            | AppError::DedupeMemoryExceeded { .. }
+            | AppError::OutputCollision { .. }
+            | AppError::InvalidConfig(_) => 1,
+        }
+    }
+
+    /// Stable identifier for this kind of error, shown in error reports
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::InputNotFound { .. } => "input_not_found",
+            AppError::OutputExists(_) => "output_exists",
+            AppError::OutputDirMissing(_) => "output_dir_missing",
+            AppError::OutputsSkipped { .. } => "outputs_skipped",
+            AppError::WouldChange(_) => "would_change",
+            AppError::ChecksumMismatch { .. } => "checksum_mismatch",
+            AppError::Interrupted { .. } => "interrupted",
+            AppError::CorruptCheckpoint { .. } => "corrupt_checkpoint",
+            AppError::InputTooLarge { .. } => "input_too_large",
+            AppError::InputsTooLarge { .. } => "inputs_too_large",
+            AppError::Timeout(_) => "timeout",
+            AppError::ConfigExists(_) => "config_exists",
+            AppError::LineTooLong { .. } => "line_too_long",
+            AppError::DedupeMemoryExceeded { .. } => "dedupe_memory_exceeded",
+            AppError::NotConfirmed { .. } => "not_confirmed",
+            AppError::OutputCollision { .. } => "output_collision",
+            AppError::InvalidConfig(_) => "invalid_config",
+            AppError::UnknownProfile { .. } => "unknown_profile",
+        }
+    }
+
+    /// The file or directory this error is about, if it is about one
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            AppError::InputNotFound { path, .. }
+            | AppError::ChecksumMismatch { path, .. }
+            | AppError::CorruptCheckpoint { path, .. }
+            | AppError::InputTooLarge { path, .. } => Some(path),
+            AppError::OutputCollision { output, .. } => Some(output),
+            AppError::OutputExists(path)
+            | AppError::OutputDirMissing(path)
+            | AppError::ConfigExists(path) => Some(path),
+            AppError::OutputsSkipped { .. }
+            | AppError::WouldChange(_)
+            | AppError::Interrupted { .. }
+            | AppError::InputsTooLarge { .. }
+            | AppError::Timeout(_)
+            | AppError::LineTooLong { .. }
+            | AppError::DedupeMemoryExceeded { .. }
+            | AppError::NotConfirmed { .. }
+            | AppError::InvalidConfig(_)
+            | AppError::UnknownProfile { .. } => None,
+        }
+    }
+
+    /// What the user can do about this error, if anything
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            AppError::InputNotFound { .. } => Some("check the --input path"),
+            AppError::OutputDirMissing(_) => Some("check the --output or --out-dir path"),
+            AppError::OutputExists(_) => {
+                Some("pass --force to replace it, with --backup to keep the current contents")
+            }
+            AppError::OutputsSkipped { .. } => {
+                Some("pass --force to replace them, with --backup to keep the current contents")
+            }
+            AppError::ChecksumMismatch { .. } => {
+                Some("the input is not the file the digest was taken from")
+            }
+            AppError::Interrupted { .. } => {
+                Some("completed outputs were kept; run again to process the aborted files")
+            }
+            AppError::CorruptCheckpoint { .. } => {
+                Some("pass --no-resume to start over, or use another --checkpoint file")
+            }
+            AppError::InputTooLarge { strict: true, .. } => {
+                Some("raise --max-file-size, or drop --strict-limits to skip such inputs")
+            }
+            AppError::InputTooLarge { strict: false, .. } | AppError::InputsTooLarge { .. } => {
+                Some("raise --max-file-size to process them")
+            }
+            AppError::Timeout(_) => Some("raise --timeout, or leave it out for no limit"),
+            AppError::ConfigExists(_) => Some("pass --path to write the new file elsewhere"),
+            AppError::LineTooLong { .. } => {
+                Some("raise --max-line-length if the input really has lines this long")
+            }
+            AppError::DedupeMemoryExceeded { .. } => Some(
+                "raise --dedupe-memory, or add --sort so --dedupe-mode adjacent finds the repeats",
+            ),
+            AppError::NotConfirmed { .. } => {
+                Some("pass --yes to modify them anyway, or raise --confirm-threshold")
+            }
+            AppError::OutputCollision { .. } => {
+                Some("add a placeholder that differs between them, such as {stem} or {dir}")
+            }
+            AppError::UnknownProfile { .. } => {
+                Some("pick an available profile, or add a [profile.<name>] table for it")
+            }
+            AppError::WouldChange(_) | AppError::InvalidConfig(_) => None,
+        }
+    }
+}
+
+/// Exit status for an error returned by [`App::run`]
+///
+/// Uses the code of the first [`AppError`] in the chain, or 1 for any other
+/// failure.
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<AppError>())
+        .map_or(1, AppError::exit_code)
+}
+
+/// Returns true if the error is an expected outcome the exit status already
+/// conveys, so it needs no message
+pub fn is_quiet(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref(),
+            Some(AppError::WouldChange(_) | AppError::InvalidConfig(_))
+        )
+    })
+}
+
+/// Message for an error returned by [`App::run`], as shown to the user
+///
+/// An [`AppError`] is reduced to a block with its own message, code, the
+/// file involved and a hint, leaving out the lines that do not apply:
+///
+/// ```text
+/// Error: output exists, pass --force to overwrite: out.txt
+///   Code: output_exists
+///   File: out.txt
+///   Hint: pass --force to replace it, with --backup to keep the current contents
+/// ```
+///
+/// Anything else, or any error when `debug` is set, is shown with its full
+/// cause chain. `color` adds ANSI styling to the labels.
+pub fn render_error(err: &anyhow::Error, debug: bool, color: bool) -> String {
+    let paint = |style: &str, label: &str| {
+        if color {
+            format!("\x1b[{}m{}\x1b[0m", style, label)
+        } else {
+            label.to_string()
+        }
+    };
+    let error = paint("1;31", "Error:");
+    let app_err = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<AppError>());
+    let Some(app_err) = app_err.filter(|_| !debug) else {
+        return format!("{} {:?}", error, err);
+    };
+
+    let mut block = format!(
+        "{} {}\n  {} {}",
+        error,
+        app_err,
+        paint("1", "Code:"),
+        app_err.code()
+    );
+    if let Some(path) = app_err.path() {
+        block.push_str(&format!("\n  {} {}", paint("1", "File:"), path));
+    }
+    if let Some(hint) = app_err.hint() {
+        block.push_str(&format!("\n  {} {}", paint("1;36", "Hint:"), hint));
+    }
+    block
+}
+
+/// Reports panics through `tracing` instead of the default stderr message
+///
+/// The event carries the panic message, source location and thread name as
+/// fields, so it reaches the same log output as everything else. With
+/// `abort` set the process aborts right after logging; otherwise the panic
+/// unwinds as usual and the process exits with status 101.
+pub fn install_panic_hook(abort: bool) {
+    std::panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        error!(
+            panic.message = info.payload_as_str().unwrap_or("Box<dyn Any>"),
+            panic.location = %info.location().map_or_else(String::new, ToString::to_string),
+            panic.thread = thread.name().unwrap_or("<unnamed>"),
+            "Application panicked"
+        );
+        if abort {
+            std::process::abort();
+        }
+    }));
+}
+
+/// Runs `f`, turning a panic into `LibError::OperationFailed` with the
+/// panic message, so a bug hit by one input does not take down the run
+///
+/// The panic hook has logged the panic by then. Unwinding drops whatever
+/// `f` owned, so a half-written output file is discarded as on any other
+/// failure. With `--abort-on-panic` the hook aborts first and nothing is
+/// caught.
+fn catch_panic<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    std::panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Box<dyn Any>");
+        Err(LibError::OperationFailed(format!("panicked: {}", message)).into())
+    })
+}
+
+/// Error of an input given up on because the run was interrupted
+#[derive(Debug, Error)]
+#[error("aborted by interrupt")]
+struct Aborted;
+
+/// One input and where its output goes
+#[derive(Debug, Clone)]
+struct Job {
+    input: String,
+    /// Output file path, or `None` for stdout
+    output: Option<String>,
+    /// Where to keep the previous output file, if anywhere
+    backup: Option<PathBuf>,
+    /// Output overwrites the input; identical results are not rewritten
+    in_place: bool,
+    /// Replacing an existing output file is allowed
+    overwrite: bool,
+    /// Stdout output goes into the structured record instead
+    capture: bool,
+}
+
+/// Outcome of processing one input
+#[derive(Debug)]
+pub struct FileResult {
+    pub input: String,
+    /// Output file path, or `None` for stdout
+    pub output: Option<String>,
+    pub outcome: Result<StreamStats>,
+    /// Time spent on this input
+    pub duration: Duration,
+    /// Transformed text held back from stdout for a structured record
+    pub captured: Option<String>,
+    /// Not processed because `--checkpoint` lists it as done, unchanged
+    pub resumed: bool,
+}
+
+/// Structured report of one input, emitted by `--format json|yaml`
+#[derive(Debug, Clone, Serialize)]
+pub struct FileRecord {
+    pub input: String,
+    /// Output file path, or `"stdout"`
+    pub output: String,
+    pub status: FileStatus,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub transform: Mode,
+    pub duration_ms: u64,
+    /// Transformed text, when it would otherwise have gone to stdout
+    pub result: Option<String>,
+    /// Error chain of a skipped or failed input
+    pub error: Option<String>,
+    /// Exit status the input's error maps to (see [`exit_code`])
+    pub code: Option<u8>,
+}
+
+/// Final state of one input in a [`FileRecord`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Ok,
+    Skipped,
+    Failed,
+    /// Not processed, or stopped part way, because the run was interrupted
+    Aborted,
+    /// Completed by an earlier run, according to the checkpoint
+    Resumed,
+}
+
+/// Report of a whole run, written by `--report`
+#[derive(Debug, Serialize)]
+pub struct RunReport {
+    /// The run's ID, as logged in its `run` span
+    pub run_id: String,
+    pub summary: ReportSummary,
+    /// One record per input, sorted by input path
+    pub files: Vec<FileRecord>,
+}
+
+/// Totals of a [`RunReport`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ReportSummary {
+    pub total: usize,
+    pub succeeded: usize,
+    pub resumed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub aborted: usize,
+    /// Exit status of the run
+    pub exit_code: u8,
+    pub duration_ms: u64,
+}
+
+/// Structured report of a run that failed before processing any input
+#[derive(Debug, Serialize)]
+struct ErrorRecord {
+    error: String,
+}
+
+/// Per-file results of a run, sorted by input path
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    pub results: Vec<FileResult>,
+}
+
+impl RunSummary {
+    /// Number of inputs processed successfully
+    pub fn succeeded(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|r| r.outcome.is_ok() && !r.resumed)
+            .count()
+    }
+
+    /// Number of inputs a checkpoint showed were already done
+    pub fn resumed(&self) -> usize {
+        self.results.iter().filter(|r| r.resumed).count()
+    }
+
+    /// Number of inputs skipped because their output already exists
+    pub fn skipped(&self) -> usize {
+        self.results.iter().filter(|r| r.is_skipped()).count()
+    }
+
+    /// Number of inputs abandoned because the run was interrupted
+    pub fn aborted(&self) -> usize {
+        self.results.iter().filter(|r| r.is_aborted()).count()
+    }
+
+    /// Number of inputs that failed for any other reason
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.succeeded() - self.resumed() - self.skipped() - self.aborted()
+    }
+
+    /// One structured record per input, in result order
+    pub fn records(&self, transform: Mode) -> Vec<FileRecord> {
+        self.results
+            .iter()
+            .map(|result| result.record(transform))
+            .collect()
+    }
+}
+
+impl FileResult {
+    /// True if the input was not processed because its output exists or
+    /// it is over `--max-file-size`
+    pub fn is_skipped(&self) -> bool {
+        self.outcome.as_ref().is_err_and(|e| {
+            e.chain().any(|cause| {
+                matches!(
+                    cause.downcast_ref(),
+                    Some(AppError::OutputExists(_) | AppError::InputTooLarge { strict: false, .. })
+                )
+            })
+        })
+    }
+
+    /// True if the input was skipped for its size
+    pub fn is_too_large(&self) -> bool {
+        self.outcome.as_ref().is_err_and(|e| {
+            e.chain().any(|cause| {
+                matches!(
+                    cause.downcast_ref(),
+                    Some(AppError::InputTooLarge { strict: false, .. })
+                )
+            })
+        })
+    }
+
+    /// True if the input was abandoned because the run was interrupted
+    pub fn is_aborted(&self) -> bool {
+        self.outcome
+            .as_ref()
+            .is_err_and(|e| e.chain().any(|cause| cause.is::<Aborted>()))
+    }
+
+    /// Structured record of this result
+    pub fn record(&self, transform: Mode) -> FileRecord {
+        let (status, stats, error) = match &self.outcome {
+            Ok(stats) if self.resumed => (FileStatus::Resumed, *stats, None),
+            Ok(stats) => (FileStatus::Ok, *stats, None),
+            Err(e) if self.is_aborted() => (
+                FileStatus::Aborted,
+                StreamStats::default(),
+                Some(format!("{:#}", e)),
+            ),
+            Err(e) if self.is_skipped() => (
+                FileStatus::Skipped,
+                StreamStats::default(),
+                Some(format!("{:#}", e)),
+            ),
+            Err(e) => (
+                FileStatus::Failed,
+                StreamStats::default(),
+                Some(format!("{:#}", e)),
+            ),
+        };
+        FileRecord {
+            input: self.input.clone(),
+            output: self.output.clone().unwrap_or_else(|| "stdout".to_string()),
+            status,
+            bytes_in: stats.bytes_in,
+            bytes_out: stats.bytes_out,
+            transform,
+            duration_ms: self.duration.as_millis() as u64,
+            result: self.captured.clone(),
+            code: self.outcome.as_ref().err().map(exit_code),
+            error,
+        }
+    }
+}
+
+/// Asks the user a yes or no question before a destructive operation
+pub trait Prompt: Send + Sync {
+    /// Whether there is someone to answer; if not, the operation is refused
+    fn is_interactive(&self) -> bool;
+
+    /// Asks `question`, returning true for yes
+    fn confirm(&self, question: &str) -> io::Result<bool>;
+}
+
+/// [`Prompt`] asking on stderr and reading the answer from stdin
+///
+/// Stderr keeps the question out of piped results. Anything but `y` or
+/// `yes` is a no.
+struct TerminalPrompt;
+
+impl Prompt for TerminalPrompt {
+    fn is_interactive(&self) -> bool {
+        io::stdin().is_terminal()
+    }
+
+    fn confirm(&self, question: &str) -> io::Result<bool> {
+        let mut stderr = io::stderr().lock();
+        write!(stderr, "{} [y/N] ", question)?;
+        stderr.flush()?;
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        Ok(matches!(
+            answer.trim().to_ascii_lowercase().as_str(),
+            "y" | "yes"
+        ))
+    }
+}
+
+/// Main application logic
+pub struct App {
+    config: Config,
+    /// ULID of this run, attached to every log event and the report
+    run_id: String,
+    progress: MultiProgress,
+    interrupted: Arc<AtomicBool>,
+    /// Asks before large `--in-place` runs
+    prompt: Box<dyn Prompt>,
+    /// Outputs named by `{hash8}` so far, with the input that wrote each
+    hashed_outputs: Mutex<HashMap<PathBuf, String>>,
+    /// Called with every streamed line, so tests can inject faults
+    #[cfg(test)]
+    line_hook: Option<fn(&str)>,
+}
+
+impl App {
+    /// Creates the application from its configuration
+    pub fn new(config: Config) -> Self {
+        let progress = MultiProgress::with_draw_target(config.progress.draw_target());
+        Self {
+            config,
+            run_id: ulid::Ulid::new().to_string(),
+            progress,
+            interrupted: Arc::default(),
+            prompt: Box::new(TerminalPrompt),
+            hashed_outputs: Mutex::default(),
+            #[cfg(test)]
+            line_hook: None,
+        }
+    }
+
+    /// Flag that stops a run once set, e.g. from a Ctrl-C handler
+    ///
+    /// Inputs not yet started are skipped, and the one being streamed
+    /// stops at its next line, discarding its partial output. The run then
+    /// fails with [`AppError::Interrupted`].
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupted)
+    }
+
+    /// Replaces the terminal prompt that confirms large `--in-place` runs
+    pub fn with_prompt(mut self, prompt: impl Prompt + 'static) -> Self {
+        self.prompt = Box::new(prompt);
+        self
+    }
+
+    /// ID correlating the logs and report of this run
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    /// Describes the environment of this run for a bug report
+    ///
+    /// Lists the platform, version, the config profile and the values it
+    /// overrode, the configuration in effect and every input with the output
+    /// it resolves to. Paths and the configuration
+    /// honor `--redact`; no file is read.
+    pub fn diagnostics(&self) -> String {
+        use std::env::consts;
+
+        let mut block = format!(
+            "Diagnostics:\n  Version: {} {}\n  OS: {} ({})\n  Arch: {}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            consts::OS,
+            consts::FAMILY,
+            consts::ARCH
+        );
+        let resolve = |path: &str| match path {
+            STDIN => "stdin".to_string(),
+            path => std::path::absolute(path)
+                .map_or_else(|_| path.to_string(), |p| p.display().to_string()),
+        };
+        match self.plan_jobs() {
+            Ok(jobs) => {
+                for job in &jobs {
+                    let output = job.output.as_deref().map_or("stdout".to_string(), resolve);
+                    block.push_str(&format!(
+                        "\n  Path: {} -> {}",
+                        self.sensitive(resolve(&job.input)),
+                        self.sensitive(output)
+                    ));
+                }
+            }
+            Err(e) => block.push_str(&format!("\n  Path: unresolved ({})", e)),
+        }
+        if let Some(profile) = &self.config.profile {
+            block.push_str(&format!(
+                "\n  Profile: {} (overrides: {})",
+                profile,
+                self.config.profile_overrides.join(", ")
+            ));
+        }
+        block.push_str(&format!(
+            "\n  Config: {}",
+            self.sensitive(format!("{:?}", self.config))
+        ));
+        block
+    }
+
+    /// Log writer for `tracing_subscriber` that keeps log lines on stderr
+    /// from tearing this app's progress bars
+    pub fn log_writer(&self) -> LogWriter {
+        LogWriter {
+            progress: self.progress.clone(),
+            inner: io::stderr,
+        }
+    }
+
+    /// Run the application
+    ///
+    /// A single input reports its own error. With several inputs every
+    /// file is attempted, failures are listed, and the run fails if any
+    /// file did. With `--format json|yaml` the results, failures included,
+    /// are also written to stdout as [`FileRecord`]s.
+    ///
+    /// Stdout carries only results: transformed text, records, diffs, or
+    /// the report for `--report -`. Logs, progress, checksums for stdout
+    /// and the summary all go to stderr, so piping stdout stays safe.
+    ///
+    /// Everything logged during the run is inside a `run` span carrying
+    /// [`App::run_id`].
+    pub fn run(&self) -> Result<()> {
+        let _run = info_span!("run", run_id = %self.run_id).entered();
+        info!("Starting application");
+        let started = Instant::now();
+
+        let summary = match self.process_all() {
+            Ok(summary) => summary,
+            Err(e) => {
+                let record = ErrorRecord {
+                    error: format!("{:#}", e),
+                };
+                self.emit(&[record], io::stdout())?;
+                return Err(e);
+            }
+        };
+        let records = summary.records(self.config.mode);
+        self.emit(&records, io::stdout())?;
+
+        let changed = if self.config.diff {
+            if self.config.format == OutputFormat::Text {
+                ignore_broken_pipe(self.print_diffs(&summary))?;
+            }
+            summary
+                .results
+                .iter()
+                .filter(|result| result.captured.as_deref().is_some_and(|d| !d.is_empty()))
+                .count()
+        } else {
+            0
+        };
+
+        let report_summary = ReportSummary {
+            total: summary.results.len(),
+            succeeded: summary.succeeded(),
+            resumed: summary.resumed(),
+            skipped: summary.skipped(),
+            failed: summary.failed(),
+            aborted: summary.aborted(),
+            exit_code: 0,
+            duration_ms: 0,
+        };
+        let result = self.conclude(summary, changed);
+
+        if let Some(path) = &self.config.report {
+            let report = RunReport {
+                run_id: self.run_id.clone(),
+                summary: ReportSummary {
+                    exit_code: result.as_ref().err().map_or(0, exit_code),
+                    duration_ms: started.elapsed().as_millis() as u64,
+                    ..report_summary
+                },
+                files: records,
+            };
+            if let Err(e) = self.write_report(path, &report) {
+                // A failed run keeps its own error and exit status
+                if result.is_ok() {
+                    return Err(e);
+                }
+                error!("{}", self.sensitive_error(&e, &[path]));
+            }
+        }
+        result
+    }
+
+    /// Turns the results of a run into its outcome
+    fn conclude(&self, mut summary: RunSummary, changed: usize) -> Result<()> {
+        if self.is_interrupted() {
+            return Err(AppError::Interrupted {
+                completed: summary.succeeded(),
+                aborted: summary.aborted(),
+            }
+            .into());
+        }
+
+        let single = self.config.inputs.len() == 1;
+        let show_summary = match self.config.summary {
+            SummaryChoice::Auto => !single,
+            SummaryChoice::Always => true,
+            SummaryChoice::Never => false,
+        };
+        if show_summary {
+            info!(
+                "Processed {} file{}: {} succeeded, {} skipped, {} failed (run {})",
+                summary.results.len(),
+                if single { "" } else { "s" },
+                summary.succeeded(),
+                summary.skipped(),
+                summary.failed(),
+                self.run_id
+            );
+            if summary.resumed() > 0 {
+                info!(
+                    "Left {} files the checkpoint lists as done",
+                    summary.resumed()
+                );
+            }
+        }
+
+        if single {
+            summary.results.remove(0).outcome?;
+        } else {
+            if summary.failed() > 0 {
+                bail!(
+                    "{} of {} files failed",
+                    summary.failed(),
+                    summary.results.len()
+                );
+            }
+            if summary.skipped() > 0 {
+                let total = summary.results.len();
+                let too_large = summary.results.iter().filter(|r| r.is_too_large()).count();
+                let err = match summary.skipped() - too_large {
+                    0 => AppError::InputsTooLarge {
+                        skipped: too_large,
+                        total,
+                    },
+                    skipped => AppError::OutputsSkipped { skipped, total },
+                };
+                return Err(err.into());
+            }
+        }
+
+        if changed > 0 {
+            return Err(AppError::WouldChange(changed).into());
+        }
+        info!("Application completed successfully");
+        Ok(())
+    }
+
+    /// Writes `report` as JSON to `path`, or to stdout for `-`
+    ///
+    /// A file is replaced atomically, so a reader never sees half a report.
+    fn write_report(&self, path: &str, report: &RunReport) -> Result<()> {
+        if path == STDIN {
+            let mut stdout = io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, report)?;
+            writeln!(stdout)?;
+            return stdout.flush().context("Failed to write report");
+        }
+        let context = || format!("Cannot write report: {}", path);
+        let mut file = AtomicFile::create(Path::new(path)).with_context(context)?;
+        serde_json::to_writer_pretty(&mut file, report)?;
+        writeln!(file)?;
+        file.commit(None, true).with_context(context)?;
+        info!("Wrote report to {}", self.sensitive(path));
+        Ok(())
+    }
+
+    /// Prints the diffs collected by `--diff`, in input order
+    ///
+    /// Workers only collect diffs, so concurrent files cannot interleave.
+    fn print_diffs(&self, summary: &RunSummary) -> Result<()> {
+        let mut stdout = io::stdout().lock();
+        for diff in summary.results.iter().filter_map(|r| r.captured.as_deref()) {
+            stdout.write_all(diff.as_bytes())?;
+        }
+        stdout.flush()?;
+        Ok(())
+    }
+
+    /// Processes every input, continuing past per-file failures
+    ///
+    /// Files are spread over a scoped thread pool; results are sorted by
+    /// input path so reporting does not depend on completion order.
+    pub fn process_all(&self) -> Result<RunSummary> {
+        let jobs = self.plan_jobs()?;
+        self.confirm_in_place(jobs.len())?;
+        let checkpoint = match &self.config.checkpoint {
+            Some(path) => Some(Checkpoint::open(Path::new(path), !self.config.no_resume)?),
+            None => None,
+        };
+        let workers = self.worker_count(&jobs);
+        debug!("Processing {} inputs with {} workers", jobs.len(), workers);
+
+        let files = if jobs.len() > 1 {
+            self.progress
+                .add(ProgressBar::new(jobs.len() as u64).with_style(progress_style(FILES_TEMPLATE)))
+        } else {
+            ProgressBar::hidden()
+        };
+        // Pool threads start outside any span; file spans go under the run's
+        let parent = Span::current();
+        let process = |job: &Job| {
+            let result = parent.in_scope(|| self.process_job(job, checkpoint.as_ref()));
+            files.inc(1);
+            result
+        };
+
+        let mut results: Vec<FileResult> = if workers <= 1 {
+            jobs.iter().map(process).collect()
+        } else {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(workers)
+                .build()
+                .context("Failed to start worker pool")?;
+            // Carry the caller's subscriber onto the pool threads so scoped
+            // subscribers (e.g. in tests) see worker logs too
+            let dispatch = tracing::dispatcher::get_default(|d| d.clone());
+            debug!(threads = pool.current_num_threads(), "Started worker pool");
+            pool.install(|| {
+                jobs.par_iter()
+                    .map(|job| tracing::dispatcher::with_default(&dispatch, || process(job)))
+                    .collect()
+            })
+        };
+
+        files.finish_and_clear();
+
+        results.sort_by(|a, b| a.input.cmp(&b.input));
+        Ok(RunSummary { results })
+    }
+
+    /// Asks before `--in-place` modifies more than `--confirm-threshold`
+    /// files, unless `--yes` was given
+    ///
+    /// Without a terminal to ask on, the run is refused instead.
+    fn confirm_in_place(&self, files: usize) -> Result<()> {
+        let over_threshold = self
+            .config
+            .confirm_threshold
+            .is_some_and(|threshold| files > threshold);
+        if self.config.in_place.is_none() || !over_threshold || self.config.yes {
+            return Ok(());
+        }
+        let question = format!("About to modify {} files in place. Continue?", files);
+        if !self.prompt.is_interactive() || !self.prompt.confirm(&question)? {
+            return Err(AppError::NotConfirmed { files }.into());
+        }
+        Ok(())
+    }
+
+    /// Pairs each input with its output, rejecting ambiguous layouts
+    fn plan_jobs(&self) -> Result<Vec<Job>> {
+        if self.config.inputs.is_empty() {
+            bail!("No input files given");
+        }
+        if self.config.inputs.len() > 1 && self.config.output.is_some() {
+            bail!("--output takes a single input; use --out-dir for multiple inputs");
+        }
+        if self.config.in_place.is_some()
+            && (self.config.output.is_some()
+                || self.config.out_dir.is_some()
+                || self.config.output_template.is_some())
+        {
+            bail!("--in-place cannot be combined with --output, --out-dir or --output-template");
+        }
+        if let Some(template) = &self.config.output_template {
+            if self.config.inputs.iter().any(|i| i == STDIN) {
+                bail!("--output-template names outputs after their inputs; stdin has no name");
+            }
+            if template.uses_hash() && self.config.split.is_some() {
+                bail!("{{hash8}} cannot name split outputs; leave it out of --output-template");
+            }
+        }
+        if self.config.in_place.is_some() && self.config.inputs.iter().any(|i| i == STDIN) {
+            bail!("--in-place cannot edit stdin; pass a file path instead of -");
+        }
+        if self.config.verify_input.is_some() {
+            if self.config.inputs.len() > 1 {
+                bail!("--verify-input takes a single input");
+            }
+            if self.config.inputs[0] == STDIN {
+                bail!("--verify-input cannot check stdin; pass a file path instead of -");
+            }
+        }
+        if let Some(range) = &self.config.time_range {
+            if let (Some(since), Some(until)) = (range.since, range.until) {
+                if since > until {
+                    bail!("--since {} is later than --until {}", since, until);
+                }
+            }
+            if self.config.mode.requires_whole_input() && !range.drop_out_of_range {
+                bail!(
+                    "--mode {} reorders lines, so --since/--until need --drop-out-of-range",
+                    self.config.mode
+                );
+            }
+        }
+
+        if self.config.checkpoint.is_some() && self.config.inputs.iter().any(|i| i == STDIN) {
+            bail!("--checkpoint cannot track stdin; pass file paths instead of -");
+        }
+
+        let stdout_output = self.config.output.is_none()
+            && self.config.out_dir.is_none()
+            && self.config.output_template.is_none()
+            && self.config.in_place.is_none();
+        if self.config.split.is_some() && (stdout_output || self.config.in_place.is_some()) {
+            bail!("--split-lines and --split-bytes need --output or --out-dir");
+        }
+
+        let stdout_busy = stdout_output || self.config.format != OutputFormat::Text;
+        if self.config.report.as_deref() == Some(STDIN) && (stdout_busy || self.config.diff) {
+            bail!("--report - needs stdout to itself; write outputs with --output or --out-dir");
+        }
+
+        let in_place_suffix = self.config.in_place.as_deref().filter(|s| !s.is_empty());
+        let backup_suffix = match (in_place_suffix, self.config.backup.as_deref()) {
+            (Some(a), Some(b)) if a != b => {
+                bail!(
+                    "--in-place={} and --backup={} ask for different backups",
+                    a,
+                    b
+                )
+            }
+            (suffix, backup) => suffix.or(backup),
+        };
+
+        let templated = self.templated_outputs()?;
+        let mut seen = HashSet::new();
+        self.config
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(i, input)| {
+                let output = match &self.config.out_dir {
+                    _ if self.config.in_place.is_some() => Some(input.clone()),
+                    _ if !templated.is_empty() => Some(templated[i].clone()),
+                    Some(dir) => {
+                        let name = Path::new(input)
+                            .file_name()
+                            .context(format!("Input has no file name: {}", input))?;
+                        if !seen.insert(name.to_os_string()) {
+                            bail!(
+                                "Multiple inputs would write to the same output: {}",
+                                name.to_string_lossy()
+                            );
+                        }
+                        Some(Path::new(dir).join(name).to_string_lossy().into_owned())
+                    }
+                    None => self.config.output.clone(),
+                };
+                let backup = match (backup_suffix, &output) {
+                    (Some(suffix), Some(path)) => Some(backup_path(Path::new(path), suffix)),
+                    _ => None,
+                };
+                Ok(Job {
+                    input: input.clone(),
+                    backup,
+                    in_place: self.config.in_place.is_some(),
+                    overwrite: self.config.force || self.config.in_place.is_some(),
+                    capture: output.is_none() && self.config.format != OutputFormat::Text,
+                    output,
+                })
+            })
+            .collect()
+    }
+
+    /// Expands `--output-template` for every input, if it was given
+    ///
+    /// Inputs sharing an output fail the run here, before any is processed.
+    /// Names using `{hash8}` are only checked as each output is finished.
+    fn templated_outputs(&self) -> Result<Vec<String>> {
+        let Some(template) = &self.config.output_template else {
+            return Ok(Vec::new());
+        };
+        let transform = self.config.mode.to_string();
+        // Once per run, so a batch spanning midnight keeps one date
+        let date = Local::now().format("%Y-%m-%d").to_string();
+        let outputs = self
+            .config
+            .inputs
+            .iter()
+            .map(|input| template.expand(Path::new(input), &transform, &date))
+            .collect::<Result<Vec<_>>>()?;
+
+        if !template.uses_hash() {
+            let mut writers: HashMap<&str, Vec<String>> = HashMap::new();
+            for (output, input) in outputs.iter().zip(&self.config.inputs) {
+                writers.entry(output).or_default().push(input.clone());
+            }
+            if let Some(output) = outputs
+                .iter()
+                .find(|output| writers[output.as_str()].len() > 1)
+            {
+                return Err(AppError::OutputCollision {
+                    output: output.clone(),
+                    inputs: writers.remove(output.as_str()).unwrap_or_default(),
+                }
+                .into());
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// Number of worker threads to use for `jobs`
+    fn worker_count(&self, jobs: &[Job]) -> usize {
+        // Concurrent writers would interleave on stdout
+        if jobs.iter().any(|job| job.output.is_none()) {
+            return 1;
+        }
+        let requested = match self.config.jobs {
+            0 => std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            n => n,
+        };
+        requested.min(jobs.len()).max(1)
+    }
+
+    /// Processes one input inside a span so concurrent logs stay attributed
+    ///
+    /// The `file` span carries the input path, the transform and, once the
+    /// input is read, `bytes_in`. With several inputs a failure is logged
+    /// inside it too; a single input's error is left to the caller. With a
+    /// checkpoint, an input it lists as done is left alone and one
+    /// that succeeds is added to it. A panic fails only this input (see
+    /// [`catch_panic`]).
+    fn process_job(&self, job: &Job, checkpoint: Option<&Checkpoint>) -> FileResult {
+        let span = info_span!(
+            "file",
+            file = %self.sensitive(&job.input),
+            transform = %self.config.mode,
+            bytes_in = tracing::field::Empty,
+        );
+        let started = Instant::now();
+        let deadline = self.config.timeout.map(|limit| started + limit);
+        let mut captured = None;
+        let mut resumed = false;
+        let outcome = span.in_scope(|| {
+            catch_panic(|| {
+                if self.is_interrupted() {
+                    return Err(Aborted.into());
+                }
+                // Before the overwrite check, which the earlier run's own output
+                // would fail
+                if checkpoint.is_some_and(|checkpoint| checkpoint.is_done(&job.input)) {
+                    info!("Already done according to the checkpoint");
+                    resumed = true;
+                    return Ok(StreamStats::default());
+                }
+                self.check_size(&job.input)?;
+                if let Some(expected) = &self.config.verify_input {
+                    verify_digest(&job.input, expected)?;
+                }
+                if self.config.diff {
+                    let (stats, diff) = self.diff_job(job, deadline)?;
+                    captured = Some(diff);
+                    return Ok(stats);
+                }
+                self.check_overwrite(job)?;
+                if self.config.count {
+                    let (mut stats, count) = self.count_kept_lines(job, deadline)?;
+                    // Several inputs sharing stdout are told apart like `grep -c`
+                    let shared_stdout =
+                        job.output.is_none() && !job.capture && self.config.inputs.len() > 1;
+                    let report = if shared_stdout {
+                        format!("{}:{}", job.input, count)
+                    } else {
+                        count.to_string()
+                    };
+                    stats.bytes_out = report.len() as u64;
+                    if job.capture {
+                        captured = Some(report);
+                    } else {
+                        let mut output = self.open_output(job.output.as_deref())?;
+                        writeln!(output, "{}", report)?;
+                        self.finish_output(job, output)?;
+                    }
+                    return Ok(stats);
+                }
+                if job.capture {
+                    let (stats, output) = self.transform_in_memory(job, deadline)?;
+                    captured = Some(output);
+                    return Ok(stats);
+                }
+                let result = if self.config.mode.requires_whole_input() {
+                    warn!(
+                        "Mode {:?} needs the whole input; buffering it in memory",
+                        self.config.mode
+                    );
+                    self.run_buffered(job, deadline)
+                } else if self.config.sort.is_some() {
+                    warn!("--sort needs the whole input; buffering it in memory");
+                    self.run_buffered(job, deadline)
+                } else {
+                    self.run_streaming(job, deadline)
+                };
+                ignore_broken_pipe(result)
+            })
+        });
+        let outcome = match (outcome, checkpoint) {
+            (Ok(stats), Some(checkpoint)) if !resumed => span
+                .in_scope(|| checkpoint.record(&job.input))
+                .map(|()| stats),
+            (outcome, _) => outcome,
+        };
+        if let (Ok(stats), false) = (&outcome, resumed) {
+            let layout = stats.layout;
+            span.record("bytes_in", stats.bytes_in);
+            span.in_scope(|| {
+                info!(
+                    bom = layout.bom,
+                    crlf_lines = layout.crlf_lines,
+                    lf_lines = layout.lf_lines,
+                    final_newline = layout.final_newline,
+                    "Detected text layout"
+                )
+            });
+        }
+
+        let result = FileResult {
+            input: job.input.clone(),
+            output: self.written_output(job),
+            outcome,
+            duration: started.elapsed(),
+            captured,
+            resumed,
+        };
+        if self.config.inputs.len() > 1 {
+            let backup = job.backup.as_ref().map(|path| path.to_string_lossy());
+            let paths: Vec<&str> = [
+                Some(job.input.as_str()),
+                job.output.as_deref(),
+                result.output.as_deref(),
+                backup.as_deref(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            span.in_scope(|| match &result.outcome {
+                Err(e) if result.is_skipped() => warn!(
+                    "Skipped {}: {}",
+                    self.sensitive(&result.input),
+                    self.sensitive_error(e, &paths)
+                ),
+                Err(e) => error!(
+                    "{}: {}",
+                    self.sensitive(&result.input),
+                    self.sensitive_error(e, &paths)
+                ),
+                Ok(_) => {}
+            });
+        }
+        result
+    }
+
+    /// Fails early if the job would replace an existing file without `--force`
+    ///
+    /// This only saves processing an input whose output will be refused;
+    /// [`AtomicFile::commit`] enforces the rule without a race.
+    fn check_overwrite(&self, job: &Job) -> Result<()> {
+        let Some(path) = job.output.as_deref().filter(|_| !job.overwrite) else {
+            return Ok(());
+        };
+        let base = Path::new(path);
+        let targets = match self.config.split {
+            // A split output never writes `path` itself
+            Some(_) => vec![split_manifest_path(base), split_part_path(base, 1)],
+            None => vec![base.to_path_buf()],
+        };
+        match targets.iter().find(|target| target.exists()) {
+            Some(target) => Err(AppError::OutputExists(target.display().to_string()).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails with [`AppError::InputTooLarge`] if the input is over
+    /// `--max-file-size`
+    ///
+    /// Sizes are as stored, so a compressed input is measured compressed.
+    /// Stdin has no size to check, and an input that cannot be read is left
+    /// for opening it to report.
+    fn check_size(&self, input: &str) -> Result<()> {
+        let Some(limit) = self.config.max_file_size else {
+            return Ok(());
+        };
+        if input == STDIN {
+            return Ok(());
+        }
+        match fs::metadata(input) {
+            Ok(metadata) if metadata.len() > limit => Err(AppError::InputTooLarge {
+                path: input.to_string(),
+                size: metadata.len(),
+                limit,
+                strict: self.config.strict_limits,
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with [`Aborted`] once the run is interrupted, or with
+    /// [`AppError::Timeout`] once the input's `deadline` has passed
+    fn keep_going(&self, deadline: Option<Instant>) -> Result<()> {
+        if self.is_interrupted() {
+            return Err(Aborted.into());
+        }
+        match (deadline, self.config.timeout) {
+            (Some(deadline), Some(limit)) if Instant::now() >= deadline => {
+                Err(AppError::Timeout(limit).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the whole input, transforms it, and writes it out
+    fn run_buffered(&self, job: &Job, deadline: Option<Instant>) -> Result<StreamStats> {
+        let (stats, output) = self.transform_in_memory(job, deadline)?;
+
+        self.write_output(job, &output)
+            .context("Failed to write output")?;
+
+        Ok(stats)
+    }
+
+    /// Reads the whole input and returns it transformed, failing like
+    /// [`App::keep_going`] once past `deadline`
+    fn transform_in_memory(
+        &self,
+        job: &Job,
+        deadline: Option<Instant>,
+    ) -> Result<(StreamStats, String)> {
+        let input = self
+            .read_input(&job.input)
+            .context("Failed to read input file")?;
+        self.keep_going(deadline)?;
+
+        info!("Read {} bytes from input", input.len());
+        debug!(preview = %self.sensitive(preview(&input)), "Input preview");
+
+        let output = self
+            .process_until(&input, deadline)
+            .context("Failed to process data")?;
+        self.keep_going(deadline)?;
+
+        let stats = StreamStats {
+            bytes_in: input.len() as u64,
+            bytes_out: output.len() as u64,
+            peak_buffer: input.len(),
+            layout: TextLayout::detect(&input),
+        };
+        Ok((stats, output))
+    }
+
+    /// Writes `records` to `out` in the configured structured format
+    fn emit<T: Serialize>(&self, records: &[T], out: impl Write) -> Result<()> {
+        self.config.format.write_records(records, out)
+    }
+    /// Streams the input through the transform one line at a time
+    fn run_streaming(&self, job: &Job, deadline: Option<Instant>) -> Result<StreamStats> {
+        let reader = self
+            .open_input(&job.input)
+            .context("Failed to read input file")?;
+        let stats = if let Some(limit) = self.config.split {
+            let mut writer = SplitWriter::new(self, job, limit)?;
+            let stats = self
+                .stream_lines(reader, &mut writer, deadline)
+                .context("Failed to process data")?;
+            writer.finish().context("Failed to write output")?;
+            stats
+        } else {
+            let mut writer = self
+                .open_output(job.output.as_deref())
+                .context("Failed to write output")?;
+
+            let stats = self
+                .stream_lines(reader, &mut writer, deadline)
+                .context("Failed to process data")?;
+
+            self.finish_output(job, writer)
+                .context("Failed to write output")?;
+            stats
+        };
+
+        if stats.bytes_in == 0 {
+            warn!("Input is empty, returning unchanged");
+        }
+        info!(
+            "Processed {} bytes into {} bytes",
+            stats.bytes_in, stats.bytes_out
+        );
+        Ok(stats)
+    }
+
+    /// Transforms the input in memory and diffs it against the original,
+    /// failing like [`App::keep_going`] once past `deadline`
+    fn diff_job(&self, job: &Job, deadline: Option<Instant>) -> Result<(StreamStats, String)> {
+        let input = self
+            .read_input(&job.input)
+            .context("Failed to read input file")?;
+        self.keep_going(deadline)?;
+        let output = self
+            .process_until(&input, deadline)
+            .context("Failed to process data")?;
+        self.keep_going(deadline)?;
+
+        let stats = StreamStats {
+            bytes_in: input.len() as u64,
+            bytes_out: output.len() as u64,
+            peak_buffer: input.len(),
+            layout: TextLayout::detect(&input),
+        };
+        Ok((stats, self.render_diff(&job.input, &input, &output)))
+    }
+
+    /// Unified diff from `before` to `after`, empty if they are identical
+    ///
+    /// Both sides are labeled with `path`, so `patch -p0` applies the result.
+    /// Content with NUL bytes only gets a one-line summary, like `diff` does
+    /// for binary files.
+    fn render_diff(&self, path: &str, before: &str, after: &str) -> String {
+        if before == after {
+            return String::new();
+        }
+        if before.contains('\0') || after.contains('\0') {
+            return format!("Binary file {} would change\n", path);
+        }
+
+        let diff = TextDiff::from_lines(before, after)
+            .unified_diff()
+            .context_radius(3)
+            .header(path, path)
+            .to_string();
+        if self.config.color.enabled() {
+            colorize_diff(&diff)
+        } else {
+            diff
+        }
+    }
+
+    /// Counts the input lines that survive `--grep` and `--dedup`, one line
+    /// at a time
+    fn count_kept_lines(&self, job: &Job, deadline: Option<Instant>) -> Result<(StreamStats, u64)> {
+        let mut reader = self
+            .open_input(&job.input)
+            .context("Failed to read input file")?;
+        let mut stats = StreamStats::default();
+        let mut count = 0;
+        let mut line_number = 0;
+        let mut seen = SeenLines::new(self.config.dedup);
+        let mut buf = Vec::with_capacity(STREAM_BUFFER_SIZE);
+
+        loop {
+            buf.clear();
+            let read = self.read_line(&mut reader, &mut buf, stats.bytes_in)?;
+            if read == 0 {
+                break;
+            }
+            self.keep_going(deadline)?;
+            line_number += 1;
+            let line = std::str::from_utf8(&buf).context(format!(
+                "Input is not valid UTF-8 near byte {}",
+                stats.bytes_in
+            ))?;
+            let content = split_line_ending(line).0;
+            if self.line_action(content, line_number, &mut seen)? == LineAction::Transform {
+                count += 1;
+            }
+            stats.bytes_in += read as u64;
+            stats.peak_buffer = stats.peak_buffer.max(buf.capacity());
+        }
+
+        info!("{} lines kept", count);
+        Ok((stats, count))
+    }
+
+    /// Returns true if `--grep` and `--invert-match` let the line through
+    fn keeps_line(&self, content: &str) -> bool {
+        match &self.config.grep {
+            Some(pattern) => pattern.is_match(content) != self.config.invert_match,
+            None => true,
+        }
+    }
+
+    /// Decides what happens to a line: `--grep` first, then `--dedup`, then
+    /// the time range
+    ///
+    /// `seen` holds the lines already kept from the same input.
+    fn line_action(
+        &self,
+        content: &str,
+        line_number: u64,
+        seen: &mut SeenLines,
+    ) -> Result<LineAction> {
+        if !self.keeps_line(content) || !seen.first_sight(content) {
+            return Ok(LineAction::Drop);
+        }
+        let Some(range) = &self.config.time_range else {
+            return Ok(LineAction::Transform);
+        };
+        match range.contains_line(content) {
+            Some(true) => Ok(LineAction::Transform),
+            Some(false) if range.drop_out_of_range => Ok(LineAction::Drop),
+            Some(false) => Ok(LineAction::Copy),
+            None if range.untimed == Untimed::Pass => Ok(LineAction::Copy),
+            None => bail!(
+                "Line {} has no RFC 3339 timestamp; pass --untimed-lines=pass to copy such \
+                 lines unchanged",
+                line_number
+            ),
+        }
+    }
+
+    /// Formats `error` and its causes for a log line, hiding `paths` in
+    /// them under `--redact`
+    ///
+    /// Error contexts name the file they failed on, so wrapping the fields
+    /// around the error is not enough.
+    fn sensitive_error(&self, error: &anyhow::Error, paths: &[&str]) -> String {
+        let mut rendered = format!("{:#}", error);
+        if self.config.redact {
+            let mut paths = paths.to_vec();
+            // Longest first, so a path inside another cannot leave part of
+            // the longer one showing
+            paths.sort_by_key(|path| cmp::Reverse(path.len()));
+            for path in paths.into_iter().filter(|path| !path.is_empty()) {
+                rendered = rendered.replace(path, "<redacted>");
+            }
+        }
+        rendered
+    }
+
+    /// Wraps a value for logging, honoring `--redact`
+    fn sensitive<T>(&self, value: T) -> Sensitive<T> {
+        if self.config.redact {
+            Sensitive::Hidden(Redacted(value))
+        } else {
+            Sensitive::Shown(value)
+        }
+    }
+
+    /// Opens an input, decompressing it on the fly if needed
+    fn open_input(&self, path: &str) -> Result<Box<dyn BufRead>> {
+        if path == STDIN {
+            info!("Reading from stdin");
+            let reader = self.decompress(io::stdin().lock(), path)?;
+            return self.decode(reader, path);
+        }
+        info!("Reading from: {}", self.sensitive(path));
+        let file = File::open(path).map_err(|e| -> anyhow::Error {
+            if e.kind() == io::ErrorKind::NotFound {
+                AppError::InputNotFound {
+                    path: path.to_string(),
+                    source: e,
+                }
+                .into()
+            } else {
+                anyhow::Error::new(e).context(format!("Cannot read file: {}", path))
+            }
+        })?;
+        // Only a single input gets a byte count; batches count files
+        let bytes = if self.config.inputs.len() == 1 {
+            let len = file.metadata().map_or(0, |meta| meta.len());
+            self.progress
+                .add(ProgressBar::new(len).with_style(progress_style(BYTES_TEMPLATE)))
+        } else {
+            ProgressBar::hidden()
+        };
+        let file = Retrying {
+            inner: file,
+            policy: self.config.io_retry,
+        };
+        let reader = ProgressReader {
+            inner: BufReader::with_capacity(STREAM_BUFFER_SIZE, file),
+            bar: bytes,
+        };
+        let reader = self.decompress(reader, path)?;
+        self.decode(reader, path)
+    }
+
+    /// Wraps `reader` in a decoder if the input at `path` is compressed
+    ///
+    /// `--input-compression` decides if given, then a `.gz` extension;
+    /// failing both, the first bytes are checked for the gzip magic number.
+    fn decompress(
+        &self,
+        mut reader: impl BufRead + 'static,
+        path: &str,
+    ) -> Result<Box<dyn BufRead>> {
+        let compression = match self.config.input_compression {
+            Some(compression) => compression,
+            None if Compression::detect(path) == Compression::Gzip => Compression::Gzip,
+            None => Compression::sniff(reader.fill_buf().context("Cannot read input")?),
+        };
+        match compression {
+            Compression::None => Ok(Box::new(reader)),
+            Compression::Gzip => {
+                debug!("Decompressing gzip input");
+                let decoder = GzipReader {
+                    inner: MultiGzDecoder::new(reader),
+                    path: if path == STDIN { "stdin" } else { path }.to_string(),
+                };
+                Ok(Box::new(BufReader::with_capacity(
+                    STREAM_BUFFER_SIZE,
+                    decoder,
+                )))
+            }
+        }
+    }
+
+    /// Wraps `reader` in a decoder to UTF-8 unless the input already is UTF-8
+    ///
+    /// `--encoding` decides if given; otherwise [`TextEncoding::sniff`] looks
+    /// at the start of the decompressed input.
+    fn decode(&self, mut reader: Box<dyn BufRead>, path: &str) -> Result<Box<dyn BufRead>> {
+        let encoding = match self.config.encoding.forced() {
+            Some(encoding) => encoding,
+            None => TextEncoding::sniff(reader.fill_buf().context("Cannot read input")?),
+        };
+        // Valid UTF-8 passes through as is; `stream_lines` rejects the rest
+        if encoding == TextEncoding::Utf8 && !self.config.lossy {
+            return Ok(reader);
+        }
+        debug!("Decoding {} input", encoding);
+        let decoder = DecodingReader::new(reader, encoding, self.config.lossy, path);
+        Ok(Box::new(BufReader::with_capacity(
+            STREAM_BUFFER_SIZE,
+            decoder,
+        )))
+    }
+
+    /// Opens the output, compressing it on the fly if needed
+    fn open_output(&self, output: Option<&str>) -> Result<Output> {
+        let compression = match (self.config.output_compression, output) {
+            (Some(forced), _) => forced,
+            (None, Some(path)) => Compression::detect(path),
+            (None, None) => Compression::None,
+        };
+        self.open_output_as(output, compression)
+    }
+
+    /// [`App::open_output`] with the compression already decided
+    fn open_output_as(&self, output: Option<&str>, compression: Compression) -> Result<Output> {
+        let dest = match output {
+            Some(path) => {
+                info!("Writing to: {}", self.sensitive(path));
+                ensure_parent_dir(Path::new(path), self.config.create_dirs)?;
+                let file = AtomicFile::create(Path::new(path))
+                    .context(format!("Cannot write file: {}", path))?;
+                Destination::File(file)
+            }
+            None => {
+                info!("Writing to stdout");
+                Destination::Stdout(BufWriter::with_capacity(STREAM_BUFFER_SIZE, io::stdout()))
+            }
+        };
+        let sink = Sink {
+            dest: Retrying {
+                inner: dest,
+                policy: self.config.io_retry,
+            },
+            hasher: (self.config.emit_checksum || self.names_by_hash()).then(Sha256::new),
+        };
+
+        let output = match compression {
+            Compression::None => Output::Plain(sink),
+            Compression::Gzip => {
+                debug!("Compressing output with gzip");
+                Output::Gzip(GzEncoder::new(sink, flate2::Compression::default()))
+            }
+        };
+        match self.config.output_encoding {
+            TextEncoding::Utf8 => Ok(output),
+            encoding => {
+                debug!("Encoding output as {}", encoding);
+                Ok(Output::Encoded(Box::new(EncodingWriter::new(
+                    output,
+                    encoding,
+                    self.config.lossy,
+                ))))
+            }
+        }
+    }
+
+    /// Completes the output and, for files, moves it into place
+    fn finish_output(&self, job: &Job, output: Output) -> Result<()> {
+        let mut sink = output.finish()?;
+        // Flushed here so the last buffered bytes get `--io-retries` too
+        sink.flush()?;
+        let digest = sink.hasher.map(|hasher| format!("{:x}", hasher.finalize()));
+        match sink.dest.inner {
+            Destination::File(mut file) => {
+                let named;
+                let job = match &digest {
+                    Some(digest) if self.names_by_hash() => {
+                        named = self.name_by_hash(job, &mut file, digest)?;
+                        &named
+                    }
+                    _ => job,
+                };
+                let target = file.target.clone();
+                self.commit_output(job, file)?;
+                match digest.filter(|_| self.config.emit_checksum) {
+                    Some(digest) => write_checksum_file(&target, &digest),
+                    None => Ok(()),
+                }
+            }
+            Destination::Stdout(mut stdout) => {
+                stdout.flush()?;
+                if let Some(digest) = digest {
+                    // `sha256sum` names stdin `-`; stderr keeps stdout clean
+                    eprintln!("{}  -", digest);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether output file names include `{hash8}`
+    fn names_by_hash(&self) -> bool {
+        self.config
+            .output_template
+            .as_ref()
+            .is_some_and(OutputTemplate::uses_hash)
+    }
+
+    /// Fills in `{hash8}` in the output's name, now that its digest is known
+    ///
+    /// Returns the job with the final output and backup paths. Fails with
+    /// [`AppError::OutputCollision`] if another input of this run already
+    /// wrote that name, which means its output had the same contents.
+    fn name_by_hash(&self, job: &Job, file: &mut AtomicFile, digest: &str) -> Result<Job> {
+        let hash8 = &digest[..8];
+        file.target = fill_hash8(&file.target, hash8);
+        match self
+            .hashed_outputs
+            .lock()
+            .unwrap()
+            .entry(file.target.clone())
+        {
+            Entry::Occupied(entry) => {
+                return Err(AppError::OutputCollision {
+                    output: file.target.display().to_string(),
+                    inputs: vec![entry.get().clone(), job.input.clone()],
+                }
+                .into())
+            }
+            Entry::Vacant(entry) => entry.insert(job.input.clone()),
+        };
+        info!("Naming output: {}", self.sensitive(file.target.display()));
+        Ok(Job {
+            output: Some(file.target.to_string_lossy().into_owned()),
+            backup: job
+                .backup
+                .as_deref()
+                .map(|backup| fill_hash8(backup, hash8)),
+            ..job.clone()
+        })
+    }
+
+    /// Output path of `job` as written, with any `{hash8}` filled in
+    fn written_output(&self, job: &Job) -> Option<String> {
+        let output = job.output.clone()?;
+        if !output.contains(HASH8_MARKER) {
+            return Some(output);
+        }
+        let named = self.hashed_outputs.lock().unwrap();
+        let written = named.iter().find(|(_, input)| **input == job.input);
+        Some(written.map_or(output, |(path, _)| path.to_string_lossy().into_owned()))
+    }
+
+    /// Replaces the job's output file, unless in-place editing changed nothing
+    fn commit_output(&self, job: &Job, mut file: AtomicFile) -> Result<()> {
+        // Skipping the rename keeps the mtime, so build tools and editors
+        // don't see a change that isn't there
+        if job.in_place && file.matches_target()? {
+            info!("Output unchanged, leaving file untouched");
+            return Ok(());
+        }
+        file.commit(job.backup.as_deref(), job.overwrite)
+    }
+
+    /// Transforms `reader` into `writer` one line at a time
+    ///
+    /// Only the current line is held in memory; the line buffer is reused
+    /// across iterations so allocation stays flat for any input size.
+    pub fn process_streaming<R: BufRead, W: Write>(
+        &self,
+        reader: R,
+        writer: W,
+    ) -> Result<StreamStats> {
+        self.stream_lines(reader, writer, None)
+    }
+
+    /// [`App::process_streaming`], failing once `deadline` passes
+    fn stream_lines<R: BufRead, W: Write>(
+        &self,
+        mut reader: R,
+        mut writer: W,
+        deadline: Option<Instant>,
+    ) -> Result<StreamStats> {
+        let mode = self.config.mode;
+        let mut stats = StreamStats::default();
+        let mut buf = Vec::with_capacity(STREAM_BUFFER_SIZE);
+        // Each terminator is held back until the next line shows whether it
+        // ends the output, where `--final-newline` decides
+        let mut pending = "";
+        let mut previous = self.config.newline.convert("\n");
+        let mut wrote_line = false;
+        let mut line_number = 0;
+        let mut seen = SeenLines::new(self.config.dedup);
+        let mut dedupe = Dedupe::new(self.config.dedupe, self.config.dedupe_memory);
+
+        loop {
+            buf.clear();
+            let read = self.read_line(&mut reader, &mut buf, stats.bytes_in)?;
+            if read == 0 {
+                break;
+            }
+            self.keep_going(deadline)?;
+            line_number += 1;
+
+            let line = std::str::from_utf8(&buf).context(format!(
+                "Input is not valid UTF-8 near byte {}",
+                stats.bytes_in
+            ))?;
+            let (mut content, ending) = split_line_ending(line);
+            if stats.bytes_in == 0 {
+                debug!(preview = %self.sensitive(preview(line)), "Input preview");
+                stats.layout.bom = content.starts_with(BOM);
+                if self.config.strip_bom {
+                    content = content.strip_prefix(BOM).unwrap_or(content);
+                }
+            }
+            #[cfg(test)]
+            if let Some(hook) = self.line_hook {
+                hook(content);
+            }
+            stats.layout.record_line(ending);
+            stats.bytes_in += read as u64;
+            let output = match self.line_action(content, line_number, &mut seen)? {
+                LineAction::Transform => mode.apply_line_in(content, self.config.case_locale),
+                LineAction::Copy => Cow::Borrowed(content),
+                LineAction::Drop => continue,
+            };
+            if !dedupe.keep(&output)? {
+                continue;
+            }
+            writer.write_all(pending.as_bytes())?;
+            writer.write_all(output.as_bytes())?;
+            stats.bytes_out += (pending.len() + output.len()) as u64;
+            stats.peak_buffer = stats.peak_buffer.max(buf.capacity());
+
+            if !pending.is_empty() {
+                previous = pending;
+            }
+            pending = self.config.newline.convert(ending);
+            wrote_line = true;
+        }
+
+        if wrote_line {
+            let last = self.config.final_newline.resolve(pending, previous);
+            writer.write_all(last.as_bytes())?;
+            stats.bytes_out += last.len() as u64;
+        }
+        writer.flush()?;
+        Ok(stats)
+    }
+
+    /// Appends the next line of `reader` to `buf` like `read_until`, but
+    /// fails with [`AppError::LineTooLong`] instead of reading more than
+    /// `--max-line-length` bytes of it
+    ///
+    /// `offset` is where the line starts, counted in decompressed bytes.
+    fn read_line<R: BufRead>(
+        &self,
+        reader: &mut R,
+        buf: &mut Vec<u8>,
+        offset: u64,
+    ) -> Result<usize> {
+        let Some(limit) = self.config.max_line_length else {
+            return Ok(reader.read_until(b'\n', buf)?);
+        };
+        // One byte over the limit is either the terminator or proof of a
+        // line that is too long
+        let read = reader.take(limit + 1).read_until(b'\n', buf)?;
+        if read as u64 > limit && !buf.ends_with(b"\n") {
+            return Err(AppError::LineTooLong { offset, limit }.into());
+        }
+        Ok(read)
+    }
+
+    fn read_input(&self, path: &str) -> Result<String> {
+        let mut input = String::new();
+        self.open_input(path)?
+            .read_to_string(&mut input)
+            .context(format!("Cannot read file: {}", path))?;
+        Ok(input)
+    }
+
+    /// Filters and transforms an in-memory input with the configured mode
+    ///
+    /// Lines are filtered first, by `--grep` and `--dedup`, so whole-input
+    /// modes such as `Sort` only see the lines that were kept. Lines outside `--since`/`--until` are
+    /// copied unchanged.
+    pub fn process(&self, input: &str) -> Result<String> {
+        self.process_until(input, None)
+    }
+
+    /// [`App::process`], failing like [`App::keep_going`] between lines
+    /// and around a transform of the whole input once past `deadline`
+    fn process_until(&self, input: &str, deadline: Option<Instant>) -> Result<String> {
+        info!("Processing input");
+
+        if input.is_empty() {
+            warn!("Input is empty, returning unchanged");
+            return Ok(input.to_string());
+        }
+
+        let mut input = Cow::Borrowed(input);
+        if self.config.strip_bom {
+            if let Some(rest) = input.strip_prefix(BOM) {
+                input = Cow::Owned(rest.to_string());
+            }
+        }
+        if self.config.newline != Newline::Preserve {
+            input = Cow::Owned(self.config.newline.convert_all(&input));
+        }
+
+        let (mode, case) = (self.config.mode, self.config.case_locale);
+        let selects_lines = self.config.grep.is_some()
+            || self.config.dedup.is_some()
+            || self.config.time_range.is_some();
+        let mut seen = SeenLines::new(self.config.dedup);
+        let mut output = if !selects_lines {
+            mode.apply_in(&input, case)
+        } else if mode.requires_whole_input() {
+            let mut kept = String::new();
+            for (line, number) in input.split_inclusive('\n').zip(1..) {
+                self.keep_going(deadline)?;
+                let content = split_line_ending(line).0;
+                if self.line_action(content, number, &mut seen)? != LineAction::Drop {
+                    kept.push_str(line);
+                }
+            }
+            mode.apply_in(&kept, case)
+        } else {
+            let mut output = String::with_capacity(input.len());
+            for (line, number) in input.split_inclusive('\n').zip(1..) {
+                self.keep_going(deadline)?;
+                let (content, ending) = split_line_ending(line);
+                match self.line_action(content, number, &mut seen)? {
+                    LineAction::Transform => {
+                        output.push_str(&mode.apply_line_in(content, case));
+                        output.push_str(ending);
+                    }
+                    LineAction::Copy => output.push_str(line),
+                    LineAction::Drop => {}
+                }
+            }
+            output
+        };
+        // Whole-input modes rejoin lines with `\n`
+        if mode.requires_whole_input() && self.config.newline != Newline::Preserve {
+            output = self.config.newline.convert_all(&output);
+        }
+        self.keep_going(deadline)?;
+        let output = self.finish_text(self.sort_and_dedupe(output)?);
+
+        info!("Processed {} bytes", output.len());
+        Ok(output)
+    }
+
+    /// Applies `--sort` and then `--dedupe` to a transformed text
+    ///
+    /// A last line without a terminator borrows the one before it in case
+    /// it moves, and the text still ends the way it did.
+    fn sort_and_dedupe(&self, text: String) -> Result<String> {
+        if self.config.sort.is_none() && self.config.dedupe.is_none() {
+            return Ok(text);
+        }
+        let mut lines: Vec<_> = text.split_inclusive('\n').map(split_line_ending).collect();
+        if let [.., (_, previous), (_, last)] = lines.as_mut_slice() {
+            if last.is_empty() {
+                *last = *previous;
+            }
+        }
+        if let Some(order) = self.config.sort {
+            // Stable, so equal lines keep their input order
+            lines.sort_by(|(a, _), (b, _)| order.compare(a, b));
+        }
+
+        let mut dedupe = Dedupe::new(self.config.dedupe, self.config.dedupe_memory);
+        let mut output = String::with_capacity(text.len());
+        for (content, ending) in lines {
+            if dedupe.keep(content)? {
+                output.push_str(content);
+                output.push_str(ending);
+            }
+        }
+        if !text.ends_with('\n') {
+            output.truncate(split_line_ending(&output).0.len());
+        }
+        Ok(output)
+    }
+
+    /// Applies `--final-newline` to a transformed text
+    fn finish_text(&self, mut text: String) -> String {
+        if text.is_empty() {
+            return text;
+        }
+        let (content, last) = split_line_ending(&text);
+        let previous = match content.rfind('\n') {
+            Some(end) if content[..end].ends_with('\r') => "\r\n",
+            Some(_) => "\n",
+            None => self.config.newline.convert("\n"),
+        };
+        let ending = self.config.final_newline.resolve(last, previous);
+        text.truncate(content.len());
+        text.push_str(ending);
+        text
+    }
+
+    fn write_output(&self, job: &Job, data: &str) -> Result<()> {
+        if let Some(limit) = self.config.split {
+            let mut writer = SplitWriter::new(self, job, limit)?;
+            writer.write_all(data.as_bytes())?;
+            return writer.finish();
+        }
+        // Same Output as the streaming path, so compression and atomic
+        // replacement behave identically in both
+        let mut output = self.open_output(job.output.as_deref())?;
+        output.write_all(data.as_bytes())?;
+        let result = self.finish_output(job, output);
+        match job.output.as_deref() {
+            Some(path) => result.context(format!("Cannot write file: {}", path)),
+            None => result,
+        }
+    }
+}
+
+/// Makes sure the directory `target` goes in exists before writing to it
+///
+/// With `create` the directory is made along with any missing parents;
+/// otherwise its absence is reported as [`AppError::OutputDirMissing`]
+/// instead of the bare OS error from creating the temporary file.
+fn ensure_parent_dir(target: &Path, create: bool) -> Result<()> {
+    let dir = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => return Ok(()),
+    };
+    if dir.is_dir() {
+        return Ok(());
+    }
+    if !create {
+        return Err(AppError::OutputDirMissing(dir.display().to_string()).into());
+    }
+    // Tolerates another worker creating it first
+    fs::create_dir_all(dir).context(format!("Cannot create directory: {}", dir.display()))?;
+    debug!("Created output directory");
+    Ok(())
+}
+
+/// Progress bar layout for a single input, in bytes of the file as stored
+const BYTES_TEMPLATE: &str = "{bytes}/{total_bytes} [{wide_bar}] {bytes_per_sec}, ETA {eta}";
+
+/// Progress bar layout for a batch, in files completed
+const FILES_TEMPLATE: &str = "{pos}/{len} files [{wide_bar}] ETA {eta}";
+
+fn progress_style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template)
+        .expect("valid progress template")
+        .progress_chars("=> ")
+}
+
+/// Reader that advances a progress bar by every byte read through it
+///
+/// The bar is cleared once the reader is dropped.
+struct ProgressReader<R> {
+    inner: R,
+    bar: ProgressBar,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bar.inc(read as u64);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for ProgressReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.bar.inc(amt as u64);
+    }
+}
+
+impl<R> Drop for ProgressReader<R> {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Whether an I/O error of this kind may go away if the call is repeated
+///
+/// Anything else, such as `NotFound` or `PermissionDenied`, will fail the
+/// same way again and is reported at once.
+pub fn is_transient(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// How many times a transiently failing I/O call is repeated, and how long
+/// to wait before each repeat
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Runs `op`, repeating it while it fails with a transient error and
+    /// retries remain; each retry is logged at WARN
+    pub fn run<T>(&self, what: &str, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut attempt = 0;
+        loop {
+            match op() {
+                Err(e) if is_transient(e.kind()) && attempt < self.retries => {
+                    attempt += 1;
+                    warn!(
+                        "{} failed ({}), retry {} of {} in {:?}",
+                        what, e, attempt, self.retries, self.delay
+                    );
+                    std::thread::sleep(self.delay);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Reader or writer whose every call goes through a [`RetryPolicy`]
+///
+/// Repeating a call is safe because a `read` or `write` that returns an
+/// error has transferred no bytes.
+pub struct Retrying<T> {
+    pub inner: T,
+    pub policy: RetryPolicy,
+}
+
+impl<R: Read> Read for Retrying<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.policy.run("Read", || self.inner.read(buf))
+    }
+}
+
+impl<W: Write> Write for Retrying<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.policy.run("Write", || self.inner.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.policy.run("Flush", || self.inner.flush())
+    }
+}
+
+/// Where whole files are read from, by path
+pub trait Source: Send + Sync {
+    /// When the file at `path` was last modified
+    fn modified(&self, path: &Path) -> io::Result<SystemTime>;
+
+    /// The whole contents of the file at `path`
+    fn read(&self, path: &Path) -> io::Result<Arc<[u8]>>;
+}
+
+/// [`Source`] reading straight from the filesystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSource;
+
+impl Source for FileSource {
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        fs::metadata(path)?.modified()
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Arc<[u8]>> {
+        Ok(fs::read(path)?.into())
+    }
+}
+
+/// Read-through cache over another [`Source`], for callers that read the
+/// same files again and again, such as a server or a watch loop
+///
+/// Each read checks the file's modification time and reuses the cached
+/// contents while it is unchanged, so a hit costs one `stat` instead of a
+/// full read. A write landing within the filesystem's timestamp resolution
+/// of the cached one leaves the time unchanged; a watcher that sees the
+/// change event should call [`CachingSource::invalidate`] rather than rely
+/// on the time alone.
+pub struct CachingSource<S> {
+    inner: S,
+    entries: Mutex<HashMap<PathBuf, CachedFile>>,
+}
+
+/// Contents cached by a [`CachingSource`], with the time they are valid for
+struct CachedFile {
+    modified: SystemTime,
+    contents: Arc<[u8]>,
+}
+
+impl<S: Source> CachingSource<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Drops the cached contents of `path`, so the next read goes to the
+    /// underlying source
+    pub fn invalidate(&self, path: &Path) {
+        self.entries.lock().unwrap().remove(path);
+    }
+}
+
+impl<S: Source> Source for CachingSource<S> {
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        self.inner.modified(path)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Arc<[u8]>> {
+        // Taken before reading: a write in between makes the entry look
+        // older than it is, costing a re-read but never serving stale data
+        let modified = self.inner.modified(path)?;
+        if let Some(cached) = self.entries.lock().unwrap().get(path) {
+            if cached.modified == modified {
+                return Ok(Arc::clone(&cached.contents));
+            }
+        }
+        let contents = self.inner.read(path)?;
+        let cached = CachedFile {
+            modified,
+            contents: Arc::clone(&contents),
+        };
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), cached);
+        Ok(contents)
+    }
+}
+
+/// [`MakeWriter`] that clears the progress bars while each log event is
+/// written, then redraws them
+///
+/// Events are buffered and written in one piece, so a log line never ends
+/// up inside a bar. With no bars drawn, output is exactly what `inner`
+/// would have received on its own.
+#[derive(Clone)]
+pub struct LogWriter<M = fn() -> io::Stderr> {
+    progress: MultiProgress,
+    inner: M,
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for LogWriter<M> {
+    type Writer = LogEvent<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogEvent {
+            progress: self.progress.clone(),
+            inner: self.inner.make_writer(),
+            buf: Vec::new(),
+        }
+    }
+}
+
+/// One log event on its way through a [`LogWriter`]
+pub struct LogEvent<W: Write> {
+    progress: MultiProgress,
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Write for LogEvent<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for LogEvent<W> {
+    fn drop(&mut self) {
+        if self.buf.is_empty() {
+            return;
+        }
+        let (inner, buf) = (&mut self.inner, &self.buf);
+        // Nowhere to report a failed log write
+        self.progress.suspend(|| {
+            let _ = inner.write_all(buf).and_then(|()| inner.flush());
+        });
+    }
+}
+
+/// Decompressing reader that names its file in errors
+struct GzipReader<R> {
+    inner: MultiGzDecoder<R>,
+    path: String,
+}
+
+impl<R: BufRead> Read for GzipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Cannot decompress gzip input {}: {}", self.path, e),
+            )
+        })
+    }
+}
+
+/// Reader decoding an input from `encoding` to UTF-8
+///
+/// Without `lossy` the first undecodable sequence fails the read, naming
+/// its byte offset in the input. With it, each such sequence becomes
+/// U+FFFD, and how many were replaced is logged once the input ends.
+struct DecodingReader<R> {
+    inner: R,
+    decoder: encoding_rs::Decoder,
+    encoding: TextEncoding,
+    lossy: bool,
+    path: String,
+    /// Decoded text not yet read, from `pos` on
+    decoded: Vec<u8>,
+    pos: usize,
+    /// Input bytes decoded so far
+    offset: u64,
+    replaced: u64,
+    done: bool,
+}
+
+impl<R: BufRead> DecodingReader<R> {
+    fn new(inner: R, encoding: TextEncoding, lossy: bool, path: &str) -> Self {
+        Self {
+            inner,
+            // A byte order mark is kept as U+FEFF, so `--strip-bom` and
+            // the detected layout treat it like a UTF-8 one
+            decoder: encoding.codec().new_decoder_without_bom_handling(),
+            encoding,
+            lossy,
+            path: if path == STDIN { "stdin" } else { path }.to_string(),
+            decoded: Vec::new(),
+            pos: 0,
+            offset: 0,
+            replaced: 0,
+            done: false,
+        }
+    }
+
+    /// Decodes the next buffer of input into `decoded`
+    fn refill(&mut self) -> io::Result<()> {
+        let input = self.inner.fill_buf()?;
+        let last = input.is_empty();
+        self.decoded.clear();
+        self.pos = 0;
+        let mut consumed = 0;
+        loop {
+            let rest = &input[consumed..];
+            let start = self.decoded.len();
+            let room = self
+                .decoder
+                .max_utf8_buffer_length_without_replacement(rest.len())
+                .unwrap_or(rest.len() * 3 + 4);
+            self.decoded.resize(start + room, 0);
+            let (result, read, written) = self.decoder.decode_to_utf8_without_replacement(
+                rest,
+                &mut self.decoded[start..],
+                last,
+            );
+            self.decoded.truncate(start + written);
+            consumed += read;
+            match result {
+                DecoderResult::InputEmpty => break,
+                DecoderResult::OutputFull => {}
+                DecoderResult::Malformed(bad, after) => {
+                    let start = consumed - usize::from(bad) - usize::from(after);
+                    let at = self.offset + start as u64;
+                    if !self.lossy {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "Input {} is not valid {} at byte {} (use --lossy to replace it)",
+                                self.path, self.encoding, at
+                            ),
+                        ));
+                    }
+                    self.replaced += 1;
+                    self.decoded
+                        .extend_from_slice(char::REPLACEMENT_CHARACTER.to_string().as_bytes());
+                }
+            }
+        }
+        self.inner.consume(consumed);
+        self.offset += consumed as u64;
+
+        if last {
+            self.done = true;
+            if self.replaced > 0 {
+                warn!(
+                    "Replaced {} undecodable {} sequence(s) with U+FFFD",
+                    self.replaced, self.encoding
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // A buffer can decode to nothing, e.g. half of a UTF-16 code unit
+        while self.pos == self.decoded.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.refill()?;
+        }
+        let n = buf.len().min(self.decoded.len() - self.pos);
+        buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Writer encoding UTF-8 text as `encoding` on its way to `inner`
+///
+/// A character split across writes is held back until the rest of it
+/// arrives. Characters `encoding` cannot represent fail the write, or
+/// with `lossy` become `?` and are counted in a warning at the end.
+struct EncodingWriter {
+    inner: Output,
+    encoder: encoding_rs::Encoder,
+    encoding: TextEncoding,
+    lossy: bool,
+    /// Start of a character whose remaining bytes are still to come
+    pending: Vec<u8>,
+    encoded: Vec<u8>,
+    replaced: u64,
+}
+
+impl EncodingWriter {
+    fn new(inner: Output, encoding: TextEncoding, lossy: bool) -> Self {
+        Self {
+            inner,
+            encoder: encoding.codec().new_encoder(),
+            encoding,
+            lossy,
+            pending: Vec::new(),
+            encoded: Vec::new(),
+            replaced: 0,
+        }
+    }
+
+    /// Appends `text`, encoded, to `encoded`
+    fn encode(&mut self, text: &str) -> io::Result<()> {
+        match self.encoding {
+            TextEncoding::Utf8 => self.encoded.extend_from_slice(text.as_bytes()),
+            // encoding_rs only decodes UTF-16; its encoders for it write UTF-8
+            TextEncoding::Utf16Le => {
+                for unit in text.encode_utf16() {
+                    self.encoded.extend_from_slice(&unit.to_le_bytes());
+                }
+            }
+            TextEncoding::Utf16Be => {
+                for unit in text.encode_utf16() {
+                    self.encoded.extend_from_slice(&unit.to_be_bytes());
+                }
+            }
+            TextEncoding::Latin1 => {
+                let mut rest = text;
+                loop {
+                    let start = self.encoded.len();
+                    let room = self
+                        .encoder
+                        .max_buffer_length_from_utf8_without_replacement(rest.len())
+                        .unwrap_or(rest.len());
+                    self.encoded.resize(start + room, 0);
+                    let (result, read, written) =
+                        self.encoder.encode_from_utf8_without_replacement(
+                            rest,
+                            &mut self.encoded[start..],
+                            false,
+                        );
+                    self.encoded.truncate(start + written);
+                    rest = &rest[read..];
+                    match result {
+                        EncoderResult::InputEmpty => break,
+                        EncoderResult::OutputFull => {}
+                        EncoderResult::Unmappable(_) if self.lossy => {
+                            self.replaced += 1;
+                            self.encoded.push(b'?');
+                        }
+                        EncoderResult::Unmappable(c) => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "U+{:04X} cannot be encoded as {} (use --lossy to replace it)",
+                                    c as u32, self.encoding
+                                ),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes out anything held back and returns the stream below
+    fn finish(mut self) -> io::Result<Sink> {
+        if !self.pending.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "output ends in the middle of a UTF-8 character",
+            ));
+        }
+        if self.replaced > 0 {
+            warn!(
+                "Replaced {} character(s) {} cannot represent with ?",
+                self.replaced, self.encoding
+            );
+        }
+        self.inner.flush()?;
+        self.inner.finish()
+    }
+}
+
+impl Write for EncodingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.extend_from_slice(buf);
+        let complete = match std::str::from_utf8(&pending) {
+            Ok(text) => text.len(),
+            // Cut off by the end of `buf`; the rest comes with the next write
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        let text = std::str::from_utf8(&pending[..complete]).expect("checked above");
+        self.encode(text)?;
+        self.inner.write_all(&self.encoded)?;
+        self.encoded.clear();
+        pending.drain(..complete);
+        self.pending = pending;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Where output bytes end up, hashed on the way if requested
+struct Sink {
+    dest: Retrying<Destination>,
+    /// Digest of every byte written, for `--emit-checksum`
+    hasher: Option<Sha256>,
+}
+
+/// Stream behind a [`Sink`]
+enum Destination {
+    Stdout(BufWriter<io::Stdout>),
+    File(AtomicFile),
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.dest.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.dest.flush()
+    }
+}
+
+impl Write for Destination {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Destination::Stdout(w) => w.write(buf),
+            Destination::File(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Destination::Stdout(w) => w.flush(),
+            Destination::File(w) => w.flush(),
+        }
+    }
+}
+
+/// Destination of a run, compressed or not
+enum Output {
+    Plain(Sink),
+    Gzip(GzEncoder<Sink>),
+    /// Converted from UTF-8 to `--output-encoding` before compression
+    Encoded(Box<EncodingWriter>),
+}
+
+impl Output {
+    /// Writes any compression trailer and returns the underlying sink
+    fn finish(self) -> io::Result<Sink> {
+        match self {
+            Output::Plain(sink) => Ok(sink),
+            Output::Gzip(encoder) => encoder.finish(),
+            Output::Encoded(writer) => writer.finish(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(w) => w.write(buf),
+            Output::Gzip(w) => w.write(buf),
+            Output::Encoded(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(w) => w.flush(),
+            Output::Gzip(w) => w.flush(),
+            Output::Encoded(w) => w.flush(),
+        }
+    }
+}
+
+/// Path of part `number` of an output split from `base`
+fn split_part_path(base: &Path, number: usize) -> PathBuf {
+    backup_path(base, &format!(".part{:04}", number))
+}
+
+/// Path of the manifest listing the parts split from `base`
+fn split_manifest_path(base: &Path) -> PathBuf {
+    backup_path(base, ".manifest.json")
+}
+
+/// One part of a split output, as listed in its manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+struct SplitPart {
+    /// File name, in the manifest's directory
+    path: String,
+    lines: u64,
+    /// Size before compression
+    bytes: u64,
+}
+
+/// Manifest written next to the parts of a split output
+#[derive(Debug, Serialize)]
+struct SplitManifest<'a> {
+    parts: &'a [SplitPart],
+}
+
+/// Writer that spreads an output over numbered part files
+///
+/// Bytes are held back until their line is complete, so a part always ends
+/// between lines. Each part is compressed and committed like any output
+/// once it is full. The manifest is written last, so a loader that waits
+/// for it never sees a partial set of parts.
+struct SplitWriter<'a> {
+    app: &'a App,
+    job: &'a Job,
+    base: PathBuf,
+    limit: SplitLimit,
+    compression: Compression,
+    /// Start of the line being written
+    line: Vec<u8>,
+    current: Option<(Output, SplitPart)>,
+    parts: Vec<SplitPart>,
+}
+
+impl<'a> SplitWriter<'a> {
+    fn new(app: &'a App, job: &'a Job, limit: SplitLimit) -> Result<Self> {
+        let base = job
+            .output
+            .as_deref()
+            .context("--split-lines and --split-bytes need an output file")?;
+        Ok(Self {
+            app,
+            job,
+            base: PathBuf::from(base),
+            limit,
+            compression: app
+                .config
+                .output_compression
+                .unwrap_or_else(|| Compression::detect(base)),
+            line: Vec::new(),
+            current: None,
+            parts: Vec::new(),
+        })
+    }
+
+    /// Writes the complete line in `self.line`, starting a part if needed
+    fn write_line(&mut self) -> Result<()> {
+        let len = self.line.len() as u64;
+        let full = match (&self.current, self.limit) {
+            (None, _) => true,
+            (Some((_, part)), SplitLimit::Lines(max)) => part.lines >= max,
+            (Some((_, part)), SplitLimit::Bytes(max)) => part.bytes > 0 && part.bytes + len > max,
+        };
+        if full {
+            self.close_part()?;
+            let path = split_part_path(&self.base, self.parts.len() + 1);
+            let output = self
+                .app
+                .open_output_as(Some(&path.to_string_lossy()), self.compression)?;
+            let part = SplitPart {
+                path: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                ..SplitPart::default()
+            };
+            self.current = Some((output, part));
+        }
+
+        let (output, part) = self.current.as_mut().expect("a part was just opened");
+        output.write_all(&self.line)?;
+        part.lines += 1;
+        part.bytes += len;
+        self.line.clear();
+        Ok(())
+    }
+
+    /// Commits the part being written, if any
+    fn close_part(&mut self) -> Result<()> {
+        let Some((output, part)) = self.current.take() else {
+            return Ok(());
+        };
+        let path = split_part_path(&self.base, self.parts.len() + 1);
+        let job = Job {
+            output: Some(path.to_string_lossy().into_owned()),
+            backup: None,
+            in_place: false,
+            ..self.job.clone()
+        };
+        self.app
+            .finish_output(&job, output)
+            .context(format!("Cannot write file: {}", path.display()))?;
+        self.parts.push(part);
+        Ok(())
+    }
+
+    /// Writes any unterminated last line, commits the last part and writes
+    /// the manifest
+    fn finish(mut self) -> Result<()> {
+        if !self.line.is_empty() {
+            self.write_line()?;
+        }
+        self.close_part()?;
+
+        let path = split_manifest_path(&self.base);
+        let context = || format!("Cannot write file: {}", path.display());
+        let mut file = AtomicFile::create(&path).with_context(context)?;
+        serde_json::to_writer_pretty(&mut file, &SplitManifest { parts: &self.parts })?;
+        writeln!(file)?;
+        file.commit(None, self.job.overwrite)
+            .with_context(context)?;
+        info!("Split output into {} parts", self.parts.len());
+        Ok(())
+    }
+}
+
+impl Write for SplitWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            self.line.extend_from_slice(&rest[..=end]);
+            // Keeps the whole chain in the message; `io::Error` hides the
+            // sources of an error it wraps
+            self.write_line()
+                .map_err(|e| io::Error::other(format!("{:#}", e)))?;
+            rest = &rest[end + 1..];
+        }
+        self.line.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    /// Lines are held until complete, so there is nothing to flush early
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Output file that only replaces its target once fully written
+///
+/// Data goes to a temporary file in the target's directory, which is
+/// renamed over the target by [`AtomicFile::commit`]. Dropping it without
+/// committing deletes the temporary file and leaves the target untouched,
+/// so a failed or interrupted run never truncates an existing output.
+struct AtomicFile {
+    target: PathBuf,
+    writer: BufWriter<tempfile::NamedTempFile>,
+}
+
+impl AtomicFile {
+    fn create(target: &Path) -> io::Result<Self> {
+        let dir = match target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut prefix = OsString::from(".");
+        prefix.push(target.file_name().unwrap_or_default());
+        prefix.push(".");
+
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(&prefix).suffix(".tmp");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            // Same mode as File::create, leaving the final say to the umask
+            builder.permissions(fs::Permissions::from_mode(0o666));
+        }
+        let file = builder.tempfile_in(dir)?;
+
+        // Replacing a file must not change who can read it
+        if let Ok(existing) = fs::metadata(target) {
+            file.as_file().set_permissions(existing.permissions())?;
+        }
+
+        Ok(Self {
+            target: target.to_path_buf(),
+            writer: BufWriter::with_capacity(STREAM_BUFFER_SIZE, file),
+        })
+    }
+
+    /// Returns true if everything written so far equals the target's contents
+    fn matches_target(&mut self) -> io::Result<bool> {
+        self.writer.flush()?;
+        let written = self.writer.get_ref().path();
+        let target = match fs::metadata(&self.target) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if target.len() != fs::metadata(written)?.len() {
+            return Ok(false);
+        }
+
+        let mut a = BufReader::with_capacity(STREAM_BUFFER_SIZE, File::open(written)?);
+        let mut b = BufReader::with_capacity(STREAM_BUFFER_SIZE, File::open(&self.target)?);
+        loop {
+            let chunk = a.fill_buf()?;
+            if chunk.is_empty() {
+                return Ok(true);
+            }
+            let len = chunk.len().min(b.fill_buf()?.len());
+            if len == 0 || chunk[..len] != b.buffer()[..len] {
+                return Ok(false);
+            }
+            a.consume(len);
+            b.consume(len);
+        }
+    }
+
+    /// Syncs the data and moves it into place, first copying the target to
+    /// `backup` if given
+    ///
+    /// Without `overwrite` the move fails with [`AppError::OutputExists`] if
+    /// the target exists, even if it was created after the file was opened.
+    fn commit(self, backup: Option<&Path>, overwrite: bool) -> Result<()> {
+        let file = self.writer.into_inner().map_err(|e| e.into_error())?;
+        file.as_file().sync_all()?;
+
+        if let Some(backup) = backup.filter(|_| self.target.exists()) {
+            fs::copy(&self.target, backup)
+                .context(format!("Cannot create backup: {}", backup.display()))?;
+            debug!("Backed up previous output");
+        }
+
+        let persisted = if overwrite {
+            file.persist(&self.target)
+        } else {
+            file.persist_noclobber(&self.target)
+        };
+        let result = match persisted {
+            Ok(_) => Ok(()),
+            Err(e) if e.error.kind() == io::ErrorKind::CrossesDevices => {
+                // e.g. the target is itself a mount point; fall back to a
+                // non-atomic copy rather than failing the run
+                warn!("Cannot rename across devices; copying output instead");
+                copy_into_place(e.file.path(), &self.target, overwrite)
+            }
+            Err(e) => Err(e.error),
+        };
+
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                Err(AppError::OutputExists(self.target.display().to_string()).into())
+            }
+            other => Ok(other?),
+        }
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Copies `source` over `target` and syncs it, refusing to replace an
+/// existing target unless `overwrite` is set
+fn copy_into_place(source: &Path, target: &Path, overwrite: bool) -> io::Result<()> {
+    let mut dest = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!overwrite)
+        .open(target)?;
+    io::copy(&mut File::open(source)?, &mut dest)?;
+    dest.sync_all()
+}
+
+/// Returns `<target><suffix>`, e.g. `notes.txt.bak`
+fn backup_path(target: &Path, suffix: &str) -> PathBuf {
+    let mut path = target.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Parses a byte count with an optional unit: decimal `KB`, `MB`, `GB`,
+/// `TB` or binary `KiB`, `MiB`, `GiB`, `TiB`, in any case
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => {
+            return Err(format!(
+                "unknown size unit {:?}; use e.g. 10MB or 1.5GiB",
+                unit
+            ))
+        }
+    };
+    let invalid = || format!("expected a size like 10MB or 1.5GiB, got {:?}", s);
+    // Whole numbers stay exact however large they are
+    if let Ok(whole) = number.parse::<u64>() {
+        return whole.checked_mul(multiplier).ok_or_else(invalid);
+    }
+    let bytes = number.parse::<f64>().map_err(|_| invalid())? * multiplier as f64;
+    if bytes.is_finite() && bytes < u64::MAX as f64 {
+        Ok(bytes.round() as u64)
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Parses a positive duration: a number with a unit of `ms`, `s`, `m` or
+/// `h`, seconds if none is given
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let seconds = match unit.trim() {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => {
+            return Err(format!(
+                "unknown duration unit {:?}; use ms, s, m or h",
+                unit
+            ))
+        }
+    };
+    let invalid = || format!("expected a duration like 30s or 2m, got {:?}", s);
+    let value = number.parse::<f64>().map_err(|_| invalid())?;
+    match Duration::try_from_secs_f64(value * seconds) {
+        Ok(duration) if !duration.is_zero() => Ok(duration),
+        Ok(_) => Err("the duration must be greater than zero".to_string()),
+        Err(_) => Err(invalid()),
+    }
+}
+
+/// Parses a SHA-256 digest given as 64 hex digits, normalized to lowercase
+fn parse_sha256(s: &str) -> Result<String, String> {
+    if s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(s.to_ascii_lowercase())
+    } else {
+        Err("expected a SHA-256 digest of 64 hex digits".to_string())
+    }
+}
+
+/// Hashes the file at `path` as stored, compressed or not, and fails with
+/// [`AppError::ChecksumMismatch`] unless it matches `expected`
+fn verify_digest(path: &str, expected: &str) -> Result<()> {
+    let actual = file_sha256(path)?;
+    if actual != expected {
+        return Err(AppError::ChecksumMismatch {
+            path: path.to_string(),
+            expected: expected.to_string(),
+            actual,
+        }
+        .into());
+    }
+    debug!("Input checksum verified");
+    Ok(())
+}
+
+/// SHA-256 of the file at `path` as stored, as lowercase hex
+fn file_sha256(path: &str) -> Result<String> {
+    let mut file = File::open(path).context(format!("Cannot read file: {}", path))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).context(format!("Cannot read file: {}", path))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Inputs completed by earlier runs with the same `--checkpoint` file
+///
+/// The file holds one [`CheckpointEntry`] per line as JSON, appended as
+/// each input succeeds, so a run killed part way has recorded everything
+/// it finished. An input counts as done only while its contents still hash
+/// to the recorded digest; for `--in-place` that is the output it was
+/// rewritten to.
+struct Checkpoint {
+    path: PathBuf,
+    /// Digest of each completed input, from the file as it was opened
+    done: HashMap<String, String>,
+    file: Mutex<File>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CheckpointEntry {
+    input: String,
+    sha256: String,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint at `path`, creating it if needed
+    ///
+    /// With `resume` the entries already there are loaded, otherwise the
+    /// file is truncated. A line that does not parse is an
+    /// [`AppError::CorruptCheckpoint`], so a wrong or damaged file never
+    /// quietly turns into a full rerun.
+    fn open(path: &Path, resume: bool) -> Result<Self> {
+        let context = || format!("Cannot open checkpoint: {}", path.display());
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(!resume)
+            .open(path)
+            .with_context(context)?;
+
+        let mut done = HashMap::new();
+        if resume {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes).with_context(context)?;
+            let corrupt = |line| AppError::CorruptCheckpoint {
+                path: path.display().to_string(),
+                line,
+            };
+            let text = String::from_utf8(bytes).map_err(|e| {
+                let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+                corrupt(valid.iter().filter(|&&b| b == b'\n').count() + 1)
+            })?;
+            // Only whole lines were committed; a tail without its newline
+            // is an entry the last run was killed while writing
+            let complete = text.rfind('\n').map_or(0, |end| end + 1);
+            for (index, line) in text[..complete].lines().enumerate() {
+                let entry: CheckpointEntry =
+                    serde_json::from_str(line).map_err(|_| corrupt(index + 1))?;
+                done.insert(entry.input, entry.sha256);
+            }
+            if complete < text.len() {
+                warn!("Dropping an incomplete last entry from the checkpoint");
+                file.set_len(complete as u64).with_context(context)?;
+            }
+            info!("Checkpoint lists {} completed inputs", done.len());
+        }
+        // Appends go to the end even though the file was opened for reading
+        file.seek(io::SeekFrom::End(0)).with_context(context)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            done,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// True if `input` was completed and has not changed since
+    fn is_done(&self, input: &str) -> bool {
+        let Some(recorded) = self.done.get(input) else {
+            return false;
+        };
+        match file_sha256(input) {
+            Ok(digest) if &digest == recorded => true,
+            Ok(_) => {
+                info!("Input changed since it was checkpointed; processing it again");
+                false
+            }
+            // Processing it reports the problem properly
+            Err(_) => false,
+        }
+    }
+
+    /// Records `input`, as it is now, as completed
+    fn record(&self, input: &str) -> Result<()> {
+        let entry = CheckpointEntry {
+            input: input.to_string(),
+            sha256: file_sha256(input)?,
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        // One write per entry, so a kill leaves at most one partial line
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(line.as_bytes())
+            .context(format!("Cannot update checkpoint: {}", self.path.display()))
+    }
+}
+
+/// Writes `<target>.sha256` in `sha256sum` format
+///
+/// The file name is recorded without its directory, so `sha256sum -c` works
+/// from the directory holding both files.
+fn write_checksum_file(target: &Path, digest: &str) -> Result<()> {
+    let path = backup_path(target, ".sha256");
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let mut file =
+        AtomicFile::create(&path).context(format!("Cannot write file: {}", path.display()))?;
+    writeln!(file, "{}  {}", digest, name)?;
+    file.commit(None, true)
+        .context(format!("Cannot write file: {}", path.display()))?;
+    debug!("Wrote checksum file");
+    Ok(())
+}
+
+/// Adds ANSI colors to a unified diff: headers bold, hunks cyan, removed
+/// lines red, added lines green
+fn colorize_diff(diff: &str) -> String {
+    diff.split_inclusive('\n')
+        .map(|line| {
+            let color = if line.starts_with("---") || line.starts_with("+++") {
+                "\x1b[1m"
+            } else if line.starts_with("@@") {
+                "\x1b[36m"
+            } else if line.starts_with('-') {
+                "\x1b[31m"
+            } else if line.starts_with('+') {
+                "\x1b[32m"
+            } else {
+                return line.to_string();
+            };
+            let (content, ending) = split_line_ending(line);
+            format!("{}{}\x1b[0m{}", color, content, ending)
+        })
+        .collect()
+}
+
+/// Treats a closed output pipe (e.g. piping into `head`) as a clean exit
+fn ignore_broken_pipe<T: Default>(result: Result<T>) -> Result<T> {
+    match result {
+        Err(e) if is_broken_pipe(&e) => {
+            debug!("Output closed early (broken pipe), stopping");
+            Ok(T::default())
+        }
+        other => other,
+    }
+}
+
+/// Returns true if any cause in the error chain is `ErrorKind::BrokenPipe`
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
+
+/// Returns the first `PREVIEW_CHARS` characters of the input
+fn preview(input: &str) -> &str {
+    match input.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => &input[..end],
+        None => input,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::{Arc, Mutex};
+    use tempfile::NamedTempFile;
+
+    /// Log sink shared between a test and its tracing subscriber
+    ///
+    /// Also used by the tests of the server modules.
+    #[derive(Clone, Default)]
+    pub(crate) struct LogCapture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for LogCapture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogCapture {
+        /// Subscriber writing all tracing output into this sink
+        pub(crate) fn subscriber(&self) -> impl tracing::Subscriber + Send + Sync {
+            let sink = self.clone();
+            tracing_subscriber::fmt()
+                .with_max_level(tracing::Level::DEBUG)
+                .with_ansi(false)
+                .with_writer(move || sink.clone())
+                .finish()
+        }
+
+        /// [`LogCapture::subscriber`] writing one JSON object per event
+        pub(crate) fn json_subscriber(&self) -> impl tracing::Subscriber + Send + Sync {
+            let sink = self.clone();
+            tracing_subscriber::fmt()
+                .json()
+                .with_max_level(tracing::Level::DEBUG)
+                .with_writer(move || sink.clone())
+                .finish()
+        }
+
+        /// Runs `f` with all tracing output captured into this sink
+        pub(crate) fn capture<T>(&self, f: impl FnOnce() -> T) -> T {
+            tracing::subscriber::with_default(self.subscriber(), f)
+        }
+
+        pub(crate) fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_process_empty_input() {
+        let config = Config {
+            inputs: vec!["test.txt".to_string()],
+            output: None,
+            out_dir: None,
+            backup: None,
+            in_place: None,
+            jobs: 1,
+            config_paths: vec!["config.toml".to_string()],
+            mode: Mode::Upper,
+            redact: false,
+            ..Config::default()
+        };
+        let app = App::new(config);
+
+        let result = app.process("");
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "");
+    }
+
+    #[test]
+    fn test_process_uppercase() {
+        let config = Config {
+            inputs: vec!["test.txt".to_string()],
+            output: None,
+            out_dir: None,
+            backup: None,
+            in_place: None,
+            jobs: 1,
+            config_paths: vec!["config.toml".to_string()],
+            mode: Mode::Upper,
+            redact: false,
+            ..Config::default()
+        };
+        let app = App::new(config);
+
+        let result = app.process("hello world");
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "HELLO WORLD");
+    }
+
+    #[test]
+    fn test_read_write_integration() -> Result<()> {
+        // Create temporary input file
+        let mut input_file = NamedTempFile::new()?;
+        writeln!(input_file, "test content")?;
+
+        // Create temporary output file
+        let output_file = NamedTempFile::new()?;
+
+        let config = Config {
+            inputs: vec![input_file.path().to_string_lossy().to_string()],
+            output: Some(output_file.path().to_string_lossy().to_string()),
+            // NamedTempFile has already created the output
+            force: true,
+            out_dir: None,
+            backup: None,
+            in_place: None,
+            jobs: 1,
+            config_paths: vec!["config.toml".to_string()],
+            mode: Mode::Upper,
+            redact: false,
+            ..Config::default()
+        };
+
+        let app = App::new(config);
+        let result = app.run();
+
+        assert!(result.is_ok());
+
+        // Verify output
+        let output = std::fs::read_to_string(output_file.path())?;
+        assert_eq!(output, "TEST CONTENT\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_dirs_makes_missing_parents() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 1)?;
+        let output = dir.path().join("nested/deeper/out.txt");
+
+        let app = App::new(Config {
+            inputs,
+            output: Some(output.to_string_lossy().into_owned()),
+            create_dirs: true,
+            mode: Mode::Upper,
+            ..Config::default()
+        });
+        app.run()?;
+
+        assert_eq!(std::fs::read_to_string(&output)?, "FILE 0\nLINE TWO OF 0\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_output_dir_is_reported() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 1)?;
+        let missing = dir.path().join("missing");
+
+        let app = App::new(Config {
+            inputs,
+            output: Some(missing.join("out.txt").to_string_lossy().into_owned()),
+            mode: Mode::Upper,
+            ..Config::default()
+        });
+        let err = app.run().unwrap_err();
+
+        assert_eq!(
+            err.chain()
+                .find_map(|cause| cause.downcast_ref::<AppError>())
+                .map(ToString::to_string),
+            Some(format!(
+                "output directory does not exist: {} (use --create-dirs)",
+                missing.display()
+            ))
+        );
+        assert!(!missing.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_redacted_formatting() {
+        let secret = Redacted("/home/user/secret.txt");
+        assert_eq!(format!("{}", secret), "<redacted>");
+        assert_eq!(format!("{:?}", secret), "<redacted>");
+    }
+
+    #[test]
+    fn test_preview_truncates_on_char_boundary() {
+        let input = "é".repeat(PREVIEW_CHARS + 5);
+        assert_eq!(preview(&input).chars().count(), PREVIEW_CHARS);
+        assert_eq!(preview("short"), "short");
+    }
+
+    #[test]
+    fn test_redact_hides_paths_in_logs() -> Result<()> {
+        let mut input_file = NamedTempFile::new()?;
+        writeln!(input_file, "sensitive content")?;
+        let output_file = NamedTempFile::new()?;
+        let input_path = input_file.path().to_string_lossy().to_string();
+
+        let config = Config {
+            inputs: vec![input_path.clone()],
+            output: Some(output_file.path().to_string_lossy().to_string()),
+            // NamedTempFile has already created the output
+            force: true,
+            redact: true,
+            ..Config::default()
+        };
+        let app = App::new(config);
+
+        let logs = LogCapture::default();
+        logs.capture(|| app.run())?;
+
+        let contents = logs.contents();
+        assert!(contents.contains("<redacted>"));
+        assert!(!contents.contains(&input_path));
+        assert!(!contents.contains("sensitive content"));
+
+        // The error of a failing input names the file in its causes
+        let dir = tempfile::TempDir::new()?;
+        let missing = dir.path().join("secret-missing.txt");
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+        let app = App::new(Config {
+            inputs: vec![input_path, missing.to_string_lossy().into_owned()],
+            out_dir: Some(out_dir.to_string_lossy().into_owned()),
+            redact: true,
+            ..Config::default()
+        });
+
+        let logs = LogCapture::default();
+        assert!(logs.capture(|| app.run()).is_err());
+
+        let contents = logs.contents();
+        assert!(
+            contents.contains("input file not found: <redacted>"),
+            "{}",
+            contents
+        );
+        assert!(!contents.contains("secret-missing"), "{}", contents);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_paths_logged_without_redact() -> Result<()> {
+        let mut input_file = NamedTempFile::new()?;
+        writeln!(input_file, "plain content")?;
+        let output_file = NamedTempFile::new()?;
+        let input_path = input_file.path().to_string_lossy().to_string();
+
+        let config = Config {
+            inputs: vec![input_path.clone()],
+            output: Some(output_file.path().to_string_lossy().to_string()),
+            // NamedTempFile has already created the output
+            force: true,
+            ..Config::default()
+        };
+        let app = App::new(config);
+
+        let logs = LogCapture::default();
+        logs.capture(|| app.run())?;
+
+        let contents = logs.contents();
+        assert!(contents.contains(&input_path));
+        assert!(!contents.contains("<redacted>"));
+
+        Ok(())
+    }
+
+    /// Reader that yields `total` bytes of repeated lines without storing them
+    struct GeneratedInput {
+        line: &'static [u8],
+        remaining: usize,
+        offset: usize,
+    }
+
+    impl std::io::Read for GeneratedInput {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let mut written = 0;
+            while written < buf.len() && self.remaining > 0 {
+                let chunk = &self.line[self.offset..];
+                let n = chunk.len().min(buf.len() - written).min(self.remaining);
+                buf[written..written + n].copy_from_slice(&chunk[..n]);
+                written += n;
+                self.remaining -= n;
+                self.offset = (self.offset + n) % self.line.len();
+            }
+            Ok(written)
+        }
+    }
+
+    /// Writer that counts bytes instead of storing them
+    #[derive(Default)]
+    struct CountingSink(u64);
+
+    impl Write for CountingSink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len() as u64;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn app_with_mode(mode: Mode) -> App {
+        App::new(Config {
+            mode,
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn test_streaming_large_input_stays_bounded() -> Result<()> {
+        const TOTAL: usize = 100 * 1024 * 1024;
+        let input = GeneratedInput {
+            line: b"the quick brown fox jumps over the lazy dog\n",
+            remaining: TOTAL,
+            offset: 0,
+        };
+        let mut sink = CountingSink::default();
+
+        let app = app_with_mode(Mode::Upper);
+        let reader = BufReader::with_capacity(STREAM_BUFFER_SIZE, input);
+        let stats = app.process_streaming(reader, &mut sink)?;
+
+        assert_eq!(stats.bytes_in, TOTAL as u64);
+        assert_eq!(sink.0, TOTAL as u64);
+        assert!(
+            stats.peak_buffer <= STREAM_BUFFER_SIZE,
+            "line buffer grew to {} bytes",
+            stats.peak_buffer
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaming_matches_buffered() -> Result<()> {
+        let inputs = [
+            "hello world\n",
+            "no trailing newline",
+            "  padded  \n\nblank line above\n",
+            "windows\r\nline endings\r\n",
+            "héllo wörld ß\nzweite Zeile",
+            "",
+        ];
+
+        for mode in [Mode::Upper, Mode::Lower, Mode::Reverse, Mode::TrimLines] {
+            let app = app_with_mode(mode);
+            for input in inputs {
+                let mut streamed = Vec::new();
+                app.process_streaming(input.as_bytes(), &mut streamed)?;
+                let buffered = app.process(input)?;
+                assert_eq!(
+                    streamed,
+                    buffered.as_bytes(),
+                    "mode {:?} diverged on {:?}",
+                    mode,
+                    input
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_requires_whole_input() -> Result<()> {
+        assert!(Mode::Sort.requires_whole_input());
+        assert!(!Mode::Upper.requires_whole_input());
+
+        let mut input_file = NamedTempFile::new()?;
+        write!(input_file, "pear\napple\nfig\n")?;
+        let output_file = NamedTempFile::new()?;
+
+        let app = App::new(Config {
+            inputs: vec![input_file.path().to_string_lossy().to_string()],
+            output: Some(output_file.path().to_string_lossy().to_string()),
+            // NamedTempFile has already created the output
+            force: true,
+            mode: Mode::Sort,
+            ..Config::default()
+        });
+        let logs = LogCapture::default();
+        logs.capture(|| app.run())?;
+
+        assert!(logs.contents().contains("buffering it in memory"));
+        assert_eq!(
+            std::fs::read_to_string(output_file.path())?,
+            "apple\nfig\npear\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_broken_pipe_terminates_cleanly() -> Result<()> {
+        let (reader, writer) = std::io::pipe()?;
+        drop(reader);
+
+        let app = app_with_mode(Mode::Upper);
+        let result = app
+            .process_streaming("line one\nline two\n".as_bytes(), writer)
+            .map(|_| ())
+            .context("Failed to write output");
+
+        assert!(result.is_err());
+        assert!(ignore_broken_pipe(result).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_other_write_errors_still_fail() {
+        let err = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        let result: Result<()> = Err(anyhow::Error::from(err).context("Failed to write output"));
+        assert!(ignore_broken_pipe(result).is_err());
+    }
+
+    /// Writes `count` numbered input files and returns their paths
+    fn write_inputs(dir: &Path, count: usize) -> Result<Vec<String>> {
+        (0..count)
+            .map(|i| {
+                let path = dir.join(format!("input-{:03}.txt", i));
+                std::fs::write(&path, format!("file {}\nline two of {}\n", i, i))?;
+                Ok(path.to_string_lossy().into_owned())
+            })
+            .collect()
+    }
+
+    fn batch_app(inputs: Vec<String>, out_dir: &Path, jobs: usize) -> App {
+        App::new(Config {
+            inputs,
+            out_dir: Some(out_dir.to_string_lossy().into_owned()),
+            jobs,
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 50)?;
+        let sequential_dir = dir.path().join("sequential");
+        let parallel_dir = dir.path().join("parallel");
+        std::fs::create_dir_all(&sequential_dir)?;
+        std::fs::create_dir_all(&parallel_dir)?;
+
+        let sequential = batch_app(inputs.clone(), &sequential_dir, 1).process_all()?;
+        let parallel = batch_app(inputs.clone(), &parallel_dir, 8).process_all()?;
+
+        assert_eq!(sequential.succeeded(), 50);
+        assert_eq!(parallel.succeeded(), 50);
+        for input in &inputs {
+            let name = Path::new(input).file_name().unwrap();
+            assert_eq!(
+                std::fs::read(sequential_dir.join(name))?,
+                std::fs::read(parallel_dir.join(name))?
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_pool_is_sized_by_jobs() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 8)?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+
+        let logs = LogCapture::default();
+        let summary = logs.capture(|| batch_app(inputs, &out_dir, 2).process_all())?;
+
+        assert_eq!(summary.succeeded(), 8);
+        assert!(logs.contents().contains("threads=2"), "{}", logs.contents());
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary_choice() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 2)?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+
+        let cases = [
+            (
+                inputs.clone(),
+                SummaryChoice::Auto,
+                Some("Processed 2 files"),
+            ),
+            (inputs.clone(), SummaryChoice::Never, None),
+            (inputs[..1].to_vec(), SummaryChoice::Auto, None),
+            (
+                inputs[..1].to_vec(),
+                SummaryChoice::Always,
+                Some("Processed 1 file:"),
+            ),
+        ];
+        for (inputs, choice, expected) in cases {
+            let app = App::new(Config {
+                summary: choice,
+                force: true,
+                ..batch_app(inputs, &out_dir, 1).config
+            });
+            let logs = LogCapture::default();
+            logs.capture(|| app.run())?;
+            let contents = logs.contents();
+            match expected {
+                Some(line) => assert!(contents.contains(line), "{:?}: {}", choice, contents),
+                None => assert!(
+                    !contents.contains(" succeeded, "),
+                    "{:?}: {}",
+                    choice,
+                    contents
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_failure_does_not_abort_batch() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let mut inputs = write_inputs(dir.path(), 9)?;
+        let bad = dir.path().join("input-bad.txt");
+        std::fs::write(&bad, [0xff, 0xfe, b'\n'])?;
+        inputs.insert(3, bad.to_string_lossy().into_owned());
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+
+        let app = batch_app(inputs.clone(), &out_dir, 4);
+        let summary = app.process_all()?;
+
+        assert_eq!(summary.results.len(), 10);
+        assert_eq!(summary.succeeded(), 9);
+        assert_eq!(summary.failed(), 1);
+        assert!(out_dir.join("input-008.txt").exists());
+
+        let mut sorted = inputs;
+        sorted.sort();
+        let reported: Vec<_> = summary.results.iter().map(|r| r.input.clone()).collect();
+        assert_eq!(reported, sorted);
+
+        assert!(app.run().is_err());
+        Ok(())
+    }
+
+    fn template_app(inputs: Vec<String>, template: &str) -> App {
+        App::new(Config {
+            inputs,
+            output_template: Some(template.parse().unwrap()),
+            jobs: 1,
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn test_output_template_placeholders() -> Result<()> {
+        let expand = |template: &str, input: &str| {
+            OutputTemplate::from_str(template).unwrap().expand(
+                Path::new(input),
+                "upper",
+                "2026-01-31",
+            )
+        };
+
+        assert_eq!(expand("{dir}/out.txt", "logs/app.log")?, "logs/out.txt");
+        assert_eq!(expand("{dir}/out.txt", "app.log")?, "./out.txt");
+        assert_eq!(expand("{stem}.txt", "logs/app.tar.gz")?, "app.tar.txt");
+        assert_eq!(expand("out.{ext}", "logs/app.log")?, "out.log");
+        assert_eq!(expand("out.{ext}", "logs/README")?, "out.");
+        assert_eq!(expand("{transform}.txt", "app.log")?, "upper.txt");
+        assert_eq!(expand("{date}.txt", "app.log")?, "2026-01-31.txt");
+        assert_eq!(expand("out/{hash8}.txt", "app.log")?, "out/{hash8}.txt");
+        assert_eq!(
+            expand("{dir}/{stem}.{transform}.{ext}", "logs/app.log")?,
+            "logs/app.upper.log"
+        );
+        assert!(expand("{stem}", "..").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_template_rejects_unknown_placeholder() {
+        let err = OutputTemplate::from_str("{dir}/{steem}.txt").unwrap_err();
+        assert!(err.contains("unknown placeholder {steem}"), "{}", err);
+        assert!(err.contains("{stem}"), "{}", err);
+
+        let err = Args::try_parse_from(["my_app", "-i", "a.txt", "--output-template", "{nme}"])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("{nme}"), "{}", err);
+
+        assert!(OutputTemplate::from_str("{stem").is_err());
+        assert!(OutputTemplate::from_str("{hash8}/{stem}.txt").is_err());
+        assert!(OutputTemplate::from_str("{hash8}{dir}.txt").is_err());
+        assert!(OutputTemplate::from_str("{stem}.{hash8}.txt").is_ok());
+    }
+
+    #[test]
+    fn test_output_template_collisions_fail_before_processing() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+        let mut inputs = Vec::new();
+        for sub in ["a", "b", "c"] {
+            std::fs::create_dir_all(dir.path().join(sub))?;
+            let name = if sub == "c" { "other.txt" } else { "same.txt" };
+            let input = dir.path().join(sub).join(name);
+            std::fs::write(&input, "text\n")?;
+            inputs.push(input.to_string_lossy().into_owned());
+        }
+
+        let template = format!("{}/{{stem}}.txt", out_dir.display());
+        let err = template_app(inputs.clone(), &template).run().unwrap_err();
+
+        match err.downcast_ref::<AppError>() {
+            Some(AppError::OutputCollision {
+                output,
+                inputs: colliding,
+            }) => {
+                assert_eq!(output, &out_dir.join("same.txt").display().to_string());
+                assert_eq!(colliding, &inputs[..2]);
+            }
+            other => panic!("expected OutputCollision, got {:?}", other),
+        }
+        assert_eq!(std::fs::read_dir(&out_dir)?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash8_names_outputs_by_content() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+        let mut inputs = Vec::new();
+        for (name, text) in [
+            ("a.txt", "same\n"),
+            ("b.txt", "same\n"),
+            ("c.txt", "other\n"),
+        ] {
+            let input = dir.path().join(name);
+            std::fs::write(&input, text)?;
+            inputs.push(input.to_string_lossy().into_owned());
+        }
+        let template = format!("{}/{{stem}}-{{hash8}}.txt", out_dir.display());
+        let hash8 = |text: &str| format!("{:x}", Sha256::digest(text))[..8].to_string();
+
+        for _ in 0..2 {
+            let app = App::new(Config {
+                force: true,
+                ..template_app(inputs.clone(), &template).config
+            });
+            let summary = app.process_all()?;
+            assert_eq!(summary.succeeded(), 3);
+
+            let same = hash8("SAME\n");
+            for (result, expected) in summary.results.iter().zip([
+                format!("a-{}.txt", same),
+                format!("b-{}.txt", same),
+                format!("c-{}.txt", hash8("OTHER\n")),
+            ]) {
+                let output = out_dir.join(&expected);
+                assert_eq!(result.output.as_deref(), Some(&*output.to_string_lossy()));
+                assert!(output.exists(), "{}", expected);
+            }
+            assert_eq!(std::fs::read_dir(&out_dir)?.count(), 3);
+        }
+
+        // Identical outputs named by their digest alone land on one file
+        let template = format!("{}/{{hash8}}.txt", out_dir.display());
+        let summary = template_app(inputs[..2].to_vec(), &template).process_all()?;
+        assert_eq!(summary.succeeded(), 1);
+        let err = summary.results[1].outcome.as_ref().unwrap_err();
+        match err.downcast_ref::<AppError>() {
+            Some(AppError::OutputCollision {
+                inputs: colliding, ..
+            }) => assert_eq!(colliding, &inputs[..2]),
+            _ => panic!("expected OutputCollision, got {:#}", err),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_interrupt_aborts_remaining_files() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 4)?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+
+        let app = batch_app(inputs, &out_dir, 2);
+        app.interrupt_flag().store(true, Ordering::Relaxed);
+        let summary = app.process_all()?;
+
+        assert_eq!(summary.aborted(), 4);
+        assert_eq!(summary.failed(), 0);
+        assert!(summary
+            .records(Mode::Upper)
+            .iter()
+            .all(|record| record.status == FileStatus::Aborted));
+        assert_eq!(std::fs::read_dir(&out_dir)?.count(), 0);
+
+        let err = app.run().unwrap_err();
+        assert_eq!(exit_code(&err), 130);
+        assert_eq!(
+            err.to_string(),
+            "interrupted: 0 file(s) completed, 4 aborted"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_written_after_interrupt() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 3)?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+        let report_path = out_dir.join("report.json");
+
+        let mut app = batch_app(inputs, &out_dir, 1);
+        app.config.report = Some(report_path.to_string_lossy().into_owned());
+        app.interrupt_flag().store(true, Ordering::Relaxed);
+        assert_eq!(exit_code(&app.run().unwrap_err()), 130);
+
+        let report: serde_json::Value = serde_json::from_slice(&std::fs::read(&report_path)?)?;
+        assert_eq!(report["summary"]["aborted"], 3);
+        assert_eq!(report["summary"]["exit_code"], 130);
+        let files = report["files"].as_array().unwrap();
+        assert!(files.iter().all(|file| file["status"] == "aborted"));
+        // Only the report itself, no temp files
+        assert_eq!(std::fs::read_dir(&out_dir)?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_size_units() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10MB"), Ok(10_000_000));
+        assert_eq!(parse_size("10 mb"), Ok(10_000_000));
+        assert_eq!(parse_size("1.5GiB"), Ok(1_610_612_736));
+        assert_eq!(parse_size("64KiB"), Ok(65_536));
+        assert!(parse_size("10XB").is_err());
+        assert!(parse_size("-1MB").is_err());
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("99999999999TB").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration_units() {
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("soon").is_err());
+    }
+
+    /// [`FileSource`] counting the reads that reach it
+    #[derive(Default)]
+    struct CountingSource {
+        reads: AtomicUsize,
+    }
+
+    impl Source for CountingSource {
+        fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+            FileSource.modified(path)
+        }
+
+        fn read(&self, path: &Path) -> io::Result<Arc<[u8]>> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            FileSource.read(path)
+        }
+    }
+
+    #[test]
+    fn test_caching_source_rereads_modified_files() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("watched.txt");
+        std::fs::write(&path, "first")?;
+        let source = CachingSource::new(CountingSource::default());
+        let reads = || source.inner.reads.load(Ordering::Relaxed);
+
+        assert_eq!(&*source.read(&path)?, b"first");
+        assert_eq!(&*source.read(&path)?, b"first");
+        assert_eq!(reads(), 1);
+
+        // An explicit time, since the rewrite may land in the same tick
+        std::fs::write(&path, "second")?;
+        let later = FileSource.modified(&path)? + Duration::from_secs(1);
+        File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(later)?;
+        assert_eq!(&*source.read(&path)?, b"second");
+        assert_eq!(reads(), 2);
+
+        // A change event forces a re-read even with the time unchanged
+        source.invalidate(&path);
+        assert_eq!(&*source.read(&path)?, b"second");
+        assert_eq!(reads(), 3);
+        assert_eq!(&*source.read(&path)?, b"second");
+        assert_eq!(reads(), 3);
+
+        std::fs::remove_file(&path)?;
+        assert!(source.read(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_transient() {
+        for kind in [
+            io::ErrorKind::Interrupted,
+            io::ErrorKind::WouldBlock,
+            io::ErrorKind::TimedOut,
+        ] {
+            assert!(is_transient(kind), "{:?}", kind);
+        }
+        for kind in [
+            io::ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied,
+            io::ErrorKind::InvalidData,
+            io::ErrorKind::UnexpectedEof,
+        ] {
+            assert!(!is_transient(kind), "{:?}", kind);
+        }
+    }
+
+    /// Reader and writer that fails with `kind` for its first `failures`
+    /// calls, then reads from `data` or accepts everything
+    struct Flaky {
+        failures: usize,
+        kind: io::ErrorKind,
+        attempts: usize,
+        data: &'static [u8],
+        written: Vec<u8>,
+    }
+
+    impl Flaky {
+        fn new(failures: usize, kind: io::ErrorKind) -> Self {
+            Flaky {
+                failures,
+                kind,
+                attempts: 0,
+                data: b"payload",
+                written: Vec::new(),
+            }
+        }
+
+        fn attempt(&mut self) -> io::Result<()> {
+            self.attempts += 1;
+            if self.attempts <= self.failures {
+                return Err(io::Error::new(self.kind, "scripted failure"));
+            }
+            Ok(())
+        }
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.attempt()?;
+            self.data.read(buf)
+        }
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.attempt()?;
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn retrying(flaky: Flaky, retries: u32) -> Retrying<Flaky> {
+        Retrying {
+            inner: flaky,
+            policy: RetryPolicy {
+                retries,
+                delay: Duration::ZERO,
+            },
+        }
+    }
+
+    #[test]
+    fn test_read_succeeds_after_transient_failures() {
+        let mut reader = retrying(Flaky::new(2, io::ErrorKind::Interrupted), 3);
+        let mut buf = [0; 16];
+        let read = reader.read(&mut buf).unwrap();
+        assert_eq!(&buf[..read], b"payload");
+        assert_eq!(reader.inner.attempts, 3);
+    }
+
+    #[test]
+    fn test_write_fails_once_retries_run_out() {
+        let mut writer = retrying(Flaky::new(5, io::ErrorKind::TimedOut), 2);
+        let err = writer.write(b"data").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(writer.inner.attempts, 3);
+        assert!(writer.inner.written.is_empty());
+
+        let mut writer = retrying(Flaky::new(2, io::ErrorKind::WouldBlock), 2);
+        writer.write_all(b"data").unwrap();
+        assert_eq!(writer.inner.written, b"data");
+        assert_eq!(writer.inner.attempts, 3);
+    }
+
+    #[test]
+    fn test_permanent_errors_are_not_retried() {
+        for kind in [io::ErrorKind::NotFound, io::ErrorKind::PermissionDenied] {
+            let mut reader = retrying(Flaky::new(1, kind), 5);
+            let err = reader.read(&mut [0; 16]).unwrap_err();
+            assert_eq!(err.kind(), kind);
+            assert_eq!(reader.inner.attempts, 1);
+
+            let mut writer = retrying(Flaky::new(1, kind), 5);
+            assert_eq!(writer.write(b"data").unwrap_err().kind(), kind);
+            assert_eq!(writer.inner.attempts, 1);
+        }
+    }
+
+    #[test]
+    fn test_io_retry_flags() {
+        let args = Args::try_parse_from(["my_app", "-i", "in.txt"]).unwrap();
+        let policy = Config::from_args(args).io_retry;
+        assert_eq!(policy.retries, 0);
+        assert_eq!(policy.delay, Duration::from_millis(100));
+
+        let args = Args::try_parse_from([
+            "my_app",
+            "-i",
+            "in.txt",
+            "--io-retries",
+            "3",
+            "--io-retry-delay",
+            "250ms",
+        ])
+        .unwrap();
+        assert_eq!(
+            Config::from_args(args).io_retry,
+            RetryPolicy {
+                retries: 3,
+                delay: Duration::from_millis(250)
+            }
+        );
+    }
+
+    #[test]
+    fn test_long_line_fails_with_offset() {
+        let mut app = app_with_mode(Mode::Upper);
+        app.config.max_line_length = Some(8);
+
+        // The terminator does not count towards the limit
+        let mut output = Vec::new();
+        app.process_streaming("fits\neightch\n".as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(output, b"FITS\nEIGHTCH\n");
+
+        let err = app
+            .process_streaming("short\nfar too long\nok\n".as_bytes(), io::sink())
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(AppError::LineTooLong {
+                offset: 6,
+                limit: 8
+            })
+        ));
+        assert_eq!(
+            err.to_string(),
+            "line starting at byte 6 is over the --max-line-length limit of 8 bytes"
+        );
+        assert_eq!(exit_code(&err), 1);
+    }
+
+    #[test]
+    fn test_max_line_length_defaults_to_1mib() {
+        let args = Args::try_parse_from(["my_app", "-i", "in.txt"]).unwrap();
+        assert_eq!(Config::from_args(args).max_line_length, Some(1024 * 1024));
+    }
+
+    #[test]
+    fn test_oversized_input_is_skipped_or_failed() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 3)?;
+        std::fs::write(&inputs[1], "x".repeat(100))?;
+
+        for (strict, code) in [(false, 2), (true, 1)] {
+            let out_dir = dir.path().join(format!("out-{}", strict));
+            std::fs::create_dir_all(&out_dir)?;
+            let mut app = batch_app(inputs.clone(), &out_dir, 2);
+            app.config.max_file_size = Some(50);
+            app.config.strict_limits = strict;
+
+            let summary = app.process_all()?;
+            assert_eq!(summary.succeeded(), 2);
+            assert_eq!(summary.skipped(), usize::from(!strict));
+            assert_eq!(summary.failed(), usize::from(strict));
+            let record = summary.results[1].record(Mode::Upper);
+            assert_eq!(record.code, Some(code));
+            assert!(record.error.unwrap().contains("input is 100 bytes"));
+
+            let err = app.run().unwrap_err();
+            assert_eq!(exit_code(&err), code, "{:#}", err);
+            assert_eq!(std::fs::read_dir(&out_dir)?.count(), 2);
+            std::fs::remove_dir_all(&out_dir)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_slow_transform_times_out() {
+        /// Hands out one line per read, taking `delay` for each
+        struct SlowLines {
+            delay: Duration,
+        }
+
+        impl Read for SlowLines {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                std::thread::sleep(self.delay);
+                let line = b"slow line\n";
+                buf[..line.len()].copy_from_slice(line);
+                Ok(line.len())
+            }
+        }
+
+        let limit = Duration::from_millis(50);
+        let app = App::new(Config {
+            timeout: Some(limit),
+            ..Config::default()
+        });
+        let reader = BufReader::new(SlowLines {
+            delay: Duration::from_millis(10),
+        });
+        let started = Instant::now();
+        let err = app
+            .stream_lines(reader, io::sink(), Some(started + limit))
+            .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<AppError>(),
+            Some(AppError::Timeout(d)) if *d == limit
+        ));
+        assert_eq!(exit_code(&err), 124);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    fn checkpoint_app(inputs: Vec<String>, out_dir: &Path, checkpoint: &Path) -> App {
+        let mut app = batch_app(inputs, out_dir, 2);
+        app.config.checkpoint = Some(checkpoint.to_string_lossy().into_owned());
+        app
+    }
+
+    #[test]
+    fn test_checkpoint_reprocesses_changed_input() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 3)?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+        let checkpoint = dir.path().join("run.checkpoint");
+        checkpoint_app(inputs.clone(), &out_dir, &checkpoint).run()?;
+
+        std::fs::write(&inputs[1], "edited\n")?;
+        // The kill came while the last entry was being written
+        let mut file = OpenOptions::new().append(true).open(&checkpoint)?;
+        file.write_all(b"{\"input\":\"inp")?;
+        drop(file);
+
+        let mut app = checkpoint_app(inputs.clone(), &out_dir, &checkpoint);
+        app.config.force = true;
+        let summary = app.process_all()?;
+        let resumed: Vec<bool> = summary.results.iter().map(|r| r.resumed).collect();
+        assert_eq!(resumed, [true, false, true]);
+        assert_eq!(summary.succeeded(), 1);
+        assert_eq!(
+            std::fs::read_to_string(out_dir.join("input-001.txt"))?,
+            "EDITED\n"
+        );
+
+        // The torn entry is gone and the edited input recorded again
+        let text = std::fs::read_to_string(&checkpoint)?;
+        assert_eq!(text.lines().count(), 4);
+        assert!(text.ends_with('\n'));
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_checkpoint_is_reported() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 2)?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+        let checkpoint = dir.path().join("run.checkpoint");
+        std::fs::write(
+            &checkpoint,
+            "{\"input\":\"a\",\"sha256\":\"00\"}\nnot json\n",
+        )?;
+
+        let err = checkpoint_app(inputs.clone(), &out_dir, &checkpoint)
+            .run()
+            .unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<AppError>(),
+                Some(AppError::CorruptCheckpoint { line: 2, .. })
+            ),
+            "{:#}",
+            err
+        );
+        assert_eq!(std::fs::read_dir(&out_dir)?.count(), 0);
+
+        let mut app = checkpoint_app(inputs, &out_dir, &checkpoint);
+        app.config.no_resume = true;
+        app.run()?;
+        let text = std::fs::read_to_string(&checkpoint)?;
+        assert_eq!(text.lines().count(), 2);
+        assert!(!text.contains("not json"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_worker_logs_carry_file_span() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 8)?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+
+        let app = batch_app(inputs.clone(), &out_dir, 4);
+        let logs = LogCapture::default();
+        logs.capture(|| app.process_all())?;
+
+        let contents = logs.contents();
+        for input in &inputs {
+            let attributed = contents.lines().any(|line| {
+                line.contains(&format!("file={}", input)) && line.contains("Reading from")
+            });
+            assert!(attributed, "no attributed log line for {}", input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_logs_carry_run_id_and_file() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let mut inputs = write_inputs(dir.path(), 4)?;
+        inputs.push(
+            dir.path()
+                .join("missing.txt")
+                .to_string_lossy()
+                .into_owned(),
+        );
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+        let report_path = dir.path().join("report.json");
+
+        let mut app = batch_app(inputs.clone(), &out_dir, 2);
+        app.config.report = Some(report_path.to_string_lossy().into_owned());
+        let logs = LogCapture::default();
+        let result = tracing::subscriber::with_default(logs.json_subscriber(), || app.run());
+        assert!(result.is_err());
+
+        let mut files = HashSet::new();
+        for line in logs.contents().lines() {
+            let event: serde_json::Value = serde_json::from_str(line)?;
+            let spans = event["spans"].as_array().expect("span list");
+            let field = |name: &str| spans.iter().find_map(|span| span[name].as_str());
+            assert_eq!(field("run_id"), Some(app.run_id()), "{}", line);
+            if let Some(file) = field("file") {
+                assert_eq!(event["span"]["name"], "file", "{}", line);
+                assert_eq!(event["span"]["transform"], "upper", "{}", line);
+                files.insert(file.to_string());
+            }
+        }
+        assert_eq!(files, inputs.iter().cloned().collect());
+        // The failed input's error is logged inside its own span
+        assert!(logs
+            .contents()
+            .lines()
+            .any(|line| { line.contains("\"level\":\"ERROR\"") && line.contains("missing.txt") }));
+
+        let report: serde_json::Value = serde_json::from_slice(&std::fs::read(&report_path)?)?;
+        assert_eq!(report["run_id"], app.run_id());
+        assert_eq!(app.run_id().len(), 26);
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_output_names_rejected() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("a"))?;
+        std::fs::create_dir_all(dir.path().join("b"))?;
+        let inputs = vec![
+            dir.path().join("a/same.txt").to_string_lossy().into_owned(),
+            dir.path().join("b/same.txt").to_string_lossy().into_owned(),
+        ];
+
+        let app = batch_app(inputs, dir.path(), 2);
+        let err = app.process_all().unwrap_err();
+        assert!(err.to_string().contains("same output"));
+        Ok(())
+    }
+
+    /// Single-file app writing `input` to `output`
+    fn file_app(input: &Path, output: &Path, mode: Mode, backup: bool) -> App {
+        App::new(Config {
+            inputs: vec![input.to_string_lossy().into_owned()],
+            output: Some(output.to_string_lossy().into_owned()),
+            mode,
+            force: true,
+            backup: backup.then(|| ".bak".to_string()),
+            ..Config::default()
+        })
+    }
+
+    /// Panics on the line `boom`, like a bug hit halfway through an output
+    fn panic_on_boom(line: &str) {
+        if line == "boom" {
+            panic!("injected fault");
+        }
+    }
+
+    #[test]
+    fn test_panic_fails_run_without_output() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, "one\ntwo\nboom\nfour\n")?;
+
+        let mut app = file_app(&input, &output, Mode::Upper, false);
+        app.line_hook = Some(panic_on_boom);
+        let err = app.run().unwrap_err();
+
+        match err.downcast_ref() {
+            Some(LibError::OperationFailed(message)) => {
+                assert_eq!(message, "panicked: injected fault")
+            }
+            other => panic!("Expected OperationFailed, got {:?}", other),
+        }
+        // Neither the output nor its temporary file survives the unwind
+        let leftovers: Vec<_> = std::fs::read_dir(dir.path())?
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name != "input.txt")
+            .collect();
+        assert!(leftovers.is_empty(), "files left: {:?}", leftovers);
+        Ok(())
+    }
+
+    #[test]
+    fn test_panic_fails_only_its_input() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let mut inputs = write_inputs(dir.path(), 4)?;
+        let bad = dir.path().join("input-bad.txt");
+        std::fs::write(&bad, "boom\n")?;
+        inputs.push(bad.to_string_lossy().into_owned());
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+
+        let mut app = batch_app(inputs, &out_dir, 2);
+        app.line_hook = Some(panic_on_boom);
+        let summary = app.process_all()?;
+
+        assert_eq!(summary.succeeded(), 4);
+        assert_eq!(summary.failed(), 1);
+        assert!(!out_dir.join("input-bad.txt").exists());
+        Ok(())
+    }
+
+    /// Runs `input` through a split run and returns each part's contents
+    /// followed by the parsed manifest
+    fn split_run(
+        input: &str,
+        mode: Mode,
+        limit: SplitLimit,
+    ) -> Result<(Vec<String>, serde_json::Value)> {
+        let dir = tempfile::TempDir::new()?;
+        let input_path = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input_path, input)?;
+
+        App::new(Config {
+            split: Some(limit),
+            ..file_app(&input_path, &output, mode, false).config
+        })
+        .run()?;
+
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.path().join("output.txt.manifest.json"))?)?;
+        let parts = (1..)
+            .map(|number| split_part_path(&output, number))
+            .take_while(|path| path.exists())
+            .map(std::fs::read_to_string)
+            .collect::<io::Result<_>>()?;
+        assert!(!output.exists(), "the unsplit output was written too");
+        Ok((parts, manifest))
+    }
+
+    #[test]
+    fn test_split_lines_boundaries() -> Result<()> {
+        // Streamed and buffered outputs split the same way
+        for (mode, input, expected) in [
+            (Mode::Upper, "a\nb\nc\nd\ne\n", ["A\nB\n", "C\nD\n", "E\n"]),
+            (Mode::Sort, "e\nd\nc\nb\na\n", ["a\nb\n", "c\nd\n", "e\n"]),
+        ] {
+            let (parts, manifest) = split_run(input, mode, SplitLimit::Lines(2))?;
+
+            assert_eq!(parts, expected);
+            assert_eq!(
+                manifest,
+                serde_json::json!({"parts": [
+                    {"path": "output.txt.part0001", "lines": 2, "bytes": 4},
+                    {"path": "output.txt.part0002", "lines": 2, "bytes": 4},
+                    {"path": "output.txt.part0003", "lines": 1, "bytes": 2},
+                ]})
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_bytes_keeps_lines_whole() -> Result<()> {
+        // No terminator on the last line, which still counts as one
+        let (parts, manifest) = split_run(
+            "aaaa\nbb\ncccccccccc\nd\ne",
+            Mode::Lower,
+            SplitLimit::Bytes(8),
+        )?;
+
+        // A line over the limit gets a part of its own
+        assert_eq!(parts, ["aaaa\nbb\n", "cccccccccc\n", "d\ne"]);
+        let sizes: Vec<_> = manifest["parts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|part| {
+                (
+                    part["lines"].as_u64().unwrap(),
+                    part["bytes"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(sizes, [(2, 8), (1, 11), (2, 3)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_refuses_existing_parts() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, "one\ntwo\n")?;
+        std::fs::write(split_part_path(&output, 1), "from an earlier run\n")?;
+        let app = |force| {
+            App::new(Config {
+                split: Some(SplitLimit::Lines(1)),
+                force,
+                ..file_app(&input, &output, Mode::Upper, false).config
+            })
+        };
+
+        let err = app(false).run().unwrap_err();
+        assert_eq!(exit_code(&err), 2);
+        assert!(err.to_string().contains("output.txt.part0001"), "{}", err);
+
+        app(true).run()?;
+        assert_eq!(
+            std::fs::read_to_string(split_part_path(&output, 1))?,
+            "ONE\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_run_leaves_output_untouched() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        // Valid first line so the temp file is created and written to
+        // before the invalid UTF-8 aborts processing
+        std::fs::write(&input, b"good line\n\xff\xfe\n")?;
+        std::fs::write(&output, "original\n")?;
+
+        let result = App::new(Config {
+            encoding: EncodingChoice::Utf8,
+            ..file_app(&input, &output, Mode::Upper, false).config
+        })
+        .run();
+
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&output)?, "original\n");
+        let leftovers: Vec<_> = std::fs::read_dir(dir.path())?
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name != "input.txt" && name != "output.txt")
+            .collect();
+        assert!(leftovers.is_empty(), "temp files left: {:?}", leftovers);
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_keeps_previous_output() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, "pear\napple\n")?;
+        std::fs::write(&output, "previous run\n")?;
+
+        // Sort takes the buffered path, Upper the streaming one
+        file_app(&input, &output, Mode::Sort, true).run()?;
+        assert_eq!(std::fs::read_to_string(&output)?, "apple\npear\n");
+        assert_eq!(
+            std::fs::read_to_string(backup_path(&output, ".bak"))?,
+            "previous run\n"
+        );
+
+        file_app(&input, &output, Mode::Upper, true).run()?;
+        assert_eq!(std::fs::read_to_string(&output)?, "PEAR\nAPPLE\n");
+        assert_eq!(
+            std::fs::read_to_string(backup_path(&output, ".bak"))?,
+            "apple\npear\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_backup_by_default() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, "new\n")?;
+        std::fs::write(&output, "old\n")?;
+
+        file_app(&input, &output, Mode::Upper, false).run()?;
+
+        assert_eq!(std::fs::read_to_string(&output)?, "NEW\n");
+        assert!(!backup_path(&output, ".bak").exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_permissions_preserved() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, "content\n")?;
+        std::fs::write(&output, "old\n")?;
+        std::fs::set_permissions(&output, std::fs::Permissions::from_mode(0o640))?;
+
+        for mode in [Mode::Upper, Mode::Sort] {
+            file_app(&input, &output, mode, false).run()?;
+            let perms = std::fs::metadata(&output)?.permissions();
+            assert_eq!(perms.mode() & 0o777, 0o640, "mode changed for {:?}", mode);
+        }
+        Ok(())
+    }
+
+    fn in_place_app(input: &Path, suffix: &str) -> App {
+        App::new(Config {
+            inputs: vec![input.to_string_lossy().into_owned()],
+            in_place: Some(suffix.to_string()),
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn test_in_place_without_suffix() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("notes.txt");
+        std::fs::write(&input, "hello\nworld\n")?;
+
+        in_place_app(&input, "").run()?;
+
+        assert_eq!(std::fs::read_to_string(&input)?, "HELLO\nWORLD\n");
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_place_with_suffix() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("notes.txt");
+        std::fs::write(&input, "hello\n")?;
+
+        in_place_app(&input, ".orig").run()?;
+
+        assert_eq!(std::fs::read_to_string(&input)?, "HELLO\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("notes.txt.orig"))?,
+            "hello\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_place_unchanged_keeps_mtime() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("notes.txt");
+        std::fs::write(&input, "ALREADY UPPER\n")?;
+        let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(&input)?
+            .set_modified(old)?;
+
+        // Cover both the streaming and the buffered write path
+        for mode in [Mode::Upper, Mode::Sort] {
+            App::new(Config {
+                mode,
+                ..in_place_app(&input, ".orig").config
+            })
+            .run()?;
+            assert_eq!(std::fs::metadata(&input)?.modified()?, old);
+        }
+        assert!(!dir.path().join("notes.txt.orig").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_place_conflicts_with_outputs() {
+        for conflicting in [["-o", "out.txt"], ["--out-dir", "out"]] {
+            let mut argv = vec!["my_app", "-i", "in.txt", "-I"];
+            argv.extend(conflicting);
+            let err = Args::try_parse_from(argv).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn test_in_place_suffix_parsing() {
+        let args = Args::try_parse_from(["my_app", "-i", "in.txt", "-I"]).unwrap();
+        assert_eq!(args.in_place.as_deref(), Some(""));
+
+        let args = Args::try_parse_from(["my_app", "-i", "in.txt", "--in-place=.bak"]).unwrap();
+        assert_eq!(args.in_place.as_deref(), Some(".bak"));
+
+        let args = Args::try_parse_from(["my_app", "-i", "in.txt"]).unwrap();
+        assert_eq!(args.in_place, None);
+    }
+
+    /// [`Prompt`] giving a fixed answer and recording each question
+    #[derive(Clone, Default)]
+    struct ScriptedPrompt {
+        interactive: bool,
+        answer: bool,
+        asked: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Prompt for ScriptedPrompt {
+        fn is_interactive(&self) -> bool {
+            self.interactive
+        }
+
+        fn confirm(&self, question: &str) -> io::Result<bool> {
+            self.asked.lock().unwrap().push(question.to_string());
+            Ok(self.answer)
+        }
+    }
+
+    /// In-place app over `count` new files, asking above `threshold`
+    fn confirmed_app(
+        dir: &Path,
+        count: usize,
+        threshold: usize,
+        prompt: &ScriptedPrompt,
+    ) -> Result<(App, Vec<String>)> {
+        let inputs = write_inputs(dir, count)?;
+        let app = App::new(Config {
+            inputs: inputs.clone(),
+            in_place: Some(String::new()),
+            confirm_threshold: Some(threshold),
+            jobs: 1,
+            ..Config::default()
+        })
+        .with_prompt(prompt.clone());
+        Ok((app, inputs))
+    }
+
+    #[test]
+    fn test_in_place_asks_above_threshold() -> Result<()> {
+        let prompt = ScriptedPrompt {
+            interactive: true,
+            answer: true,
+            ..ScriptedPrompt::default()
+        };
+
+        // Exactly at the threshold goes ahead without asking
+        let dir = tempfile::TempDir::new()?;
+        let (app, _) = confirmed_app(dir.path(), 3, 3, &prompt)?;
+        app.run()?;
+        assert!(prompt.asked.lock().unwrap().is_empty());
+
+        let dir = tempfile::TempDir::new()?;
+        let (app, inputs) = confirmed_app(dir.path(), 4, 3, &prompt)?;
+        app.run()?;
+        assert_eq!(
+            *prompt.asked.lock().unwrap(),
+            ["About to modify 4 files in place. Continue?"]
+        );
+        assert_eq!(
+            std::fs::read_to_string(&inputs[0])?,
+            "FILE 0\nLINE TWO OF 0\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_place_refused_without_confirmation() -> Result<()> {
+        let declined = ScriptedPrompt {
+            interactive: true,
+            answer: false,
+            ..ScriptedPrompt::default()
+        };
+        let no_terminal = ScriptedPrompt::default();
+
+        for prompt in [&declined, &no_terminal] {
+            let dir = tempfile::TempDir::new()?;
+            let (app, inputs) = confirmed_app(dir.path(), 4, 3, prompt)?;
+            let err = app.run().unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(AppError::NotConfirmed { files: 4 })
+            ));
+            assert_eq!(exit_code(&err), 2);
+            assert_eq!(
+                std::fs::read_to_string(&inputs[0])?,
+                "file 0\nline two of 0\n"
+            );
+        }
+        assert_eq!(declined.asked.lock().unwrap().len(), 1);
+        // With nobody to answer, nothing is asked
+        assert!(no_terminal.asked.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_yes_skips_the_prompt() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let prompt = ScriptedPrompt::default();
+        let (mut app, inputs) = confirmed_app(dir.path(), 4, 3, &prompt)?;
+        app.config.yes = true;
+        app.run()?;
+        assert!(prompt.asked.lock().unwrap().is_empty());
+        assert_eq!(
+            std::fs::read_to_string(&inputs[3])?,
+            "FILE 3\nLINE TWO OF 3\n"
+        );
+
+        let args = Args::try_parse_from(["my_app", "-i", "in.txt", "-I", "-y"]).unwrap();
+        let config = Config::from_args(args);
+        assert!(config.yes);
+        assert_eq!(config.confirm_threshold, Some(10));
+        Ok(())
+    }
+
+    #[test]
+    fn test_existing_output_is_refused() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, "new\n")?;
+        std::fs::write(&output, "keep me\n")?;
+
+        let app = App::new(Config {
+            inputs: vec![input.to_string_lossy().into_owned()],
+            output: Some(output.to_string_lossy().into_owned()),
+            ..Config::default()
+        });
+        let err = app.run().unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("output exists, pass --force to overwrite"));
+        assert_eq!(exit_code(&err), 2);
+        assert_eq!(std::fs::read_to_string(&output)?, "keep me\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_force_overwrites_existing_output() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, "new\n")?;
+        std::fs::write(&output, "old\n")?;
+
+        file_app(&input, &output, Mode::Upper, false).run()?;
+
+        assert_eq!(std::fs::read_to_string(&output)?, "NEW\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_input_renders_hint() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let input = dir.path().join("missing.txt");
+        let err = file_app(&input, &dir.path().join("out.txt"), Mode::Upper, false)
+            .run()
+            .unwrap_err();
+
+        let friendly = render_error(&err, false, false);
+        assert_eq!(
+            friendly,
+            format!(
+                "Error: input file not found: {0}\n  \
+                 Code: input_not_found\n  \
+                 File: {0}\n  \
+                 Hint: check the --input path",
+                input.display()
+            )
+        );
+
+        let raw = render_error(&err, true, false);
+        assert_eq!(raw, format!("Error: {:?}", err));
+        assert!(raw.contains("Caused by"), "{}", raw);
+        assert!(!raw.contains("Hint:"), "{}", raw);
+        assert_eq!(exit_code(&err), 1);
+    }
+
+    #[test]
+    fn test_unknown_error_renders_full_chain() {
+        let err = anyhow::anyhow!("disk on fire").context("Failed to write output");
+
+        assert_eq!(
+            render_error(&err, false, false),
+            format!("Error: {:?}", err)
+        );
+    }
+
+    #[test]
+    fn test_error_block_colors() {
+        let err = anyhow::Error::new(AppError::OutputExists("out.txt".to_string()))
+            .context("Application execution failed");
+
+        let colored = render_error(&err, false, true);
+        assert!(
+            colored.starts_with("\x1b[1;31mError:\x1b[0m output exists"),
+            "{:?}",
+            colored
+        );
+        assert!(
+            colored.contains("\n  \x1b[1;36mHint:\x1b[0m pass --force"),
+            "{:?}",
+            colored
+        );
+        assert!(!render_error(&err, false, false).contains('\x1b'));
+    }
+
+    #[test]
+    fn test_no_color_requested() {
+        assert!(!no_color_requested(None));
+        // An empty value counts as unset
+        assert!(!no_color_requested(Some("".into())));
+        assert!(no_color_requested(Some("1".into())));
+    }
+
+    #[test]
+    fn test_diagnostics_honor_redact() {
+        let config = || Config {
+            inputs: vec!["/data/secret-name.txt".to_string(), STDIN.to_string()],
+            out_dir: Some("/out".to_string()),
+            ..Config::default()
+        };
+
+        let shown = App::new(config()).diagnostics();
+        assert!(shown.contains(&format!("\n  OS: {} (", std::env::consts::OS)));
+        assert!(shown.contains("\n  Path: /data/secret-name.txt -> /out/secret-name.txt"));
+        assert!(shown.contains("\n  Path: stdin -> /out/-"));
+
+        let redacted = App::new(Config {
+            redact: true,
+            ..config()
+        })
+        .diagnostics();
+        assert!(redacted.contains("\n  Path: <redacted> -> <redacted>"));
+        assert!(!redacted.contains("secret-name"), "{}", redacted);
+    }
+
+    #[test]
+    fn test_commit_does_not_clobber_late_file() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let target = dir.path().join("output.txt");
+
+        let mut file = AtomicFile::create(&target)?;
+        file.write_all(b"ours\n")?;
+        // Another process creates the target after our existence check
+        std::fs::write(&target, "theirs\n")?;
+
+        let err = file.commit(None, false).unwrap_err();
+        assert_eq!(exit_code(&err), 2);
+        assert_eq!(std::fs::read_to_string(&target)?, "theirs\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_skips_existing_outputs() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 3)?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+        std::fs::write(out_dir.join("input-001.txt"), "keep me\n")?;
+
+        let app = batch_app(inputs, &out_dir, 2);
+        let summary = app.process_all()?;
+
+        assert_eq!(summary.succeeded(), 2);
+        assert_eq!(summary.skipped(), 1);
+        assert_eq!(summary.failed(), 0);
+        assert!(summary.results[1].is_skipped());
+        assert_eq!(
+            std::fs::read_to_string(out_dir.join("input-001.txt"))?,
+            "keep me\n"
+        );
+        assert!(out_dir.join("input-002.txt").exists());
+
+        // Every output exists now, so a rerun skips them all
+        let err = app.run().unwrap_err();
+        assert_eq!(exit_code(&err), 2);
+        assert!(err.to_string().contains("3 of 3 outputs exist"));
+        Ok(())
+    }
+
+    fn gzip(data: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn gunzip(path: &Path) -> Result<String> {
+        let mut data = String::new();
+        MultiGzDecoder::new(File::open(path)?).read_to_string(&mut data)?;
+        Ok(data)
+    }
+
+    #[test]
+    fn test_gzip_round_trip_matches_plain() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let text = "pear\napple\nfig\n";
+        let plain_in = dir.path().join("input.txt");
+        let gz_in = dir.path().join("input.txt.gz");
+        std::fs::write(&plain_in, text)?;
+        std::fs::write(&gz_in, gzip(text))?;
+
+        for mode in [Mode::Upper, Mode::Sort] {
+            let plain_out = dir.path().join(format!("{:?}.txt", mode));
+            let gz_out = dir.path().join(format!("{:?}.txt.gz", mode));
+            file_app(&plain_in, &plain_out, mode, false).run()?;
+            file_app(&gz_in, &gz_out, mode, false).run()?;
+
+            assert_eq!(gunzip(&gz_out)?, std::fs::read_to_string(&plain_out)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_gzip_detected_by_magic_bytes() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let text = "pear\napple\nfig\n";
+        let plain_in = dir.path().join("input.txt");
+        // Compressed, but nothing in the name says so
+        let gz_in = dir.path().join("input.log");
+        std::fs::write(&plain_in, text)?;
+        std::fs::write(&gz_in, gzip(text))?;
+
+        for mode in [Mode::Upper, Mode::Sort] {
+            let plain_out = dir.path().join(format!("{:?}-plain.txt", mode));
+            let gz_out = dir.path().join(format!("{:?}-gz.txt", mode));
+            file_app(&plain_in, &plain_out, mode, false).run()?;
+            file_app(&gz_in, &gz_out, mode, false).run()?;
+
+            assert_eq!(std::fs::read(&gz_out)?, std::fs::read(&plain_out)?);
+        }
+
+        // Truncated data is still caught as gzip and fails to decompress
+        let truncated = dir.path().join("truncated.log");
+        let data = gzip(text);
+        std::fs::write(&truncated, &data[..data.len() / 2])?;
+        let err = file_app(&truncated, &dir.path().join("out.txt"), Mode::Upper, false)
+            .run()
+            .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(
+            message.contains("Cannot decompress gzip input"),
+            "{}",
+            message
+        );
+
+        // An explicit --input-compression none reads the bytes as they are
+        let forced = App::new(Config {
+            input_compression: Some(Compression::None),
+            encoding: EncodingChoice::Utf8,
+            ..file_app(&gz_in, &dir.path().join("raw.txt"), Mode::Upper, false).config
+        });
+        let message = format!("{:#}", forced.run().unwrap_err());
+        assert!(message.contains("not valid UTF-8"), "{}", message);
+        Ok(())
+    }
+
+    fn encoded_app(input: &Path, output: &Path, encoding: EncodingChoice) -> App {
+        App::new(Config {
+            encoding,
+            ..file_app(input, output, Mode::Upper, false).config
+        })
+    }
+
+    #[test]
+    fn test_utf16le_with_bom_round_trips() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        let utf16le =
+            |text: &str| -> Vec<u8> { text.encode_utf16().flat_map(u16::to_le_bytes).collect() };
+        std::fs::write(&input, utf16le("\u{feff}grüße\r\nzoë\r\n"))?;
+
+        // Detected by its byte order mark, which stays U+FEFF in UTF-8
+        encoded_app(&input, &output, EncodingChoice::Auto).run()?;
+        assert_eq!(
+            std::fs::read_to_string(&output)?,
+            "\u{feff}GRÜSSE\r\nZOË\r\n"
+        );
+
+        let mut app = encoded_app(&input, &output, EncodingChoice::Utf16Le);
+        app.config.output_encoding = TextEncoding::Utf16Le;
+        app.config.force = true;
+        app.run()?;
+        assert_eq!(
+            std::fs::read(&output)?,
+            utf16le("\u{feff}GRÜSSE\r\nZOË\r\n")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_latin1_input_decodes_to_its_characters() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        // "café naïve £5" in ISO-8859-1
+        std::fs::write(&input, b"caf\xe9 na\xefve \xa35\n")?;
+
+        for choice in [EncodingChoice::Latin1, EncodingChoice::Auto] {
+            let mut app = encoded_app(&input, &output, choice);
+            app.config.force = true;
+            app.run()?;
+            assert_eq!(
+                std::fs::read_to_string(&output)?,
+                "CAFÉ NAÏVE £5\n",
+                "{:?}",
+                choice
+            );
+        }
+
+        // And back, for tools that only read Latin-1
+        let mut app = encoded_app(&input, &output, EncodingChoice::Latin1);
+        app.config.output_encoding = TextEncoding::Latin1;
+        app.config.force = true;
+        app.run()?;
+        assert_eq!(std::fs::read(&output)?, b"CAF\xc9 NA\xcfVE \xa35\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_decoding_error_names_byte_offset() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        // "ok\n" then an unpaired high surrogate at byte 6
+        std::fs::write(&input, b"o\0k\0\n\0\x00\xd8x\0\n\0")?;
+
+        let err = encoded_app(&input, &output, EncodingChoice::Utf16Le)
+            .run()
+            .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(
+            message.contains("not valid utf-16le at byte 6"),
+            "{}",
+            message
+        );
+        assert!(message.contains("input.txt"), "{}", message);
+        assert!(!output.exists());
+
+        let err = encoded_app(&input, &output, EncodingChoice::Utf8)
+            .run()
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("not valid UTF-8"),
+            "{:#}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lossy_decoding_warns_with_replacement_count() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, b"a\xffb\nc\xc3\n\xfe\n")?;
+
+        let mut app = encoded_app(&input, &output, EncodingChoice::Utf8);
+        app.config.lossy = true;
+        let logs = LogCapture::default();
+        logs.capture(|| app.run())?;
+
+        assert_eq!(
+            std::fs::read_to_string(&output)?,
+            "A\u{fffd}B\nC\u{fffd}\n\u{fffd}\n"
+        );
+        let contents = logs.contents();
+        assert!(
+            contents.contains("WARN") && contents.contains("Replaced 3 undecodable utf-8"),
+            "{}",
+            contents
+        );
+
+        // Latin-1 output has no `✓`; lossy writes `?` for it and says so
+        std::fs::write(&input, "done ✓ ✓\n")?;
+        let mut app = encoded_app(&input, &output, EncodingChoice::Utf8);
+        app.config.output_encoding = TextEncoding::Latin1;
+        app.config.force = true;
+        let err = app.run().unwrap_err();
+        assert!(format!("{:#}", err).contains("U+2713"), "{:#}", err);
+        app.config.lossy = true;
+        let logs = LogCapture::default();
+        logs.capture(|| app.run())?;
+        assert_eq!(std::fs::read(&output)?, b"DONE ? ?\n");
+        assert!(
+            logs.contents().contains("Replaced 2 character(s)"),
+            "{}",
+            logs.contents()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_encoding_sniff() {
+        assert_eq!(TextEncoding::sniff(b"plain"), TextEncoding::Utf8);
+        assert_eq!(TextEncoding::sniff("zoë".as_bytes()), TextEncoding::Utf8);
+        // Cut off inside "ë" by the end of the buffer
+        assert_eq!(TextEncoding::sniff(b"zo\xc3"), TextEncoding::Utf8);
+        assert_eq!(TextEncoding::sniff(b"\xff\xfez\0"), TextEncoding::Utf16Le);
+        assert_eq!(TextEncoding::sniff(b"\xfe\xff\0z"), TextEncoding::Utf16Be);
+        assert_eq!(TextEncoding::sniff(b"a\0b\0c\0"), TextEncoding::Utf16Le);
+        assert_eq!(TextEncoding::sniff(b"\0a\0b\0c"), TextEncoding::Utf16Be);
+        assert_eq!(TextEncoding::sniff(b"zo\xeb\n"), TextEncoding::Latin1);
+        assert_eq!(TextEncoding::sniff(b""), TextEncoding::Utf8);
+    }
+
+    #[test]
+    fn test_compression_sniff() {
+        assert_eq!(Compression::sniff(&gzip("x")), Compression::Gzip);
+        assert_eq!(Compression::sniff(b"\x1f"), Compression::None);
+        assert_eq!(Compression::sniff(b"plain text"), Compression::None);
+        assert_eq!(Compression::sniff(b""), Compression::None);
+    }
+
+    #[test]
+    fn test_forced_compression_overrides_extension() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.dat");
+        let plain_out = dir.path().join("plain.gz");
+        let gz_out = dir.path().join("compressed.txt");
+        std::fs::write(&input, gzip("hello\n"))?;
+
+        let app = |output: &Path, compression| {
+            App::new(Config {
+                inputs: vec![input.to_string_lossy().into_owned()],
+                output: Some(output.to_string_lossy().into_owned()),
+                input_compression: Some(Compression::Gzip),
+                output_compression: Some(compression),
+                ..Config::default()
+            })
+        };
+        app(&plain_out, Compression::None).run()?;
+        app(&gz_out, Compression::Gzip).run()?;
+
+        assert_eq!(std::fs::read_to_string(&plain_out)?, "HELLO\n");
+        assert_eq!(gunzip(&gz_out)?, "HELLO\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_gzip_names_file() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("broken.txt.gz");
+        let mut data = gzip("some content that will not survive\n");
+        let middle = data.len() / 2;
+        data[middle..].fill(0xAA);
+        std::fs::write(&input, data)?;
+
+        for mode in [Mode::Upper, Mode::Sort] {
+            let output = dir.path().join(format!("{:?}.txt", mode));
+            let err = file_app(&input, &output, mode, false).run().unwrap_err();
+
+            let message = format!("{:#}", err);
+            assert!(
+                message.contains("Cannot decompress gzip input"),
+                "{}",
+                message
+            );
+            assert!(message.contains("broken.txt.gz"), "{}", message);
+            assert!(!output.exists());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_mode_parses_every_name() {
+        for &mode in Mode::all() {
+            assert_eq!(mode.as_str().parse::<Mode>(), Ok(mode));
+            assert_eq!(Mode::try_from(mode.as_str()), Ok(mode));
+
+            // Same spelling clap accepts for --mode
+            let value = mode.to_possible_value().unwrap();
+            assert_eq!(value.get_name(), mode.as_str());
+        }
+        assert_eq!(Mode::all().len(), Mode::value_variants().len());
+    }
+
+    #[test]
+    fn test_invalid_mode_lists_valid_values() {
+        let err = "foo".parse::<Mode>().unwrap_err();
+
+        assert_eq!(err, ParseModeError("foo".to_string()));
+        assert_eq!(
+            err.to_string(),
+            "invalid mode 'foo', expected one of: upper, lower, reverse, trim-lines, sort"
+        );
+        assert!(Mode::try_from("Upper").is_err());
+    }
+
+    #[test]
+    fn test_turkish_case_mappings() {
+        let turkish = CaseLocale::Turkish;
+        assert_eq!(turkish.to_upper("istanbul ılık"), "İSTANBUL ILIK");
+        assert_eq!(turkish.to_lower("İSTANBUL ILIK"), "istanbul ılık");
+        // The rest of Unicode keeps its usual mappings, final sigma included
+        assert_eq!(turkish.to_upper("çiğ straße"), "ÇİĞ STRASSE");
+        assert_eq!(turkish.to_lower("ΙΣΤΟΣ Iİ"), "ιστος ıi");
+
+        // Without the locale i and I pair up, and İ lowercases to i + U+0307
+        assert_eq!(CaseLocale::Default.to_upper("ılık"), "ILIK");
+        assert_eq!(CaseLocale::Default.to_lower("İ"), "i\u{307}");
+    }
+
+    #[test]
+    fn test_ascii_case_leaves_non_ascii() {
+        let ascii = CaseLocale::Ascii;
+        assert_eq!(ascii.to_upper("straße, café, ılık"), "STRAßE, CAFé, ıLıK");
+        assert_eq!(ascii.to_lower("ÉCOLE İSTANBUL"), "École İstanbul");
+
+        // Nothing to change, nothing allocated
+        assert!(matches!(ascii.to_upper("ÉCOLE 42"), Cow::Borrowed(_)));
+        assert!(matches!(ascii.to_lower("école 42"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_case_locale_reaches_every_path() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        std::fs::write(&input, "iı\n")?;
+        let app = |mode| {
+            App::new(Config {
+                mode,
+                case_locale: CaseLocale::Turkish,
+                ..file_app(&input, &dir.path().join("output.txt"), mode, false).config
+            })
+        };
+
+        // In memory, then streamed to a file
+        assert_eq!(app(Mode::Upper).process("iı\n")?, "İI\n");
+        assert_eq!(app(Mode::Lower).process("İI\n")?, "iı\n");
+        app(Mode::Upper).run()?;
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("output.txt"))?,
+            "İI\n"
+        );
+        Ok(())
+    }
+
+    fn structured_app(inputs: Vec<String>, out_dir: Option<&Path>, format: OutputFormat) -> App {
+        App::new(Config {
+            inputs,
+            out_dir: out_dir.map(|dir| dir.to_string_lossy().into_owned()),
+            format,
+            ..Config::default()
+        })
+    }
+
+    /// What `run` would print on stdout for `app`
+    fn structured_output(app: &App) -> Result<String> {
+        let summary = app.process_all()?;
+        let mut out = Vec::new();
+        app.emit(&summary.records(app.config.mode), &mut out)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    fn assert_record_schema(record: &serde_json::Value) {
+        let fields = [
+            "input",
+            "output",
+            "status",
+            "bytes_in",
+            "bytes_out",
+            "transform",
+            "duration_ms",
+            "result",
+            "error",
+            "code",
+        ];
+        let object = record.as_object().expect("record is not an object");
+        assert_eq!(object.len(), fields.len(), "{}", record);
+        for field in fields {
+            assert!(object.contains_key(field), "missing {}: {}", field, record);
+        }
+        assert!(record["duration_ms"].is_u64());
+    }
+
+    #[test]
+    fn test_json_record_captures_stdout_result() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = write_inputs(dir.path(), 1)?;
+        let app = structured_app(input.clone(), None, OutputFormat::Json);
+
+        let record: serde_json::Value = serde_json::from_str(&structured_output(&app)?)?;
+
+        assert_record_schema(&record);
+        assert_eq!(record["input"], input[0].as_str());
+        assert_eq!(record["output"], "stdout");
+        assert_eq!(record["status"], "ok");
+        assert_eq!(record["transform"], "upper");
+        assert_eq!(record["result"], "FILE 0\nLINE TWO OF 0\n");
+        assert_eq!(record["bytes_in"], 21);
+        assert_eq!(record["bytes_out"], 21);
+        assert!(record["error"].is_null());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_batch_is_array_with_failures() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir(&out_dir)?;
+        let mut inputs = write_inputs(dir.path(), 2)?;
+        inputs.push(
+            dir.path()
+                .join("missing.txt")
+                .to_string_lossy()
+                .into_owned(),
+        );
+        let app = structured_app(inputs, Some(&out_dir), OutputFormat::Json);
+
+        let records: Vec<serde_json::Value> = serde_json::from_str(&structured_output(&app)?)?;
+
+        assert_eq!(records.len(), 3);
+        records.iter().for_each(assert_record_schema);
+        assert_eq!(records[0]["status"], "ok");
+        assert_eq!(
+            records[0]["output"],
+            out_dir.join("input-000.txt").to_string_lossy().as_ref()
+        );
+        assert!(records[0]["result"].is_null());
+        assert_eq!(records[2]["status"], "failed");
+        assert_eq!(records[2]["bytes_in"], 0);
+        let error = records[2]["error"].as_str().unwrap();
+        assert!(error.contains("input file not found"), "{}", error);
+        assert!(error.contains("missing.txt"), "{}", error);
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_emits_document_per_file() -> Result<()> {
+        use serde::Deserialize;
+
+        let dir = tempfile::TempDir::new()?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir(&out_dir)?;
+        let inputs = write_inputs(dir.path(), 2)?;
+        std::fs::write(out_dir.join("input-001.txt"), "existing")?;
+        let app = structured_app(inputs, Some(&out_dir), OutputFormat::Yaml);
+
+        let output = structured_output(&app)?;
+        let documents: Vec<serde_json::Value> = serde_yaml::Deserializer::from_str(&output)
+            .map(serde_json::Value::deserialize)
+            .collect::<Result<_, _>>()?;
+
+        assert_eq!(documents.len(), 2);
+        documents.iter().for_each(assert_record_schema);
+        assert_eq!(documents[0]["status"], "ok");
+        assert_eq!(documents[0]["bytes_out"], 21);
+        assert_eq!(documents[1]["status"], "skipped");
+        assert!(documents[1]["error"]
+            .as_str()
+            .unwrap()
+            .contains("output exists"));
+        Ok(())
+    }
+
+    /// Panics unless applying `transform` to its own output changes nothing
+    fn assert_idempotent_with(transform: impl Fn(&str) -> String, input: &str) {
+        let once = transform(input);
+        let twice = transform(&once);
+        assert_eq!(twice, once, "not idempotent for input {:?}", input);
+    }
+
+    fn assert_idempotent(mode: Mode, input: &str) {
+        assert_idempotent_with(|text| mode.apply(text), input);
+    }
+
+    #[test]
+    fn test_idempotent_modes() {
+        let idempotent: Vec<Mode> = Mode::all()
+            .iter()
+            .copied()
+            .filter(|mode| mode.is_idempotent())
+            .collect();
+        assert_eq!(idempotent, [Mode::Upper, Mode::Lower, Mode::TrimLines]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not idempotent")]
+    fn test_reverse_is_not_idempotent() {
+        assert_idempotent(Mode::Reverse, "abc\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "not idempotent")]
+    fn test_detects_transform_that_appends() {
+        // What a regression in Upper that tags its output would look like
+        assert_idempotent_with(|text| Mode::Upper.apply(text) + "!", "abc\n");
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_idempotent_modes(input in "(?s).{0,200}") {
+            for &mode in Mode::all().iter().filter(|mode| mode.is_idempotent()) {
+                assert_idempotent(mode, &input);
+            }
+        }
+
+        // A reversed line starting with `\r` would end in one and merge with
+        // its `\n` into a CRLF terminator
+        #[test]
+        fn prop_reverse_twice_is_identity(input in "[^\r]{0,200}") {
+            let twice = Mode::Reverse.apply(&Mode::Reverse.apply(&input));
+            proptest::prop_assert_eq!(twice, input);
+        }
+    }
+
+    fn grep_app(pattern: &str, invert_match: bool, mode: Mode) -> App {
+        App::new(Config {
+            grep: Some(Regex::new(pattern).unwrap()),
+            invert_match,
+            mode,
+            ..Config::default()
+        })
+    }
+
+    const CAFE_MENU: &str = "café ☕\nthé\nnaïve ☕\n";
+
+    #[test]
+    fn test_grep_filters_multibyte_lines() -> Result<()> {
+        let app = grep_app("☕$", false, Mode::Upper);
+
+        let mut streamed = Vec::new();
+        let stats = app.process_streaming(CAFE_MENU.as_bytes(), &mut streamed)?;
+
+        assert_eq!(String::from_utf8(streamed)?, "CAFÉ ☕\nNAÏVE ☕\n");
+        assert_eq!(app.process(CAFE_MENU)?, "CAFÉ ☕\nNAÏVE ☕\n");
+        assert_eq!(stats.bytes_in, CAFE_MENU.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_grep_filters_before_whole_input_transform() -> Result<()> {
+        let app = grep_app("☕", false, Mode::Sort);
+        assert_eq!(
+            app.process("zeta ☕\nalpha\nbeta ☕\n")?,
+            "beta ☕\nzeta ☕\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invert_match_keeps_other_lines() -> Result<()> {
+        let app = grep_app("☕", true, Mode::Upper);
+
+        let mut streamed = Vec::new();
+        app.process_streaming(CAFE_MENU.as_bytes(), &mut streamed)?;
+
+        assert_eq!(String::from_utf8(streamed)?, "THÉ\n");
+        assert_eq!(app.process(CAFE_MENU)?, "THÉ\n");
+        Ok(())
+    }
+
+    /// Runs `input` through both the streaming and buffered paths with
+    /// `--dedup`, checking they agree
+    fn dedup_run(scope: DedupScope, input: &str) -> Result<String> {
+        let app = App::new(Config {
+            dedup: Some(scope),
+            mode: Mode::Upper,
+            ..Config::default()
+        });
+        let mut streamed = Vec::new();
+        app.process_streaming(input.as_bytes(), &mut streamed)?;
+        let streamed = String::from_utf8(streamed)?;
+        assert_eq!(app.process(input)?, streamed);
+        Ok(streamed)
+    }
+
+    #[test]
+    fn test_dedup_keeps_first_occurrences() -> Result<()> {
+        let input = "b\na\nb\r\nc\na\nb\nd\na";
+        // Terminators are not compared, so `b\r\n` repeats `b\n`
+        assert_eq!(dedup_run(DedupScope::All, input)?, "B\nA\nC\nD\n");
+
+        // Sort only sees the lines dedup kept
+        let app = App::new(Config {
+            dedup: Some(DedupScope::All),
+            mode: Mode::Sort,
+            ..Config::default()
+        });
+        assert_eq!(app.process(input)?, "a\nb\nc\nd\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup_window_forgets_least_recent() -> Result<()> {
+        let window = |size| DedupScope::Window(NonZeroUsize::new(size).unwrap());
+        // With room for two lines, the second `a` keeps `a` recent, so `c`
+        // evicts `b` and the last `b` is written again
+        assert_eq!(dedup_run(window(2), "a\nb\na\nc\na\nb\n")?, "A\nB\nC\nB\n");
+        // A window as large as the input removes every repeat
+        assert_eq!(dedup_run(window(4), "a\nb\na\nc\na\nb\n")?, "A\nB\nC\n");
+        assert_eq!(dedup_run(window(1), "a\na\nb\na\n")?, "A\nB\nA\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup_window_requires_dedup() {
+        assert!(Args::try_parse_from(["my_app", "-i", "in.txt", "--dedup-window", "10"]).is_err());
+        assert!(
+            Args::try_parse_from(["my_app", "-i", "in.txt", "--dedup", "--dedup-window", "0"])
+                .is_err()
+        );
+        let args =
+            Args::try_parse_from(["my_app", "-i", "in.txt", "--dedup", "--dedup-window", "10"])
+                .unwrap();
+        assert_eq!(
+            Config::from_args(args).dedup,
+            Some(DedupScope::Window(NonZeroUsize::new(10).unwrap()))
+        );
+    }
+
+    /// Leaves lines as they are, so only `--sort` and `--dedupe` act
+    fn stage_app(sort: Option<SortMode>, dedupe: Option<DedupeMode>) -> App {
+        App::new(Config {
+            mode: Mode::TrimLines,
+            sort,
+            dedupe,
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn test_numeric_sort_mixed_widths() -> Result<()> {
+        let app = stage_app(Some(SortMode::Numeric), None);
+        assert_eq!(
+            app.process("10 kB\n9\n-3\n100\n+2.5x\n0.25\nn/a\n")?,
+            "n/a\n-3\n0.25\n+2.5x\n9\n10 kB\n100\n"
+        );
+        // Lexically, `10` comes before `9`
+        let app = stage_app(Some(SortMode::Lexical), None);
+        assert_eq!(app.process("9\n10\n100\n")?, "10\n100\n9\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_natural_sort_compares_digit_runs() -> Result<()> {
+        let input = "file10\nfile2\nfile1.txt\nfile02b\nfile\n";
+        let app = stage_app(Some(SortMode::Natural), None);
+        assert_eq!(
+            app.process(input)?,
+            "file\nfile1.txt\nfile2\nfile02b\nfile10\n"
+        );
+        let app = stage_app(Some(SortMode::Lexical), None);
+        assert_eq!(
+            app.process(input)?,
+            "file\nfile02b\nfile1.txt\nfile10\nfile2\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_is_stable() -> Result<()> {
+        // Equal keys keep their input order, and an unterminated last line
+        // stays unterminated wherever it ends up
+        let app = stage_app(Some(SortMode::Numeric), None);
+        assert_eq!(app.process("10 b\n2 a\n10 a\n2 c")?, "2 a\n2 c\n10 b\n10 a");
+        let app = stage_app(Some(SortMode::Natural), None);
+        assert_eq!(app.process("x01\nx1\nx001\n")?, "x01\nx1\nx001\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_adjacent_and_global_dedupe() -> Result<()> {
+        let input = "b\n a\na\nb\na\n";
+        for (sort, mode, expected) in [
+            (None, DedupeMode::Adjacent, "b\na\nb\na\n"),
+            (None, DedupeMode::Global, "b\na\n"),
+            // Sorting first brings every repeat together
+            (Some(SortMode::Lexical), DedupeMode::Adjacent, "a\nb\n"),
+            (Some(SortMode::Lexical), DedupeMode::Global, "a\nb\n"),
+        ] {
+            let app = stage_app(sort, Some(mode));
+            assert_eq!(app.process(input)?, expected, "{:?} {:?}", sort, mode);
+            if sort.is_none() {
+                let mut streamed = Vec::new();
+                app.process_streaming(input.as_bytes(), &mut streamed)?;
+                assert_eq!(String::from_utf8(streamed)?, expected, "{:?}", mode);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_global_dedupe_memory_limit() -> Result<()> {
+        // Room for two distinct lines, however often they repeat
+        let app = App::new(Config {
+            dedupe: Some(DedupeMode::Global),
+            dedupe_memory: Some(2 * DEDUPE_BYTES_PER_LINE),
+            ..Config::default()
+        });
+        assert_eq!(app.process("a\nb\na\nb\n")?, "A\nB\n");
+
+        let err = app
+            .process_streaming("a\nb\na\nc\n".as_bytes(), io::sink())
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(AppError::DedupeMemoryExceeded { limit: 32 })
+        ));
+        assert_eq!(exit_code(&err), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_outputs_only_number() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("menu.txt");
+        std::fs::write(&input, CAFE_MENU)?;
+        let pattern = Regex::new("☕")?;
+
+        for (invert_match, expected) in [(false, "2\n"), (true, "1\n")] {
+            let output = dir.path().join(format!("count-{}.txt", invert_match));
+            App::new(Config {
+                inputs: vec![input.to_string_lossy().into_owned()],
+                output: Some(output.to_string_lossy().into_owned()),
+                grep: Some(pattern.clone()),
+                invert_match,
+                count: true,
+                ..Config::default()
+            })
+            .run()?;
+
+            assert_eq!(std::fs::read_to_string(&output)?, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_grep_pattern_names_flag() {
+        let err =
+            Args::try_parse_from(["app", "--input", "in.txt", "--grep", "caf(é"]).unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.contains("--grep"), "{}", message);
+        assert!(message.contains("caf(é"), "{}", message);
+    }
+
+    fn time_app(since: &str, until: &str, mode: Mode) -> App {
+        let parse = |time| Some(DateTime::parse_from_rfc3339(time).unwrap());
+        App::new(Config {
+            time_range: Some(TimeRange::new(parse(since), parse(until))),
+            mode,
+            ..Config::default()
+        })
+    }
+
+    const TIMED_LOG: &str = "2024-05-01T09:59:59Z early\n\
+                             2024-05-01T10:00:00Z start\n\
+                             2024-05-01T12:00:00+02:00 middle\n\
+                             2024-05-01T11:00:00Z end\n\
+                             2024-05-01T11:00:01Z late\n";
+
+    #[test]
+    fn test_time_range_bounds_are_inclusive() -> Result<()> {
+        let app = time_app("2024-05-01T10:00:00Z", "2024-05-01T11:00:00Z", Mode::Upper);
+        let expected = "2024-05-01T09:59:59Z early\n\
+                        2024-05-01T10:00:00Z START\n\
+                        2024-05-01T12:00:00+02:00 MIDDLE\n\
+                        2024-05-01T11:00:00Z END\n\
+                        2024-05-01T11:00:01Z late\n";
+
+        let mut streamed = Vec::new();
+        app.process_streaming(TIMED_LOG.as_bytes(), &mut streamed)?;
+
+        assert_eq!(String::from_utf8(streamed)?, expected);
+        assert_eq!(app.process(TIMED_LOG)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_out_of_range_before_sort() -> Result<()> {
+        let mut app = time_app("2024-05-01T10:00:00Z", "2024-05-01T11:00:00Z", Mode::Sort);
+        app.config.inputs = vec!["log.txt".to_string()];
+        let err = app.plan_jobs().unwrap_err();
+        assert!(err.to_string().contains("--drop-out-of-range"), "{}", err);
+
+        app.config.time_range.as_mut().unwrap().drop_out_of_range = true;
+        assert_eq!(
+            app.process(TIMED_LOG)?,
+            "2024-05-01T10:00:00Z start\n\
+             2024-05-01T11:00:00Z end\n\
+             2024-05-01T12:00:00+02:00 middle\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_untimed_lines_fail_or_pass() -> Result<()> {
+        let input = "2024-05-01T10:30:00Z ok\n  continued\n";
+        let mut app = time_app("2024-05-01T10:00:00Z", "2024-05-01T11:00:00Z", Mode::Upper);
+
+        let err = app.process(input).unwrap_err();
+        assert!(
+            err.to_string().contains("Line 2 has no RFC 3339 timestamp"),
+            "{}",
+            err
+        );
+        let mut streamed = Vec::new();
+        assert!(app
+            .process_streaming(input.as_bytes(), &mut streamed)
+            .is_err());
+
+        app.config.time_range.as_mut().unwrap().untimed = Untimed::Pass;
+        assert_eq!(
+            app.process(input)?,
+            "2024-05-01T10:30:00Z OK\n  continued\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp_field_from_args() -> Result<()> {
+        let args = Args::try_parse_from([
+            "app",
+            "--input",
+            "in.txt",
+            "--since",
+            "2024-05-01T10:00:00Z",
+            "--timestamp-field",
+            r"time=(\S+)",
+            "--drop-out-of-range",
+        ])?;
+        let app = App::new(Config::from_args(args));
+
+        assert_eq!(
+            app.process(
+                "level=info time=2024-05-01T09:00:00Z\nlevel=warn time=2024-05-01T10:00:00Z\n"
+            )?,
+            "LEVEL=WARN TIME=2024-05-01T10:00:00Z\n"
+        );
+        assert!(Args::try_parse_from(["app", "--input", "in.txt", "--drop-out-of-range"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_place_with_backup_suffix() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("notes.txt");
+        std::fs::write(&input, "hello\n")?;
+
+        let args = Args::try_parse_from([
+            "app",
+            "--input",
+            &input.to_string_lossy(),
+            "--in-place",
+            "--backup=.orig",
+        ])?;
+        App::new(Config::from_args(args)).run()?;
+
+        assert_eq!(std::fs::read_to_string(&input)?, "HELLO\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("notes.txt.orig"))?,
+            "hello\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_suffix_defaults_to_bak() {
+        let args = Args::try_parse_from(["app", "--input", "in.txt", "--backup"]).unwrap();
+        assert_eq!(args.backup.as_deref(), Some(".bak"));
+
+        let args = Args::try_parse_from(["app", "--input", "in.txt"]).unwrap();
+        assert_eq!(args.backup, None);
+    }
+
+    #[test]
+    fn test_in_place_rejects_stdin() {
+        let app = in_place_app(Path::new("-"), "");
+
+        let err = app.run().unwrap_err();
+
+        assert!(
+            err.to_string().contains("--in-place cannot edit stdin"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_conflicting_backup_suffixes_rejected() {
+        let app = App::new(Config {
+            backup: Some(".bak".to_string()),
+            ..in_place_app(Path::new("notes.txt"), ".orig").config
+        });
+
+        let err = app.run().unwrap_err();
+
+        assert!(err.to_string().contains("different backups"), "{}", err);
+    }
+
+    fn diff_app(inputs: Vec<String>, color: ColorChoice) -> App {
+        App::new(Config {
+            inputs,
+            diff: true,
+            color,
+            ..Config::default()
+        })
+    }
+
+    fn diffs(app: &App) -> Result<Vec<String>> {
+        app.process_all()?
+            .results
+            .into_iter()
+            .map(|result| {
+                result.outcome?;
+                Ok(result.captured.unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_identical_is_silent_success() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("upper.txt");
+        std::fs::write(&input, "ALREADY UPPER\n")?;
+        let app = diff_app(
+            vec![input.to_string_lossy().into_owned()],
+            ColorChoice::Never,
+        );
+
+        assert_eq!(diffs(&app)?, [""]);
+        app.run()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_shows_hunks_per_file() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let changed = dir.path().join("a.txt");
+        let same = dir.path().join("b.txt");
+        std::fs::write(&changed, "one\nTWO\nthree\n")?;
+        std::fs::write(&same, "SAME\n")?;
+        let inputs = vec![
+            changed.to_string_lossy().into_owned(),
+            same.to_string_lossy().into_owned(),
+        ];
+        let app = diff_app(inputs, ColorChoice::Never);
+
+        let diffs = diffs(&app)?;
+
+        let path = changed.to_string_lossy();
+        assert_eq!(
+            diffs[0],
+            format!("--- {path}\n+++ {path}\n@@ -1,3 +1,3 @@\n-one\n+ONE\n TWO\n-three\n+THREE\n")
+        );
+        assert_eq!(diffs[1], "");
+        let err = app.run().unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(AppError::WouldChange(1))));
+        assert_eq!(exit_code(&err), 1);
+        assert!(is_quiet(&err));
+        assert_eq!(std::fs::read_to_string(&changed)?, "one\nTWO\nthree\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_color() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("a.txt");
+        std::fs::write(&input, "x\n")?;
+        let app = diff_app(
+            vec![input.to_string_lossy().into_owned()],
+            ColorChoice::Always,
+        );
+
+        let diff = diffs(&app)?.remove(0);
+
+        assert!(diff.contains("\x1b[36m@@ -1 +1 @@\x1b[0m\n"), "{:?}", diff);
+        assert!(diff.contains("\x1b[31m-x\x1b[0m\n"), "{:?}", diff);
+        assert!(diff.contains("\x1b[32m+X\x1b[0m\n"), "{:?}", diff);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_binary_content_summarized() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("blob.bin");
+        std::fs::write(&input, "abc\0def\n")?;
+        let app = diff_app(
+            vec![input.to_string_lossy().into_owned()],
+            ColorChoice::Never,
+        );
+
+        assert_eq!(
+            diffs(&app)?,
+            [format!("Binary file {} would change\n", input.display())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_times_out() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("a.txt");
+        std::fs::write(&input, "x\n")?;
+        let app = App::new(Config {
+            timeout: Some(Duration::ZERO),
+            ..diff_app(
+                vec![input.to_string_lossy().into_owned()],
+                ColorChoice::Never,
+            )
+            .config
+        });
+
+        let err = app.run().unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<AppError>(),
+            Some(AppError::Timeout(d)) if d.is_zero()
+        ));
+        assert_eq!(exit_code(&err), 124);
+        Ok(())
+    }
+
+    fn layout_app(
+        mode: Mode,
+        newline: Newline,
+        strip_bom: bool,
+        final_newline: FinalNewline,
+    ) -> App {
+        App::new(Config {
+            mode,
+            newline,
+            strip_bom,
+            final_newline,
+            ..Config::default()
+        })
+    }
+
+    /// Runs `input` through the streaming and the buffered path, which must
+    /// agree byte for byte
+    fn normalized(app: &App, input: &str) -> Result<String> {
+        let mut streamed = Vec::new();
+        app.process_streaming(input.as_bytes(), &mut streamed)?;
+        let buffered = app.process(input)?;
+        assert_eq!(String::from_utf8(streamed)?, buffered, "input {:?}", input);
+        Ok(buffered)
+    }
+
+    #[test]
+    fn test_crlf_to_lf_is_byte_exact() -> Result<()> {
+        let app = layout_app(Mode::Lower, Newline::Lf, false, FinalNewline::Preserve);
+
+        assert_eq!(
+            normalized(&app, "A\r\nB\r\n\r\nC")?.as_bytes(),
+            b"a\nb\n\nc"
+        );
+        // Mixed endings and a lone `\r`, which is not a line break
+        assert_eq!(normalized(&app, "x\ry\r\nz\n")?.as_bytes(), b"x\ry\nz\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_lf_to_crlf_survives_sort() -> Result<()> {
+        let app = layout_app(Mode::Sort, Newline::Crlf, false, FinalNewline::Preserve);
+
+        assert_eq!(app.process("pear\napple\r\n")?, "apple\r\npear\r\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_bom_stripped_only_at_start() -> Result<()> {
+        let app = layout_app(Mode::Upper, Newline::Preserve, true, FinalNewline::Preserve);
+
+        assert_eq!(
+            normalized(&app, "\u{feff}one\ntwo \u{feff} mid\n\u{feff}three\n")?,
+            "ONE\nTWO \u{feff} MID\n\u{feff}THREE\n"
+        );
+        // Without the option the BOM passes through untouched
+        let kept = layout_app(
+            Mode::Upper,
+            Newline::Preserve,
+            false,
+            FinalNewline::Preserve,
+        );
+        assert_eq!(normalized(&kept, "\u{feff}one\n")?, "\u{feff}ONE\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_final_newline_ensure_adds_exactly_one() -> Result<()> {
+        let app = layout_app(Mode::Upper, Newline::Preserve, false, FinalNewline::Ensure);
+
+        assert_eq!(normalized(&app, "a\nb")?, "A\nB\n");
+        assert_eq!(normalized(&app, "a\nb\n")?, "A\nB\n");
+        assert_eq!(normalized(&app, "a\n\n")?, "A\n\n");
+        assert_eq!(normalized(&app, "a\r\nb")?, "A\r\nB\r\n");
+        assert_eq!(normalized(&app, "")?, "");
+
+        let crlf = layout_app(Mode::Upper, Newline::Crlf, false, FinalNewline::Ensure);
+        assert_eq!(normalized(&crlf, "single")?, "SINGLE\r\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_final_newline_strip() -> Result<()> {
+        let app = layout_app(Mode::Upper, Newline::Preserve, false, FinalNewline::Strip);
+
+        assert_eq!(normalized(&app, "a\r\nb\r\n")?, "A\r\nB");
+        assert_eq!(normalized(&app, "a\nb")?, "A\nB");
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_layout_detected() -> Result<()> {
+        let input = "\u{feff}one\r\ntwo\r\nthree\nfour";
+        let expected = TextLayout {
+            bom: true,
+            crlf_lines: 2,
+            lf_lines: 1,
+            final_newline: false,
+        };
+        assert_eq!(TextLayout::detect(input), expected);
+
+        // Normalizing does not hide what the input looked like
+        let app = layout_app(Mode::Upper, Newline::Lf, true, FinalNewline::Ensure);
+        let stats = app.process_streaming(input.as_bytes(), io::sink())?;
+        assert_eq!(stats.layout, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_layered_config_deep_merges() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let base = dir.path().join("base.toml");
+        let overlay = dir.path().join("prod.toml");
+        std::fs::write(
+            &base,
+            "mode = \"upper\"\nworkers = 4\n\n\
+             [server]\nhost = \"127.0.0.1\"\nport = 8080\n\n\
+             [server.tls]\nenabled = false\ncert = \"dev.pem\"\n",
+        )?;
+        std::fs::write(
+            &overlay,
+            "workers = 16\n\n[server]\nport = 443\n\n[server.tls]\nenabled = true\n",
+        )?;
+
+        let args = Args::try_parse_from([
+            "my_app",
+            "-i",
+            "in.txt",
+            "--config",
+            &base.to_string_lossy(),
+            &overlay.to_string_lossy(),
+        ])?;
+        let settings = Config::from_args(args).load_settings()?;
+
+        let expected: toml::Table = "mode = \"upper\"\nworkers = 16\n\n\
+             [server]\nhost = \"127.0.0.1\"\nport = 443\n\n\
+             [server.tls]\nenabled = true\ncert = \"dev.pem\"\n"
+            .parse()?;
+        assert_eq!(settings, expected);
+        Ok(())
+    }
+
+    /// Base settings, a `processor` table the app does not know, and two
+    /// profiles overriding them
+    const PROFILES: &str = "mode = \"upper\"\njobs = 2\n\n\
+                            [processor]\nthreads = 4\nbatch = 100\n\n\
+                            [profile.dev]\nmode = \"lower\"\n\n\
+                            [profile.dev.processor]\nthreads = 1\n\n\
+                            [profile.prod]\njobs = 16\n";
+
+    /// Settings parsed from [`PROFILES`] with `profile` applied, and the
+    /// config it was applied to
+    fn with_profile(profile: Option<&str>) -> Result<(Config, toml::Table)> {
+        let mut config = Config {
+            profile: profile.map(str::to_string),
+            ..Config::default()
+        };
+        let mut settings: toml::Table = PROFILES.parse()?;
+        config.apply_profile(&mut settings)?;
+        Ok((config, settings))
+    }
+
+    #[test]
+    fn test_base_settings_without_profile() -> Result<()> {
+        let (config, settings) = with_profile(None)?;
+        let base: toml::Table = "mode = \"upper\"\njobs = 2\n\n\
+                                 [processor]\nthreads = 4\nbatch = 100\n"
+            .parse()?;
+        assert_eq!(settings, base);
+        assert!(config.profile_overrides.is_empty());
+        assert_eq!(
+            Settings::from_table(settings)?.unknown_keys(),
+            ["processor"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_overrides_base_settings() -> Result<()> {
+        let (mut config, settings) = with_profile(Some("prod"))?;
+        assert_eq!(config.profile_overrides, ["jobs"]);
+        config.apply_settings(&Settings::from_table(settings)?, |_| false)?;
+        assert_eq!((config.mode, config.jobs), (Mode::Upper, 16));
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_overrides_nested_tables() -> Result<()> {
+        let (config, settings) = with_profile(Some("dev"))?;
+        let expected: toml::Table = "mode = \"lower\"\njobs = 2\n\n\
+                                     [processor]\nthreads = 1\nbatch = 100\n"
+            .parse()?;
+        assert_eq!(settings, expected);
+        assert_eq!(config.profile_overrides, ["mode", "processor.threads"]);
+
+        let shown = App::new(config).diagnostics();
+        assert!(
+            shown.contains("\n  Profile: dev (overrides: mode, processor.threads)"),
+            "{}",
+            shown
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_profile_lists_available() {
+        let err = with_profile(Some("staging")).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(AppError::UnknownProfile { name, .. }) if name == "staging"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "unknown config profile staging; available: dev, prod"
+        );
+        assert_eq!(exit_code(&err), 2);
+    }
+
+    #[test]
+    fn test_later_layer_replaces_non_tables() {
+        let mut base: toml::Table = "tags = [\"a\", \"b\"]\nlimits = { max = 1 }"
+            .parse()
+            .unwrap();
+        let overlay: toml::Table = "tags = [\"c\"]\nlimits = 5".parse().unwrap();
+
+        merge_settings(&mut base, overlay);
+
+        assert_eq!(base, "tags = [\"c\"]\nlimits = 5".parse().unwrap());
+    }
+
+    #[test]
+    fn test_config_defaults_to_single_file() {
+        let args = Args::try_parse_from(["my_app", "-i", "in.txt"]).unwrap();
+        assert_eq!(args.config, ["config.toml"]);
+    }
+
+    #[test]
+    fn test_missing_config_layer_is_named() {
+        let config = Config {
+            config_paths: vec!["does-not-exist.toml".to_string()],
+            ..Config::default()
+        };
+        let err = config.load_settings().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Cannot read config file: does-not-exist.toml"));
+    }
+
+    /// Config as the command line leaves it with no options but an input
+    fn cli_defaults() -> Config {
+        Config::from_args(Args::try_parse_from(["my_app", "-i", "in.txt"]).unwrap())
+    }
+
+    #[test]
+    fn test_init_configs_load_cleanly() -> Result<()> {
+        for full in [false, true] {
+            let settings = Settings::from_table(render_config(full).parse()?)?;
+            assert_eq!(settings.unknown_keys(), Vec::<&str>::new());
+
+            // The defaults written out are the ones the flags already have
+            let mut config = cli_defaults();
+            config.apply_settings(&settings, |_| false)?;
+            assert_eq!(format!("{:?}", config), format!("{:?}", cli_defaults()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_config_lists_every_setting() -> Result<()> {
+        // Serializing the defaults names every field, set or not
+        let fields = serde_json::to_value(Settings::default())?;
+        let full = render_config(true);
+        for key in fields.as_object().unwrap().keys() {
+            let listed = full.lines().any(|line| {
+                line.trim_start_matches("# ")
+                    .starts_with(&format!("{} = ", key))
+            });
+            assert!(listed, "{} missing from:\n{}", key, full);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_init_refuses_to_overwrite() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "mode = \"lower\"\n")?;
+        let init = |force| Commands::Init {
+            path: path.clone(),
+            full: false,
+            force,
+        };
+
+        let err = init(false).run(&mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(AppError::ConfigExists(_))
+        ));
+        assert_eq!(exit_code(&err), 2);
+        assert_eq!(std::fs::read_to_string(&path)?, "mode = \"lower\"\n");
+
+        init(true).run(&mut Vec::new())?;
+        assert_eq!(std::fs::read_to_string(&path)?, render_config(false));
+        Ok(())
+    }
+
+    /// Runs `config validate` on a file holding `text`, returning its
+    /// output and result
+    fn validate(text: &str, strict: bool) -> (String, Result<()>) {
+        validate_profile(text, None, strict)
+    }
+
+    /// [`validate`] with `--profile`
+    fn validate_profile(text: &str, profile: Option<&str>, strict: bool) -> (String, Result<()>) {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, text).unwrap();
+        let validate = Commands::Config {
+            action: ConfigAction::Validate {
+                config: vec![path.to_string_lossy().into_owned()],
+                profile: profile.map(str::to_string),
+                strict,
+            },
+        };
+        let mut out = Vec::new();
+        let result = validate.run(&mut out);
+        let out = String::from_utf8(out).unwrap();
+        (out.replace(&*path.to_string_lossy(), "config.toml"), result)
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let (out, result) = validate(
+            "mode = \"sideways\"\n\
+             jobs = 4\n\
+             max-file-size = \"lots\"\n\
+             split-lines = 1000\n\
+             split-bytes = \"1MB\"\n",
+            false,
+        );
+        let err = result.unwrap_err();
+        assert_eq!(exit_code(&err), 1);
+        assert!(is_quiet(&err));
+
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", out);
+        assert!(lines[0].starts_with("config.toml:1: error: mode: unknown variant `sideways`"));
+        assert!(lines[1].starts_with("config.toml:3: error: max-file-size: Invalid max-file-size"));
+        assert_eq!(
+            lines[2],
+            "config.toml:5: error: split-lines: cannot be set together with split-bytes"
+        );
+        assert_eq!(lines[3], "config.toml: invalid (3 error(s))");
+    }
+
+    #[test]
+    fn test_validate_unknown_keys_warn_unless_strict() {
+        let text = "mode = \"lower\"\nmood = \"upper\"\n";
+        let warning = "config.toml:2: warning: mood: unknown key";
+
+        let (out, result) = validate(text, false);
+        assert!(result.is_ok());
+        assert_eq!(
+            out,
+            format!(
+                "{}\nconfig.toml: valid (1 setting(s), 1 warning(s))\n",
+                warning
+            )
+        );
+
+        let (out, result) = validate(text, true);
+        assert_eq!(exit_code(&result.unwrap_err()), 1);
+        assert_eq!(
+            out,
+            "config.toml:2: error: mood: unknown key\nconfig.toml: invalid (1 error(s))\n"
+        );
+    }
+
+    #[test]
+    fn test_validate_checks_profiles() {
+        let text = "split-lines = 1000\n\n\
+                    [profile.dev]\nmode = \"sideways\"\n\n\
+                    [profile.prod]\nsplit-bytes = \"1MB\"\n";
+
+        // Every profile's keys are checked; only the selected one is merged
+        let (out, result) = validate_profile(text, None, false);
+        assert!(result.is_err());
+        assert!(out.starts_with("config.toml:4: error: profile.dev.mode: unknown variant"));
+        assert!(!out.contains("split-bytes"), "{}", out);
+
+        let (out, _) = validate_profile(text, Some("prod"), false);
+        assert!(
+            out.contains(
+                "config.toml:7: error: split-lines: cannot be set together with split-bytes"
+            ),
+            "{}",
+            out
+        );
+
+        let (out, _) = validate_profile("mode = \"lower\"\n", Some("prod"), false);
+        assert!(
+            out.starts_with("config.toml: error: unknown config profile prod; available: none"),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_validate_accepts_init_config() {
+        let (out, result) = validate(&render_config(true), true);
+        assert!(result.is_ok());
+        let settings = CONFIG_KEYS.iter().filter(|(_, _, set)| *set).count();
+        assert_eq!(
+            out,
+            format!("config.toml: valid ({} setting(s))\n", settings)
+        );
+    }
+
+    /// Inputs for `stats` with their exact counts: lines, words, chars,
+    /// bytes, longest line and encoding
+    fn stats_fixtures() -> Vec<(&'static [u8], TextStats)> {
+        let stats = |lines, words, chars, bytes, longest_line, encoding| TextStats {
+            lines,
+            words,
+            chars,
+            bytes,
+            longest_line,
+            encoding,
+            fields: None,
+            separator: None,
+        };
+        vec![
+            (b"", TextStats::default()),
+            (b"hello world\n", stats(1, 2, 12, 12, 11, Encoding::Ascii)),
+            // CRLF terminators are not part of the line
+            (
+                b"one two\r\nthree\r\n",
+                stats(2, 3, 16, 16, 7, Encoding::Ascii),
+            ),
+            // A lone `\r` is, and so is a last line without a terminator
+            (b"a\rb\nlast", stats(2, 3, 8, 8, 4, Encoding::Ascii)),
+            // Punctuation and emoji are not words; multibyte characters
+            // count once
+            (
+                "Grüße, naïve café ☕\r\n".as_bytes(),
+                stats(1, 3, 21, 27, 19, Encoding::Utf8),
+            ),
+            (
+                "\u{feff}hi there\n".as_bytes(),
+                stats(1, 2, 10, 12, 9, Encoding::Utf8Bom),
+            ),
+            (b"ok \xff\xfe\n", stats(1, 1, 6, 6, 5, Encoding::Unknown)),
+            (
+                b"\xff\xfeh\x00i\x00",
+                stats(1, 2, 6, 6, 6, Encoding::Utf16Le),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_stats_fixture_counts() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        for (number, (input, expected)) in stats_fixtures().into_iter().enumerate() {
+            let path = dir.path().join(format!("fixture-{}.txt", number));
+            std::fs::write(&path, input)?;
+            assert_eq!(
+                TextStats::read(File::open(&path)?, None)?,
+                expected,
+                "{:?}",
+                String::from_utf8_lossy(input)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_ignore_chunk_boundaries() {
+        // Every way of cutting each fixture into chunks of up to 3 bytes,
+        // which splits every multibyte character, word and CRLF somewhere
+        for (input, expected) in stats_fixtures() {
+            for first in 0..=input.len() {
+                for size in 1..=3 {
+                    let mut counter = StatsCounter::default();
+                    counter.update(&input[..first]);
+                    for chunk in input[first..].chunks(size) {
+                        counter.update(chunk);
+                    }
+                    assert_eq!(
+                        counter.finish(),
+                        expected,
+                        "{:?} cut at {} then every {}",
+                        String::from_utf8_lossy(input),
+                        first,
+                        size
+                    );
+                }
+            }
+        }
+    }
+
+    /// Writes `lines` copies of a mixed-script CRLF line to a file, counts
+    /// it with `StatsCounter` one read buffer at a time, and checks the
+    /// counter never holds more than the words it cannot finish yet
+    fn assert_stats_stream(lines: u64) -> Result<()> {
+        const LINE: &str = "The quick brown fox, naïve café ☕ 42\r\n";
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("big.txt");
+        let mut file = BufWriter::new(File::create(&path)?);
+        for _ in 0..lines {
+            file.write_all(LINE.as_bytes())?;
+        }
+        file.into_inner()?.sync_all()?;
+
+        let mut reader = File::open(&path)?;
+        let mut counter = StatsCounter::default();
+        let mut buf = vec![0; STREAM_BUFFER_SIZE];
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            counter.update(&buf[..read]);
+            assert!(
+                counter.pending.len() < LINE.len(),
+                "{}",
+                counter.pending.len()
+            );
+            assert!(counter.partial.len() < 4);
+        }
+
+        let chars = LINE.chars().count() as u64;
+        assert_eq!(
+            counter.finish(),
+            TextStats {
+                lines,
+                words: 7 * lines,
+                chars: chars * lines,
+                bytes: LINE.len() as u64 * lines,
+                longest_line: chars - 2,
+                encoding: Encoding::Utf8,
+                fields: None,
+                separator: None,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_sniff_separator() -> Result<()> {
+        // Tab-separated, with commas inside the name column
+        let tsv = "id\tname\tcity\n1\tDoe, Jane\tParis\n2\tRoe, Rick\tLyon\n";
+        // Comma-separated, with a pipe and a tab in single values and a
+        // blank line, which has no fields
+        let csv = "id,cmd,note\n1,ls | wc,ok\n2,echo,tab\there\n\n3,true,\n";
+        let cases = [
+            (tsv, Separator::Auto, Some('\t'), 9),
+            (csv, Separator::Auto, Some(','), 12),
+            ("a|b|c\nd|e|f", Separator::Auto, Some('|'), 6),
+            // No candidate on every line: each line is one field
+            ("one, two\nthree\n", Separator::Auto, None, 2),
+            // Forced, even where another candidate would be sniffed
+            (csv, Separator::Char('\t'), Some('\t'), 5),
+        ];
+        for (input, separator, expected, fields) in cases {
+            let stats = TextStats::read(input.as_bytes(), Some(separator))?;
+            assert_eq!(
+                (stats.separator, stats.fields),
+                (expected, Some(fields)),
+                "{:?}",
+                input
+            );
+
+            // Byte by byte, which cuts every line
+            let mut counter = StatsCounter::with_separator(separator);
+            for byte in input.as_bytes() {
+                counter.update(std::slice::from_ref(byte));
+            }
+            assert_eq!(counter.finish(), stats);
+        }
+
+        assert_eq!("auto".parse(), Ok(Separator::Auto));
+        assert_eq!("tab".parse(), Ok(Separator::Char('\t')));
+        assert_eq!(";".parse(), Ok(Separator::Char(';')));
+        assert!("::".parse::<Separator>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_table_shows_fields() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let path = dir.path().join("table.tsv");
+        std::fs::write(&path, "a\tb\nc\td\n")?;
+        let mut out = Vec::new();
+        Commands::Stats {
+            inputs: vec![path.to_string_lossy().into_owned()],
+            format: OutputFormat::Text,
+            separator: Some(Separator::Auto),
+        }
+        .run(&mut out)?;
+
+        let out = String::from_utf8(out)?;
+        let rows: Vec<Vec<&str>> = out
+            .lines()
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(
+            rows[0],
+            [
+                "lines",
+                "words",
+                "chars",
+                "bytes",
+                "longest",
+                "fields",
+                "separator",
+                "encoding",
+                "input"
+            ]
+        );
+        assert_eq!(rows[1][..8], ["2", "4", "8", "8", "3", "4", "tab", "ascii"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_stream_in_bounded_memory() -> Result<()> {
+        // 4MB; the ignored test below repeats this at 200MB
+        assert_stats_stream(100_000)
+    }
+
+    #[test]
+    #[ignore = "writes a 200MB file; run with --release -- --ignored"]
+    fn test_stats_stream_200mb() -> Result<()> {
+        assert_stats_stream(5_000_000)
+    }
+
+    #[test]
+    fn test_settings_yield_to_command_line() -> Result<()> {
+        let table = "mode = \"lower\"\njobs = 3\ntimeout = \"30s\"\nworkers = 4\n".parse()?;
+        let settings = Settings::from_table(table)?;
+        assert_eq!(settings.unknown_keys(), ["workers"]);
+
+        let args = Args::try_parse_from(["my_app", "-i", "in.txt", "--mode", "upper"])?;
+        let mut config = Config::from_args(args);
+        config.apply_settings(&settings, |id| id == "mode")?;
+        assert_eq!(config.mode, Mode::Upper);
+        assert_eq!(config.jobs, 3);
+        assert_eq!(config.timeout, Some(Duration::from_secs(30)));
+
+        let bad = Settings::from_table("max-file-size = \"huge\"".parse()?)?;
+        let err = cli_defaults().apply_settings(&bad, |_| false).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid max-file-size in config file"));
+        Ok(())
+    }
+
+    fn sha256_hex(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    #[test]
+    fn test_verify_input_mismatch_exits_3() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt");
+        std::fs::write(&input, "tampered\n")?;
+        let expected = sha256_hex(b"original\n");
+        let app = App::new(Config {
+            verify_input: Some(expected.clone()),
+            ..file_app(&input, &output, Mode::Upper, false).config
+        });
+
+        let err = app.run().unwrap_err();
+
+        assert_eq!(exit_code(&err), 3);
+        let message = format!("{:#}", err);
+        assert!(message.contains(&expected), "{}", message);
+        assert!(message.contains(&sha256_hex(b"tampered\n")), "{}", message);
+        assert!(!output.exists());
+
+        let verified = App::new(Config {
+            verify_input: Some(sha256_hex(b"tampered\n")),
+            ..app.config
+        });
+        verified.run()?;
+        assert_eq!(std::fs::read_to_string(&output)?, "TAMPERED\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_input_checked_in_diff_mode() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        std::fs::write(&input, "tampered\n")?;
+        let app = App::new(Config {
+            verify_input: Some(sha256_hex(b"original\n")),
+            ..diff_app(
+                vec![input.to_string_lossy().into_owned()],
+                ColorChoice::Never,
+            )
+            .config
+        });
+
+        let err = app.run().unwrap_err();
+
+        assert_eq!(exit_code(&err), 3);
+        assert!(format!("{:#}", err).contains(&sha256_hex(b"tampered\n")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_input_parses_hex() {
+        let upper = "AB".repeat(32);
+        let args =
+            Args::try_parse_from(["my_app", "-i", "in.txt", "--verify-input", &upper]).unwrap();
+        assert_eq!(args.verify_input, Some("ab".repeat(32)));
+
+        for bad in ["abc", &"zz".repeat(32)] {
+            let err = Args::try_parse_from(["my_app", "-i", "in.txt", "--verify-input", bad])
+                .unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+        }
+    }
+
+    #[test]
+    fn test_emit_checksum_per_output() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let out_dir = dir.path().join("out");
+        std::fs::create_dir_all(&out_dir)?;
+        let app = App::new(Config {
+            emit_checksum: true,
+            ..batch_app(write_inputs(dir.path(), 3)?, &out_dir, 2).config
+        });
+
+        app.run()?;
+
+        for n in 0..3 {
+            let output = out_dir.join(format!("input-{:03}.txt", n));
+            let line = std::fs::read_to_string(backup_path(&output, ".sha256"))?;
+            // `sha256sum -c` reads `<hex>  <name>` with a binary-safe two
+            // space separator, one entry per line
+            let (digest, name) = line
+                .strip_suffix('\n')
+                .and_then(|line| line.split_once("  "))
+                .expect("not in sha256sum format");
+            assert_eq!(name, format!("input-{:03}.txt", n));
+            assert_eq!(digest, sha256_hex(&std::fs::read(&output)?));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_covers_compressed_bytes() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let input = dir.path().join("input.txt");
+        let output = dir.path().join("output.txt.gz");
+        std::fs::write(&input, "zip me\n")?;
+        let app = App::new(Config {
+            emit_checksum: true,
+            ..file_app(&input, &output, Mode::Upper, false).config
+        });
+
+        app.run()?;
+
+        let line = std::fs::read_to_string(dir.path().join("output.txt.gz.sha256"))?;
+        assert_eq!(
+            line,
+            format!("{}  output.txt.gz\n", sha256_hex(&std::fs::read(&output)?))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_progress_reader_counts_every_byte() -> Result<()> {
+        let data = "a line of input\n".repeat(1000);
+        let bar = ProgressBar::hidden();
+        let mut reader = ProgressReader {
+            // Small buffer so reads span many refills
+            inner: BufReader::with_capacity(64, data.as_bytes()),
+            bar: bar.clone(),
+        };
+
+        // Mix the BufRead and Read paths, as the line loop and decoders do
+        let mut line = Vec::new();
+        reader.read_until(b'\n', &mut line)?;
+        assert_eq!(bar.position(), line.len() as u64);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest)?;
+
+        assert_eq!(bar.position(), data.len() as u64);
+        assert_eq!(line.len() + rest.len(), data.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_progress_counts_compressed_bytes() -> Result<()> {
+        let compressed = gzip(&"compress me\n".repeat(500));
+        let bar = ProgressBar::hidden();
+        let reader = ProgressReader {
+            inner: BufReader::new(compressed.as_slice()),
+            bar: bar.clone(),
+        };
+
+        let mut text = String::new();
+        MultiGzDecoder::new(reader).read_to_string(&mut text)?;
+
+        assert_eq!(text.len(), 6000);
+        assert_eq!(bar.position(), compressed.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_progress_never_leaves_logs_unchanged() -> Result<()> {
+        fn logs(writer: impl for<'a> MakeWriter<'a> + Send + Sync + 'static, app: &App) {
+            let subscriber = tracing_subscriber::fmt()
+                .with_max_level(tracing::Level::DEBUG)
+                .with_ansi(false)
+                .without_time()
+                .with_writer(writer)
+                .finish();
+            tracing::subscriber::with_default(subscriber, || app.run()).unwrap();
+        }
+
+        let dir = tempfile::TempDir::new()?;
+        let inputs = write_inputs(dir.path(), 3)?;
+        let app = |out: &str| {
+            let out_dir = dir.path().join(out);
+            std::fs::create_dir(&out_dir).unwrap();
+            App::new(Config {
+                progress: ProgressChoice::Never,
+                ..batch_app(inputs.clone(), &out_dir, 1).config
+            })
+        };
+
+        let direct = LogCapture::default();
+        let sink = direct.clone();
+        let direct_app = app("direct");
+        logs(move || sink.clone(), &direct_app);
+
+        let through_bars = LogCapture::default();
+        let sink = through_bars.clone();
+        let app = app("through-bars");
+        let writer = LogWriter {
+            progress: app.progress.clone(),
+            inner: move || sink.clone(),
+        };
+        logs(writer, &app);
+
+        let normalize = |logs: String| {
+            logs.replace("through-bars", "direct")
+                .replace(app.run_id(), direct_app.run_id())
+        };
+        assert!(direct.contents().contains("Processing 3 inputs"));
+        assert_eq!(normalize(through_bars.contents()), direct.contents());
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+//! Heap allocation benchmarks using dhat
+//!
+//! Save as `benches/alloc_bench.rs` in the application crate. Counts the
+//! heap allocations made per call by `Calculator::add`, `MyLib::process`,
+//! `App::process` and an ASCII uppercase of an already uppercase line, and
+//! exits with a failure if `Calculator::add` or the uppercase allocates at
+//! all or another count rises more than 5% above its baseline. Running it
+//! in CI turns an allocation regression into a failed build.
+//!
+//! Add to Cargo.toml:
+//! [features]
+//! bench_alloc = ["dep:dhat"]
+//!
+//! [dependencies]
+//! dhat = { version = "0.3", optional = true }
+//!
+//! [dev-dependencies]
+//! calculator = { path = "../calculator" }
+//!
+//! [[bench]]
+//! name = "alloc_bench"
+//! harness = false
+//! required-features = ["bench_alloc"]
+//!
+//! Run with `cargo bench --features bench_alloc --bench alloc_bench`. The
+//! feature keeps dhat's allocator out of every other build; plain
+//! `cargo bench` skips this bench. Calculator is the crate built from
+//! `test-template.rs`.
+
+use std::hint::black_box;
+
+use calculator::Calculator;
+use my_app::{App, CaseLocale, Config, Mode};
+use my_lib::MyLib;
+
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
+
+/// Calls averaged into each count
+const ITERATIONS: u64 = 1_000;
+
+/// Allowed growth over a baseline before the run fails
+const TOLERANCE: f64 = 0.05;
+
+/// Allocations per call when the baselines were last updated
+///
+/// Lower a baseline when an optimization brings a count down, so the guard
+/// keeps the improvement; raise it only for an intended change.
+const MYLIB_PROCESS_BASELINE: f64 = 1.0;
+const APP_PROCESS_BASELINE: f64 = 69.0;
+
+/// Average number of heap allocations one call of `f` makes
+fn allocations_per_call(mut f: impl FnMut()) -> f64 {
+    // The first call may allocate once for lazily initialized state
+    f();
+    let before = dhat::HeapStats::get().total_blocks;
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let after = dhat::HeapStats::get().total_blocks;
+    (after - before) as f64 / ITERATIONS as f64
+}
+
+/// Prints a count and fails the run if it exceeds `baseline` by more than
+/// [`TOLERANCE`]
+fn check(name: &str, allocations: f64, baseline: f64) {
+    let limit = baseline * (1.0 + TOLERANCE);
+    println!(
+        "{:<16} {:>8.2} allocations/call (baseline {}, limit {:.2})",
+        name, allocations, baseline, limit
+    );
+    dhat::assert!(
+        allocations <= limit,
+        "{} allocates {:.2} times per call, over the baseline of {} by more than {}%",
+        name,
+        allocations,
+        baseline,
+        TOLERANCE * 100.0
+    );
+}
+
+fn main() {
+    let _profiler = dhat::Profiler::builder().testing().build();
+
+    let calc = Calculator::new(2);
+    let add = allocations_per_call(|| {
+        black_box(calc.add(black_box(1.5), black_box(2.25)));
+    });
+    // A baseline of zero leaves no room: any allocation fails the run
+    check("Calculator::add", add, 0.0);
+
+    let lib = MyLib::new("bench").expect("valid config");
+    let process = allocations_per_call(|| {
+        black_box(lib.process(black_box("hello")).expect("non-empty input"));
+    });
+    check("MyLib::process", process, MYLIB_PROCESS_BASELINE);
+
+    let app = App::new(Config {
+        mode: Mode::Upper,
+        ..Config::default()
+    });
+    let input = "the quick brown fox\njumps over the lazy dog\n".repeat(16);
+    let transform = allocations_per_call(|| {
+        black_box(app.process(black_box(&input)).expect("valid input"));
+    });
+    check("App::process", transform, APP_PROCESS_BASELINE);
+
+    // `--case-locale ascii` hands back a line with nothing to change
+    // instead of copying it, unlike the Unicode mappings
+    let line = "THE QUICK BROWN FOX JUMPS OVER THE LAZY DOG";
+    let upper = allocations_per_call(|| {
+        black_box(Mode::Upper.apply_line_in(black_box(line), CaseLocale::Ascii));
+    });
+    check("ascii upper", upper, 0.0);
+}
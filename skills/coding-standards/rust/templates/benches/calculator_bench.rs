@@ -0,0 +1,89 @@
+//! Benchmarks for every `Calculator` operation
+//!
+//! Save as `benches/calculator_bench.rs` in the crate built from
+//! `test-template.rs`. Each operation is measured at precision 0, 2 and 8,
+//! since the rounding step scales with it.
+//!
+//! Add to Cargo.toml:
+//! [dev-dependencies]
+//! criterion = "0.5"
+//!
+//! [[bench]]
+//! name = "calculator_bench"
+//! harness = false
+//!
+//! Run all of them with `cargo bench`, or one operation with
+//! `cargo bench -- divide`. To compare two git revisions, save a named
+//! baseline on the first and compare against it on the second:
+//!
+//! ```text
+//! git checkout main
+//! cargo bench --bench calculator_bench -- --save-baseline main
+//! git checkout my-branch
+//! cargo bench --bench calculator_bench -- --baseline main
+//! ```
+//!
+//! Criterion prints the change against the baseline for each benchmark and
+//! flags the ones outside its noise threshold. Baselines are kept under
+//! `target/criterion/`.
+
+use std::time::Duration;
+
+use calculator::Calculator;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// Precisions every operation is measured at
+const PRECISIONS: [u32; 3] = [0, 2, 8];
+
+/// Benchmarks `op` once per precision, as `<name>/<precision>`
+fn bench_op<T>(c: &mut Criterion, name: &str, op: impl Fn(&Calculator) -> T) {
+    let mut group = c.benchmark_group(name);
+    for precision in PRECISIONS {
+        let calc = Calculator::new(precision);
+        group.bench_with_input(BenchmarkId::from_parameter(precision), &calc, |b, calc| {
+            b.iter(|| op(calc));
+        });
+    }
+    group.finish();
+}
+
+fn bench_add(c: &mut Criterion) {
+    bench_op(c, "add", |calc| {
+        calc.add(black_box(1.234_567_89), black_box(2.5))
+    });
+}
+
+fn bench_subtract(c: &mut Criterion) {
+    bench_op(c, "subtract", |calc| {
+        calc.subtract(black_box(5.5), black_box(2.345_678_91))
+    });
+}
+
+fn bench_multiply(c: &mut Criterion) {
+    bench_op(c, "multiply", |calc| {
+        calc.multiply(black_box(1.5), black_box(2.345_678_91))
+    });
+}
+
+fn bench_divide_success(c: &mut Criterion) {
+    bench_op(c, "divide_success", |calc| {
+        calc.divide(black_box(10.0), black_box(3.0))
+    });
+}
+
+fn bench_divide_zero(c: &mut Criterion) {
+    // Covers the error path, including allocating its message
+    bench_op(c, "divide_zero", |calc| {
+        calc.divide(black_box(10.0), black_box(0.0))
+    });
+}
+
+criterion_group! {
+    name = benches;
+    // 100 samples over 5 seconds per benchmark
+    config = Criterion::default()
+        .measurement_time(Duration::from_secs(5))
+        .sample_size(100);
+    targets = bench_add, bench_subtract, bench_multiply, bench_divide_success, bench_divide_zero
+}
+criterion_main!(benches);
@@ -0,0 +1,72 @@
+//! Instruction-count benchmarks for `Calculator` arithmetic
+//!
+//! Save as `benches/iai_calculator.rs` in the crate built from
+//! `test-template.rs`. iai-callgrind runs each benchmark once under
+//! Valgrind's callgrind and reports the instructions executed, so unlike
+//! the timings in `calculator_bench.rs` the counts do not change with
+//! system load.
+//!
+//! Add to Cargo.toml:
+//! [dev-dependencies]
+//! iai-callgrind = "0.14"
+//!
+//! [[bench]]
+//! name = "iai_calculator"
+//! harness = false
+//!
+//! Running it needs Valgrind and the runner binary, whose version must
+//! match the library:
+//!
+//! ```text
+//! cargo install iai-callgrind-runner --version 0.14.2
+//! cargo bench --bench iai_calculator
+//! ```
+//!
+//! `iai_baseline.json` holds the committed count for each benchmark, keyed
+//! `<function>/<id>`, and CI fails if a count rises more than 2% above it.
+//! After an intended change, regenerate it with:
+//!
+//! ```text
+//! cargo bench --bench iai_calculator -- --output-format=json \
+//!   | jq -s 'map({key: "\(.function_name)/\(.id)",
+//!                 value: (.callgrind_summary.callgrind_run.total.summary.Ir.metrics
+//!                         | .Left // .Both[0])}) | from_entries' \
+//!   > benches/iai_baseline.json
+//! ```
+
+use std::hint::black_box;
+
+use calculator::Calculator;
+use iai_callgrind::{library_benchmark, library_benchmark_group, main};
+
+#[library_benchmark]
+#[bench::small(2, 1.5, 2.25)]
+#[bench::large(2, 1.0e12, 3.333_333)]
+#[bench::fine(8, 0.1, 0.2)]
+fn bench_add(precision: u32, a: f64, b: f64) -> f64 {
+    black_box(Calculator::new(precision).add(a, b))
+}
+
+#[library_benchmark]
+#[bench::small(2, 1.5, 2.25)]
+#[bench::large(2, 1.0e12, 3.333_333)]
+#[bench::fine(8, 0.1, 0.2)]
+fn bench_multiply(precision: u32, a: f64, b: f64) -> f64 {
+    black_box(Calculator::new(precision).multiply(a, b))
+}
+
+#[library_benchmark]
+#[bench::small(2, 10.0, 3.0)]
+#[bench::fine(8, 1.0, 7.0)]
+// Covers the error path, including allocating its message
+#[bench::zero(2, 10.0, 0.0)]
+fn bench_divide(precision: u32, a: f64, b: f64) -> Result<f64, String> {
+    black_box(Calculator::new(precision).divide(a, b))
+}
+
+library_benchmark_group!(
+    name = arithmetic;
+    benchmarks = bench_add, bench_multiply, bench_divide
+);
+
+main!(library_benchmark_groups = arithmetic);
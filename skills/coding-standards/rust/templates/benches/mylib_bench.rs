@@ -0,0 +1,40 @@
+//! Throughput benchmark for `MyLib::process`
+//!
+//! Save as `benches/mylib_bench.rs` in the library crate and run with
+//! `cargo bench`. Criterion reports throughput in bytes per second for each
+//! input size, so regressions show up independent of input length.
+//!
+//! Add to Cargo.toml:
+//! [dev-dependencies]
+//! criterion = "0.5"
+//!
+//! [[bench]]
+//! name = "mylib_bench"
+//! harness = false
+//! required-features = ["std"]
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use my_lib::MyLib;
+
+/// Input sizes in bytes, labelled for the report
+const SIZES: [(&str, usize); 3] = [("10B", 10), ("1KB", 1024), ("1MB", 1024 * 1024)];
+
+fn bench_process(c: &mut Criterion) {
+    let lib = MyLib::new("bench").expect("valid config");
+    let mut group = c.benchmark_group("process");
+
+    for (label, size) in SIZES {
+        let input = "x".repeat(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(label), &input, |b, input| {
+            b.iter(|| black_box(lib.process(black_box(input))));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_process);
+criterion_main!(benches);
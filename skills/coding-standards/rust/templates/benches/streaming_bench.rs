@@ -0,0 +1,63 @@
+//! Throughput benchmarks for `App::process_streaming`
+//!
+//! Save as `benches/streaming_bench.rs` in the application crate. Each run
+//! streams an in-memory input of 10 MB, then 100 MB, through the `upper`
+//! transform into `io::sink()`, so the numbers cover line splitting and the
+//! transform but no disk I/O. Criterion reports both as bytes per second;
+//! the 100 MB figure should match the 10 MB one, and a clear drop means
+//! something grows with the input instead of staying per line.
+//!
+//! Add to Cargo.toml:
+//! [dev-dependencies]
+//! criterion = "0.5"
+//!
+//! [[bench]]
+//! name = "streaming_bench"
+//! harness = false
+//!
+//! Run with `cargo bench --bench streaming_bench`, then
+//! `ci/check_throughput.sh` to hold the results to a floor.
+//! `streaming_baseline.json` records what the last intended change
+//! measured, for comparison; update it from the script's output.
+
+use std::hint::black_box;
+use std::io::{self, Cursor};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use my_app::{App, Config, Mode};
+
+/// Input sizes in bytes, labelled for the report and the threshold check
+const SIZES: [(&str, usize); 2] = [("10MB", 10_000_000), ("100MB", 100_000_000)];
+
+/// Mixed-case text of exactly `size` bytes, in lines of typical length
+fn input(size: usize) -> Vec<u8> {
+    let line = b"The quick brown fox jumps over the lazy dog, 0123456789 times.\n";
+    line.iter().copied().cycle().take(size).collect()
+}
+
+fn bench_streaming(c: &mut Criterion) {
+    let app = App::new(Config {
+        mode: Mode::Upper,
+        ..Config::default()
+    });
+    let mut group = c.benchmark_group("process_streaming");
+    // A 100 MB pass takes long enough that the default 100 samples would
+    // run for minutes
+    group.sample_size(10);
+
+    for (label, size) in SIZES {
+        let input = input(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(label), &input, |b, input| {
+            b.iter(|| {
+                app.process_streaming(Cursor::new(black_box(input)), io::sink())
+                    .expect("valid input")
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_streaming);
+criterion_main!(benches);
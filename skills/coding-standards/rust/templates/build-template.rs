@@ -0,0 +1,74 @@
+//! Build script template for generated version constants
+//!
+//! Save as `build.rs` next to the library's `Cargo.toml`. Writes
+//! `$OUT_DIR/version_info.rs`, which `lib-template.rs` pulls in with
+//! `include!` and exposes through `built_info()`.
+//!
+//! Demonstrates:
+//! - Generating Rust source at build time
+//! - Reading build metadata from the environment with fallbacks
+//! - Telling cargo when to rerun the script
+//!
+//! The git hash comes from `GIT_HASH` when set (e.g. by CI), otherwise from
+//! `git rev-parse`. Builds without git, such as from a published crate,
+//! record no hash and `built_info()` reports `"unknown"`. The timestamp
+//! honors `SOURCE_DATE_EPOCH` for reproducible builds.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-env-changed=GIT_HASH");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    // Only watch HEAD when it exists; a missing path would rerun every build
+    if Path::new(".git/HEAD").exists() {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+    }
+
+    let version = env::var("CARGO_PKG_VERSION")?;
+    let git_hash = git_hash();
+    let timestamp = build_timestamp()?;
+
+    let out = PathBuf::from(env::var("OUT_DIR")?).join("version_info.rs");
+    fs::write(
+        out,
+        format!(
+            "/// Crate version from Cargo.toml\n\
+             pub const VERSION: &str = {:?};\n\
+             /// Commit the crate was built from, if known\n\
+             pub const GIT_HASH: Option<&str> = {:?};\n\
+             /// Build time in seconds since the Unix epoch\n\
+             pub const BUILD_TIMESTAMP: u64 = {};\n",
+            version, git_hash, timestamp
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// Returns the short commit hash, or `None` when git is unavailable
+fn git_hash() -> Option<String> {
+    if let Some(hash) = env::var("GIT_HASH").ok().filter(|h| !h.is_empty()) {
+        return Some(hash);
+    }
+
+    let output = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let hash = String::from_utf8(output.stdout).ok()?;
+    Some(hash.trim().to_string()).filter(|h| !h.is_empty())
+}
+
+fn build_timestamp() -> Result<u64, Box<dyn std::error::Error>> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => Ok(epoch.parse()?),
+        Err(_) => Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
+    }
+}
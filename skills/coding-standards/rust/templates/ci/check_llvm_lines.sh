@@ -0,0 +1,50 @@
+#!/usr/bin/env bash
+# Fails if the LLVM IR generated for the Calculator module exceeds a budget
+#
+# Save as `ci/check_llvm_lines.sh` in the crate built from
+# `test-template.rs`; needs `cargo install cargo-llvm-lines`. Every generic
+# function is compiled once per set of type arguments it is used with, and
+# the lines of LLVM IR they produce are what the compiler spends its time
+# optimizing. Counting them catches monomorphization bloat long before it
+# shows up as a slow build.
+#
+# Usage: ci/check_llvm_lines.sh [extra cargo llvm-lines args, e.g. -p calc]
+#
+#   LLVM_LINES_MAX      budget for the matching functions (default 350)
+#   LLVM_LINES_PATTERN  regex selecting the functions (default Calculator::)
+#
+# Finding the worst offenders: run `cargo llvm-lines --release --lib` and
+# read the table top down. It is sorted by lines; a function with a high
+# Copies count is generic and instantiated for many types, and one with many
+# lines but a single copy is just large. `--sort copies` surfaces the former.
+#
+# Mitigating them:
+# - Move the body of a generic function into a non-generic inner function
+#   and keep only the conversion generic, as std does with
+#   `fn open<P: AsRef<Path>>(path: P)` calling an `fn inner(path: &Path)`.
+# - Take `&dyn Trait` or `Box<dyn Trait>` instead of `impl Trait` where the
+#   call is not hot; one copy is compiled instead of one per type (type
+#   erasure, at the cost of a virtual call).
+# - Box closures handed to long generic helpers, e.g. `Box<dyn Fn(f64) ->
+#   f64>`, so the helper is not instantiated for every closure type.
+# - Mark large helpers `#[inline(never)]` so they are not copied into every
+#   caller.
+#
+# When a count drops, lower the default budget so the improvement is kept.
+
+set -euo pipefail
+
+max_lines="${LLVM_LINES_MAX:-350}"
+pattern="${LLVM_LINES_PATTERN:-Calculator::}"
+
+report="$(cargo llvm-lines --release --lib --filter "$pattern" "$@")"
+echo "$report"
+
+# Rows start with a line count; TOTAL counts every function, so skip it
+lines="$(awk '$1 ~ /^[0-9]+$/ && !/\(TOTAL\)/ { sum += $1 } END { print sum + 0 }' <<<"$report")"
+
+if ((lines > max_lines)); then
+    echo "Error: functions matching '$pattern' generate $lines lines of LLVM IR, over the budget of $max_lines" >&2
+    exit 1
+fi
+echo "OK: functions matching '$pattern' generate $lines lines of LLVM IR (budget $max_lines)"
@@ -0,0 +1,58 @@
+#!/usr/bin/env bash
+# Fails if `App::process_streaming` streams slower than a floor, or slows
+# down as the input grows
+#
+# Save as `ci/check_throughput.sh` in the application crate and run it after
+# `cargo bench --bench streaming_bench`; needs jq. It reads the mean time
+# criterion measured for each input size, turns it into MB/s (10^6 bytes a
+# second) and prints it next to the figure in `benches/streaming_baseline.json`.
+#
+# Usage: ci/check_throughput.sh
+#
+#   THROUGHPUT_MIN_MBPS  slowest acceptable throughput (default 100)
+#   SCALING_TOLERANCE    how far, as a fraction, the 100 MB throughput may
+#                        fall below the 10 MB one (default 0.2)
+#   CRITERION_DIR        criterion's output (default target/criterion)
+#
+# The floor is absolute because CI machines vary too much for a tight
+# comparison with the baseline, which was measured on a developer machine;
+# it catches an order-of-magnitude slowdown, such as buffering whole inputs
+# or a regex compiled per line. The scaling check needs no baseline at all:
+# per-line processing takes the same time per byte at any size, so a drop
+# at 100 MB means work that grows with the input. After an intended change,
+# copy the printed figures into the baseline file.
+
+set -euo pipefail
+
+min_mbps="${THROUGHPUT_MIN_MBPS:-100}"
+tolerance="${SCALING_TOLERANCE:-0.2}"
+criterion_dir="${CRITERION_DIR:-target/criterion}"
+baseline_file="benches/streaming_baseline.json"
+
+declare -A mbps
+failed=0
+for size in 10MB 100MB; do
+    estimates="$criterion_dir/process_streaming/$size/new/estimates.json"
+    if [[ ! -f "$estimates" ]]; then
+        echo "Error: $estimates not found; run cargo bench --bench streaming_bench first" >&2
+        exit 1
+    fi
+    bytes="${size%MB}000000"
+    # Criterion's times are in nanoseconds
+    mbps[$size]="$(jq --argjson bytes "$bytes" \
+        '$bytes / .mean.point_estimate * 1000 | floor' "$estimates")"
+    baseline="$(jq -r --arg name "process_streaming/$size" '.[$name] // "none"' "$baseline_file")"
+    echo "process_streaming/$size: ${mbps[$size]} MB/s (baseline $baseline, floor $min_mbps)"
+    if ((mbps[$size] < min_mbps)); then
+        echo "Error: process_streaming/$size streams ${mbps[$size]} MB/s, under the floor of $min_mbps" >&2
+        failed=1
+    fi
+done
+
+if jq -en --argjson small "${mbps[10MB]}" --argjson large "${mbps[100MB]}" \
+    --argjson tolerance "$tolerance" '$large < $small * (1 - $tolerance)' >/dev/null; then
+    echo "Error: 100 MB streams at ${mbps[100MB]} MB/s against ${mbps[10MB]} MB/s for 10 MB; throughput should not depend on input size" >&2
+    failed=1
+fi
+
+exit "$failed"
@@ -0,0 +1,47 @@
+#!/usr/bin/env bash
+# Runs the test suite under Miri to catch undefined behaviour in unsafe code
+#
+# Save as `ci/miri.sh` in the crate built from `lib-template.rs`. Miri
+# interprets the compiled tests instead of running them natively and checks
+# every memory access against Rust's rules: out-of-bounds and unaligned
+# reads, use after free, double frees, leaks, reads of uninitialized memory
+# and aliasing violations. A `// SAFETY:` comment is a claim; this is what
+# checks it. Unit tests, integration tests and doctests all run, 10 to 100
+# times slower than natively.
+#
+# Usage: ci/miri.sh [extra cargo miri test args, e.g. ffi_utils]
+#
+#   MIRI_TOOLCHAIN  nightly toolchain providing Miri (default nightly)
+#   MIRIFLAGS       Miri options (default -Zmiri-strict-provenance)
+#
+# Reading the output: Miri stops at the first problem in each test with
+# `error: Undefined Behavior: <what happened>`. The backtrace below it
+# starts in core or std; the first frame in this crate is the unsafe block
+# at fault. `help:` lines name the rule that was broken, and for aliasing
+# errors point at where the pointer was created and where it was
+# invalidated. `error: memory leaked` after the tests lists allocations
+# never freed, with where each was made. `unsupported operation` is not
+# undefined behaviour: Miri cannot emulate that call (a C function,
+# spawning a process, most system calls), so the test is marked
+# `#[cfg_attr(miri, ignore = "reason")]`, as the trybuild tests are.
+#
+# When an unsafe block causes undefined behaviour:
+# - Treat it as a bug even if the test passes natively; the optimizer is
+#   free to break it in the next compiler release.
+# - Check the block's `// SAFETY:` comment against each condition in the
+#   `# Safety` section of what it calls; the condition that does not hold
+#   is the bug. Fix the code or the caller, then correct the comment.
+# - Prefer shrinking the unsafe code: a safe API (`slice::get` instead of
+#   `ptr.add(i).read()`), or a weaker operation (`read_unaligned` for
+#   packed data).
+# - Keep the test that found it, so Miri keeps checking the fix. Never
+#   ignore a test under Miri because it reports undefined behaviour.
+
+set -euo pipefail
+
+toolchain="${MIRI_TOOLCHAIN:-nightly}"
+export MIRIFLAGS="${MIRIFLAGS:--Zmiri-strict-provenance}"
+
+rustup toolchain install "$toolchain" --profile minimal --component miri
+cargo "+$toolchain" miri setup
+cargo "+$toolchain" miri test "$@"
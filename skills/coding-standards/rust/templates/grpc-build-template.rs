@@ -0,0 +1,26 @@
+//! Build script template for gRPC code generation
+//!
+//! Save as `build.rs` next to `Cargo.toml`. Compiles `proto/processor.proto`
+//! into Rust stubs that `grpc-service-template.rs` pulls in with
+//! `tonic::include_proto!`.
+//!
+//! Add to Cargo.toml:
+//! [build-dependencies]
+//! tonic-build = "0.12"
+//! protoc-bin-vendored = "3"
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Use a vendored protoc so builds don't depend on a system install
+    if std::env::var_os("PROTOC").is_none() {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    }
+
+    println!("cargo:rerun-if-changed=proto/processor.proto");
+
+    tonic_build::configure()
+        .build_server(true)
+        .build_client(true)
+        .compile_protos(&["proto/processor.proto"], &["proto"])?;
+
+    Ok(())
+}
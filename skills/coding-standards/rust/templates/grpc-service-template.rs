@@ -0,0 +1,221 @@
+//! gRPC service template for `MyLib`
+//!
+//! Demonstrates:
+//! - Serving `MyLib` over gRPC with tonic
+//! - Unary and server-streaming RPCs
+//! - Mapping `LibError` to gRPC status codes
+//! - In-process integration tests over an in-memory transport
+//!
+//! Add to Cargo.toml:
+//! [dependencies]
+//! my_lib = { path = "../my_lib" }
+//! prost = "0.13"
+//! tonic = "0.12"
+//! tokio = { version = "1.0", features = ["full"] }
+//! tokio-stream = "0.1"
+//! tracing = "0.1"
+//!
+//! [dev-dependencies]
+//! hyper-util = { version = "0.1", features = ["tokio"] }
+//! tower = "0.4"
+//!
+//! Stubs are generated from `proto/processor.proto` by the build script in
+//! `grpc-build-template.rs`.
+
+use std::sync::Arc;
+
+use my_lib::{LibError, MyLib};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use tracing::{debug, info};
+
+/// Generated protobuf types and service stubs
+pub mod pb {
+    tonic::include_proto!("processor.v1");
+}
+
+use pb::processor_service_server::{ProcessorService, ProcessorServiceServer};
+use pb::{process_result, ProcessBatchRequest, ProcessRequest, ProcessResponse, ProcessResult};
+
+/// Number of streamed results buffered ahead of a slow client
+const STREAM_BUFFER: usize = 16;
+
+/// gRPC implementation of `ProcessorService` backed by `MyLib`
+#[derive(Debug, Clone)]
+pub struct ProcessorGrpc {
+    lib: Arc<MyLib>,
+}
+
+impl ProcessorGrpc {
+    /// Creates a new service
+    pub fn new(lib: MyLib) -> Self {
+        Self { lib: Arc::new(lib) }
+    }
+
+    /// Wraps the service for `tonic::transport::Server::add_service`
+    pub fn into_server(self) -> ProcessorServiceServer<Self> {
+        ProcessorServiceServer::new(self)
+    }
+}
+
+/// Maps library errors to gRPC status codes
+fn to_status(err: LibError) -> Status {
+    match err {
+        LibError::InvalidInput(msg) => Status::invalid_argument(msg),
+        other => Status::internal(other.to_string()),
+    }
+}
+
+#[tonic::async_trait]
+impl ProcessorService for ProcessorGrpc {
+    async fn process(
+        &self,
+        request: Request<ProcessRequest>,
+    ) -> Result<Response<ProcessResponse>, Status> {
+        let input = request.into_inner().input;
+        debug!("Process request with {} bytes", input.len());
+
+        let output = self.lib.process(&input).map_err(to_status)?;
+        Ok(Response::new(ProcessResponse { output }))
+    }
+
+    type ProcessStreamStream = ReceiverStream<Result<ProcessResult, Status>>;
+
+    async fn process_stream(
+        &self,
+        request: Request<ProcessBatchRequest>,
+    ) -> Result<Response<Self::ProcessStreamStream>, Status> {
+        let inputs = request.into_inner().inputs;
+        info!("Streaming results for {} inputs", inputs.len());
+
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let lib = Arc::clone(&self.lib);
+
+        tokio::spawn(async move {
+            for (index, input) in inputs.iter().enumerate() {
+                let outcome = match lib.process(input) {
+                    Ok(output) => process_result::Outcome::Output(output),
+                    Err(e) => process_result::Outcome::Error(e.to_string()),
+                };
+                let result = ProcessResult {
+                    index: index as u32,
+                    outcome: Some(outcome),
+                };
+                if tx.send(Ok(result)).await.is_err() {
+                    debug!("Client disconnected mid-stream");
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper_util::rt::TokioIo;
+    use pb::processor_service_client::ProcessorServiceClient;
+    use tokio_stream::StreamExt;
+    use tonic::transport::{Channel, Endpoint, Server, Uri};
+
+    /// Connects a client to a server running over an in-memory duplex pipe
+    async fn connect() -> ProcessorServiceClient<Channel> {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+
+        let service = ProcessorGrpc::new(MyLib::new("config").unwrap()).into_server();
+        tokio::spawn(async move {
+            Server::builder()
+                .add_service(service)
+                .serve_with_incoming(tokio_stream::once(Ok::<_, std::io::Error>(server_io)))
+                .await
+        });
+
+        let mut client_io = Some(client_io);
+        let channel = Endpoint::try_from("http://in-process")
+            .unwrap()
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                let io = client_io.take();
+                async move {
+                    io.map(TokioIo::new)
+                        .ok_or_else(|| std::io::Error::other("client already connected"))
+                }
+            }))
+            .await
+            .unwrap();
+
+        ProcessorServiceClient::new(channel)
+    }
+
+    #[tokio::test]
+    async fn test_unary_process() {
+        let mut client = connect().await;
+
+        let response = client
+            .process(ProcessRequest {
+                input: "hello".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.into_inner().output, "PROCESSED: hello");
+    }
+
+    #[tokio::test]
+    async fn test_unary_invalid_input() {
+        let mut client = connect().await;
+
+        let status = client
+            .process(ProcessRequest {
+                input: String::new(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_stream_collects_all_results() {
+        let mut client = connect().await;
+        let inputs = vec!["a".to_string(), String::new(), "c".to_string()];
+
+        let stream = client
+            .process_stream(ProcessBatchRequest { inputs })
+            .await
+            .unwrap()
+            .into_inner();
+        let results: Vec<ProcessResult> = stream.map(|r| r.unwrap()).collect().await;
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results.iter().map(|r| r.index).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(
+            results[0].outcome,
+            Some(process_result::Outcome::Output("PROCESSED: a".to_string()))
+        );
+        assert!(matches!(
+            results[1].outcome,
+            Some(process_result::Outcome::Error(_))
+        ));
+        assert_eq!(
+            results[2].outcome,
+            Some(process_result::Outcome::Output("PROCESSED: c".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_empty_batch() {
+        let mut client = connect().await;
+
+        let stream = client
+            .process_stream(ProcessBatchRequest { inputs: vec![] })
+            .await
+            .unwrap()
+            .into_inner();
+        let results: Vec<_> = stream.collect().await;
+        assert!(results.is_empty());
+    }
+}
@@ -0,0 +1,764 @@
+//! HTTP REST API mode for the application library
+//!
+//! Save as `src/http.rs` and declare it with `pub mod http;` in
+//! `src/lib.rs`. Enabled at runtime with `--http-port <PORT>`.
+//!
+//! Demonstrates:
+//! - A JSON endpoint built with axum
+//! - Request body size limits and content-type validation
+//! - Hand-written tower middleware for request IDs, access logging,
+//!   API key checks and RFC 7807 (`application/problem+json`) error bodies
+//! - CORS via tower-http, configured with `--cors-origins`
+//!
+//! Add to Cargo.toml:
+//! [dependencies]
+//! axum = "0.7"
+//! futures-util = "0.3"
+//! my_lib = { path = "../my_lib" }
+//! serde = { version = "1.0", features = ["derive"] }
+//! serde_json = "1.0"
+//! tokio = { version = "1.0", features = ["full"] }
+//! tower = "0.4"
+//! tower-http = { version = "0.5", features = ["cors"] }
+//!
+//! [dev-dependencies]
+//! http-body-util = "0.1"
+//! tower = { version = "0.4", features = ["util"] }
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context as TaskContext, Poll};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{DefaultBodyLimit, Request, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderName, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use futures_util::future::BoxFuture;
+use my_lib::{LibError, MyLib};
+use serde::{Deserialize, Serialize};
+use tower::{Layer, Service, ServiceBuilder};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::{App, Redacted};
+
+/// Largest request body accepted by `POST /process`
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Header carrying the request ID in both directions
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Header carrying the client's API key
+pub const X_API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+
+/// Media type of RFC 7807 error bodies
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Request body for `POST /process`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProcessRequest {
+    pub input: String,
+}
+
+/// Response body for `POST /process`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProcessResponse {
+    pub result: String,
+}
+
+/// RFC 7807 problem details, the body of every failed request
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Problem {
+    /// Always `about:blank`; `code` identifies the problem instead
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Reason phrase of `status`
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    /// Stable machine-readable code, e.g. `invalid_input`
+    pub code: String,
+}
+
+impl Problem {
+    fn new(status: StatusCode, code: &str, detail: impl Into<String>) -> Self {
+        Self {
+            kind: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            code: code.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = serde_json::to_vec(&self).unwrap_or_default();
+        (status, [(CONTENT_TYPE, PROBLEM_JSON)], body).into_response()
+    }
+}
+
+/// Errors surfaced by the HTTP layer
+#[derive(Debug)]
+pub enum HttpError {
+    /// The library rejected or failed to process the input
+    Lib(LibError),
+    /// The request body could not be accepted as JSON
+    Rejected(JsonRejection),
+}
+
+impl HttpError {
+    fn status_and_code(&self) -> (StatusCode, &'static str) {
+        match self {
+            HttpError::Lib(LibError::InvalidInput(_)) => (StatusCode::BAD_REQUEST, "invalid_input"),
+            HttpError::Lib(LibError::OperationFailed(_)) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "operation_failed")
+            }
+            HttpError::Lib(LibError::Timeout(_)) => (StatusCode::SERVICE_UNAVAILABLE, "timeout"),
+            HttpError::Lib(LibError::Io(_)) => (StatusCode::INTERNAL_SERVER_ERROR, "io_error"),
+            // LibError is non-exhaustive; variants added later land here
+            HttpError::Lib(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
+            HttpError::Rejected(JsonRejection::MissingJsonContentType(_)) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type")
+            }
+            HttpError::Rejected(rejection)
+                if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE =>
+            {
+                (StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large")
+            }
+            HttpError::Rejected(_) => (StatusCode::BAD_REQUEST, "malformed_request"),
+        }
+    }
+}
+
+impl IntoResponse for HttpError {
+    /// Attaches the problem to the response for `ErrorNormalizationLayer`
+    /// to render, so every error body is produced in one place
+    fn into_response(self) -> Response {
+        let (status, code) = self.status_and_code();
+        let detail = match &self {
+            HttpError::Lib(e) => e.to_string(),
+            HttpError::Rejected(e) => e.body_text(),
+        };
+        debug!("Request failed with {}: {}", code, detail);
+
+        let mut response = status.into_response();
+        response
+            .extensions_mut()
+            .insert(Problem::new(status, code, detail));
+        response
+    }
+}
+
+impl App {
+    /// Serves `POST /process` on `0.0.0.0:<port>` until the process exits
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the port cannot be bound
+    pub async fn run_http_server(&self, port: u16) -> Result<()> {
+        let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .context(format!("Cannot bind {}", addr))?;
+        info!("HTTP server listening on {}", listener.local_addr()?);
+
+        axum::serve(listener, self.http_router()?)
+            .await
+            .context("HTTP server failed")
+    }
+
+    /// Builds the HTTP router, middleware included, without binding a socket
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `MyLib` rejects the configuration or a CORS
+    /// origin is not a valid header value
+    pub fn http_router(&self) -> Result<Router> {
+        let lib = MyLib::new(self.config.config_paths.join(","))?;
+
+        // Each `layer` call wraps everything added before it, so this builds
+        // inside out: the key check sits closest to the handler, its 401s
+        // are rendered as problems, and CORS headers reach every response
+        let mut router = Router::new()
+            .route("/process", post(process))
+            .layer(DefaultBodyLimit::max(MAX_BODY_BYTES));
+        if let Some(key) = &self.config.api_key {
+            router = router.layer(ApiKeyLayer::new(&key.0));
+        }
+        router = router.layer(ErrorNormalizationLayer);
+        if !self.config.cors_origins.is_empty() {
+            router = router.layer(cors_layer(&self.config.cors_origins)?);
+        }
+
+        Ok(router
+            .layer(
+                ServiceBuilder::new()
+                    .layer(RequestIdLayer)
+                    .layer(TracingLayer),
+            )
+            .with_state(Arc::new(lib)))
+    }
+}
+
+/// CORS policy allowing `origins` (or any origin for `*`) to call the API
+fn cors_layer(origins: &[String]) -> Result<CorsLayer> {
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin.trim())
+                    .context(format!("Invalid CORS origin: {}", origin))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    Ok(CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::POST])
+        .allow_headers([CONTENT_TYPE, X_API_KEY, X_REQUEST_ID])
+        .expose_headers([X_REQUEST_ID]))
+}
+
+async fn process(
+    State(lib): State<Arc<MyLib>>,
+    payload: std::result::Result<Json<ProcessRequest>, JsonRejection>,
+) -> std::result::Result<Json<ProcessResponse>, HttpError> {
+    let Json(request) = payload.map_err(HttpError::Rejected)?;
+    let result = lib.process(&request.input).map_err(HttpError::Lib)?;
+    Ok(Json(ProcessResponse { result }))
+}
+
+/// ID of the current request, available to handlers as an extension
+#[derive(Debug, Clone)]
+pub struct RequestId(pub HeaderValue);
+
+/// Reads `X-Request-ID` from the request, or generates one, and echoes it
+/// on the response
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService { inner }
+    }
+}
+
+/// Service produced by [`RequestIdLayer`]
+#[derive(Debug, Clone)]
+pub struct RequestIdService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for RequestIdService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, std::result::Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<std::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let id = request
+            .headers()
+            .get(&X_REQUEST_ID)
+            .cloned()
+            .unwrap_or_else(generate_request_id);
+        request.extensions_mut().insert(RequestId(id.clone()));
+
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let mut response = future.await?;
+            response.headers_mut().insert(X_REQUEST_ID, id);
+            Ok(response)
+        })
+    }
+}
+
+/// Returns an ID unique within this process and unlikely to repeat across
+/// restarts
+fn generate_request_id() -> HeaderValue {
+    static PREFIX: OnceLock<u64> = OnceLock::new();
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+
+    let prefix = PREFIX.get_or_init(|| RandomState::new().hash_one(Instant::now()));
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    HeaderValue::from_str(&format!("{:016x}-{:08x}", prefix, n))
+        .expect("hex digits are a valid header value")
+}
+
+/// Logs method, path, status and duration of every request
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingLayer;
+
+impl<S> Layer<S> for TracingLayer {
+    type Service = TracingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TracingService { inner }
+    }
+}
+
+/// Service produced by [`TracingLayer`]
+#[derive(Debug, Clone)]
+pub struct TracingService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for TracingService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, std::result::Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<std::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .and_then(|id| id.0.to_str().ok())
+            .unwrap_or("-")
+            .to_string();
+        let span = info_span!(
+            "request",
+            method = %request.method(),
+            path = %request.uri().path(),
+            request_id = %request_id,
+        );
+
+        let start = Instant::now();
+        let future = self.inner.call(request);
+        Box::pin(
+            async move {
+                let response = future.await?;
+                let duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+                let status = response.status().as_u16();
+                if response.status().is_server_error() {
+                    warn!(status, duration_ms, "Request failed");
+                } else {
+                    info!(status, duration_ms, "Request completed");
+                }
+                Ok(response)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+/// Rejects requests whose `X-API-Key` header does not match the configured
+/// key with 401 Unauthorized
+#[derive(Debug, Clone)]
+pub struct ApiKeyLayer {
+    key: Arc<str>,
+}
+
+impl ApiKeyLayer {
+    /// Creates a layer accepting only `key`
+    pub fn new(key: &str) -> Self {
+        Self { key: key.into() }
+    }
+}
+
+impl<S> Layer<S> for ApiKeyLayer {
+    type Service = ApiKeyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ApiKeyService {
+            inner,
+            key: Arc::clone(&self.key),
+        }
+    }
+}
+
+/// Service produced by [`ApiKeyLayer`]
+#[derive(Clone)]
+pub struct ApiKeyService<S> {
+    inner: S,
+    key: Arc<str>,
+}
+
+impl<S: fmt::Debug> fmt::Debug for ApiKeyService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKeyService")
+            .field("inner", &self.inner)
+            .field("key", &Redacted(&self.key))
+            .finish()
+    }
+}
+
+impl<S> Service<Request> for ApiKeyService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, std::result::Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<std::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let authorized = request
+            .headers()
+            .get(&X_API_KEY)
+            .is_some_and(|given| constant_time_eq(given.as_bytes(), self.key.as_bytes()));
+        if authorized {
+            return Box::pin(self.inner.call(request));
+        }
+
+        debug!("Rejecting request with missing or wrong API key");
+        let status = StatusCode::UNAUTHORIZED;
+        let mut response = status.into_response();
+        response.extensions_mut().insert(Problem::new(
+            status,
+            "unauthorized",
+            "missing or invalid X-API-Key header",
+        ));
+        Box::pin(async move { Ok(response) })
+    }
+}
+
+/// Compares without short-circuiting, so response timing does not reveal
+/// how much of a guessed key was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Renders every error response as `application/problem+json`
+///
+/// Handler errors carry their [`Problem`] as a response extension; other
+/// error statuses (unknown route, wrong method) get one derived from the
+/// status code.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ErrorNormalizationLayer;
+
+impl<S> Layer<S> for ErrorNormalizationLayer {
+    type Service = ErrorNormalizationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ErrorNormalizationService { inner }
+    }
+}
+
+/// Service produced by [`ErrorNormalizationLayer`]
+#[derive(Debug, Clone)]
+pub struct ErrorNormalizationService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for ErrorNormalizationService<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, std::result::Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<std::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let mut response = future.await?;
+            let status = response.status();
+            if !status.is_client_error() && !status.is_server_error() {
+                return Ok(response);
+            }
+
+            let problem = match response.extensions_mut().remove::<Problem>() {
+                Some(problem) => problem,
+                None => {
+                    let reason = status.canonical_reason().unwrap_or("Error");
+                    let code = reason.to_lowercase().replace(' ', "_");
+                    Problem::new(status, &code, reason)
+                }
+            };
+            let mut normalized = problem.into_response();
+            // Keep headers set further in, e.g. `Allow` on 405
+            for (name, value) in response.headers() {
+                if name != CONTENT_TYPE && name != axum::http::header::CONTENT_LENGTH {
+                    normalized.headers_mut().insert(name, value.clone());
+                }
+            }
+            Ok(normalized)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::LogCapture;
+    use crate::Config;
+    use axum::body::Body;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    fn router() -> Router {
+        router_with(Config::default())
+    }
+
+    fn router_with(config: Config) -> Router {
+        App::new(Config {
+            config_paths: vec!["config.toml".to_string()],
+            ..config
+        })
+        .http_router()
+        .unwrap()
+    }
+
+    fn secured_router() -> Router {
+        router_with(Config {
+            api_key: Some(Redacted("s3cret".to_string())),
+            cors_origins: vec!["https://app.example.com".to_string()],
+            ..Config::default()
+        })
+    }
+
+    fn post(content_type: &str, body: String) -> axum::http::Request<Body> {
+        axum::http::Request::post("/process")
+            .header("content-type", content_type)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn send(content_type: &str, body: String) -> (StatusCode, serde_json::Value) {
+        let response = router().oneshot(post(content_type, body)).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    fn json_body(input: &str) -> String {
+        serde_json::json!({ "input": input }).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_process_success() {
+        let (status, body) = send("application/json", json_body("hello")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["result"], "PROCESSED: hello");
+    }
+
+    #[tokio::test]
+    async fn test_empty_input_is_bad_request() {
+        let (status, body) = send("application/json", json_body("")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_input");
+    }
+
+    #[tokio::test]
+    async fn test_content_type_mismatch() {
+        let (status, body) = send("text/plain", json_body("hello")).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["code"], "unsupported_media_type");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body() {
+        let input = "x".repeat(MAX_BODY_BYTES + 1);
+        let (status, body) = send("application/json", json_body(&input)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["code"], "payload_too_large");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_json() {
+        let (status, body) = send("application/json", "{not json".to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "malformed_request");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_echoed() {
+        let mut request = post("application/json", json_body("hello"));
+        request
+            .headers_mut()
+            .insert(X_REQUEST_ID, HeaderValue::from_static("abc-123"));
+
+        let response = router().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[&X_REQUEST_ID], "abc-123");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_generated() {
+        let first = router()
+            .oneshot(post("application/json", json_body("a")))
+            .await
+            .unwrap();
+        let second = router()
+            .oneshot(post("application/json", json_body("b")))
+            .await
+            .unwrap();
+
+        let first = first.headers().get(&X_REQUEST_ID).unwrap();
+        let second = second.headers().get(&X_REQUEST_ID).unwrap();
+        assert!(!first.is_empty());
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_access_log_has_duration() {
+        let logs = LogCapture::default();
+        let _guard = tracing::subscriber::set_default(logs.subscriber());
+
+        router()
+            .oneshot(post("application/json", json_body("hello")))
+            .await
+            .unwrap();
+
+        let line = logs
+            .contents()
+            .lines()
+            .find(|line| line.contains("Request completed"))
+            .map(str::to_string)
+            .expect("no access log line");
+        assert!(line.contains("duration_ms="));
+        assert!(line.contains("status=200"));
+        assert!(line.contains("method=POST"));
+        assert!(line.contains("path=/process"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_input_is_problem_json() {
+        let response = router()
+            .oneshot(post("application/json", json_body("")))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[CONTENT_TYPE], PROBLEM_JSON);
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let problem: Problem = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(problem.status, 400);
+        assert_eq!(problem.title, "Bad Request");
+        assert_eq!(problem.code, "invalid_input");
+        assert!(problem.detail.contains("input cannot be empty"));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_route_is_problem_json() {
+        let request = axum::http::Request::get("/missing")
+            .body(Body::empty())
+            .unwrap();
+        let response = router().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[CONTENT_TYPE], PROBLEM_JSON);
+        assert!(response.headers().contains_key(&X_REQUEST_ID));
+    }
+
+    async fn status_with_key(key: Option<&str>) -> StatusCode {
+        let mut request = post("application/json", json_body("hello"));
+        if let Some(key) = key {
+            request
+                .headers_mut()
+                .insert(X_API_KEY, HeaderValue::from_str(key).unwrap());
+        }
+        secured_router().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_missing_api_key_is_unauthorized() {
+        assert_eq!(status_with_key(None).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_api_key_is_unauthorized() {
+        assert_eq!(
+            status_with_key(Some("guess")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status_with_key(Some("s3cret!")).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_correct_api_key_is_accepted() {
+        assert_eq!(status_with_key(Some("s3cret")).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_allowed_origin_gets_cors_headers() {
+        let mut request = post("application/json", json_body("hello"));
+        let headers = request.headers_mut();
+        headers.insert(X_API_KEY, HeaderValue::from_static("s3cret"));
+        headers.insert(
+            "origin",
+            HeaderValue::from_static("https://app.example.com"),
+        );
+
+        let response = secured_router().oneshot(request).await.unwrap();
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://app.example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_other_origin_gets_no_cors_headers() {
+        let mut request = post("application/json", json_body("hello"));
+        let headers = request.headers_mut();
+        headers.insert(X_API_KEY, HeaderValue::from_static("s3cret"));
+        headers.insert(
+            "origin",
+            HeaderValue::from_static("https://evil.example.com"),
+        );
+
+        let response = secured_router().oneshot(request).await.unwrap();
+        assert!(!response
+            .headers()
+            .contains_key("access-control-allow-origin"));
+    }
+
+    #[tokio::test]
+    async fn test_preflight_does_not_need_api_key() {
+        let request = axum::http::Request::options("/process")
+            .header("origin", "https://app.example.com")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "x-api-key")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = secured_router().oneshot(request).await.unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://app.example.com"
+        );
+    }
+}
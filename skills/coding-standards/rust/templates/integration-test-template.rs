@@ -0,0 +1,1055 @@
+//! Integration test template for the application library
+//!
+//! Save as `tests/app.rs`. These tests use only the public API exported by
+//! `app-lib-template.rs`, the same way a downstream crate would.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Result;
+use clap::{CommandFactory, Parser};
+use my_app::{App, Args, Config, Mode};
+use tempfile::TempDir;
+
+fn write_file(dir: &Path, name: &str, contents: &str) -> Result<String> {
+    let path = dir.join(name);
+    std::fs::write(&path, contents)?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+#[test]
+fn test_full_run_from_config() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "hello\nworld\n")?;
+    let output = dir.path().join("output.txt");
+
+    let app = App::new(Config {
+        inputs: vec![input],
+        output: Some(output.to_string_lossy().into_owned()),
+        mode: Mode::Upper,
+        ..Config::default()
+    });
+    app.run()?;
+
+    assert_eq!(std::fs::read_to_string(&output)?, "HELLO\nWORLD\n");
+    Ok(())
+}
+
+#[test]
+fn test_full_run_from_args() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "pear\napple\n")?;
+    let output = dir.path().join("sorted.txt");
+
+    let args = Args::try_parse_from([
+        "app",
+        "--input",
+        &input,
+        "--output",
+        &output.to_string_lossy(),
+        "--mode",
+        "sort",
+    ])?;
+    App::new(Config::from_args(args)).run()?;
+
+    assert_eq!(std::fs::read_to_string(&output)?, "apple\npear\n");
+    Ok(())
+}
+
+#[test]
+fn test_batch_summary() -> Result<()> {
+    let dir = TempDir::new()?;
+    let out_dir = dir.path().join("out");
+    std::fs::create_dir_all(&out_dir)?;
+    let inputs = vec![
+        write_file(dir.path(), "a.txt", "first\n")?,
+        write_file(dir.path(), "b.txt", "second\n")?,
+        dir.path()
+            .join("missing.txt")
+            .to_string_lossy()
+            .into_owned(),
+    ];
+
+    let app = App::new(Config {
+        inputs,
+        out_dir: Some(out_dir.to_string_lossy().into_owned()),
+        jobs: 2,
+        ..Config::default()
+    });
+    let summary = app.process_all()?;
+
+    assert_eq!(summary.succeeded(), 2);
+    assert_eq!(summary.failed(), 1);
+    assert_eq!(std::fs::read_to_string(out_dir.join("a.txt"))?, "FIRST\n");
+    assert!(app.run().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_threads_sizes_worker_pool() -> Result<()> {
+    let dir = TempDir::new()?;
+    let out_dir = dir.path().join("out");
+    std::fs::create_dir_all(&out_dir)?;
+    let inputs: Vec<String> = (0..6)
+        .map(|i| write_file(dir.path(), &format!("{}.txt", i), &format!("line {}\n", i)))
+        .collect::<Result<_>>()?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .arg("--input")
+        .args(&inputs)
+        .args([
+            "--threads",
+            "2",
+            "--verbose",
+            "--color",
+            "never",
+            "--out-dir",
+        ])
+        .arg(&out_dir)
+        .output()?;
+
+    assert!(output.status.success());
+    for i in 0..6 {
+        let written = std::fs::read_to_string(out_dir.join(format!("{}.txt", i)))?;
+        assert_eq!(written, format!("LINE {}\n", i));
+    }
+    // Best effort: the pool logs its own size
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("threads=2"), "{}", stderr);
+    Ok(())
+}
+
+#[test]
+fn test_existing_output_exits_with_code_2() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "new\n")?;
+    let output = write_file(dir.path(), "output.txt", "keep me\n")?;
+
+    let refused = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", &input, "--output", &output])
+        .output()?;
+    assert_eq!(refused.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&refused.stderr)
+        .contains("output exists, pass --force to overwrite"));
+    assert_eq!(std::fs::read_to_string(&output)?, "keep me\n");
+
+    let forced = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", &input, "--output", &output, "--force"])
+        .output()?;
+    assert!(forced.status.success());
+    assert_eq!(std::fs::read_to_string(&output)?, "NEW\n");
+    Ok(())
+}
+
+#[test]
+fn test_error_report_color_control() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "new\n")?;
+    let output = write_file(dir.path(), "output.txt", "keep me\n")?;
+    let run = |color: &str| {
+        Command::new(env!("CARGO_BIN_EXE_my_app"))
+            .args(["--input", &input, "--output", &output, "--color", color])
+            .output()
+    };
+
+    let plain = String::from_utf8(run("never")?.stderr)?;
+    assert!(!plain.contains('\x1b'), "{:?}", plain);
+    let block = format!(
+        "Error: output exists, pass --force to overwrite: {0}\n  \
+         Code: output_exists\n  \
+         File: {0}\n  \
+         Hint: pass --force to replace it, with --backup to keep the current contents\n",
+        output
+    );
+    assert!(plain.ends_with(&block), "{}", plain);
+
+    let colored = String::from_utf8(run("always")?.stderr)?;
+    assert!(
+        colored.contains("\x1b[1;31mError:\x1b[0m output exists"),
+        "{:?}",
+        colored
+    );
+    Ok(())
+}
+
+#[test]
+fn test_diagnostics_on_failure() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "private contents\n")?;
+    let output = write_file(dir.path(), "output.txt", "keep me\n")?;
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_my_app"))
+            .args(["--input", &input, "--output", &output, "--color", "never"])
+            .args(extra)
+            .output()
+    };
+
+    let failed = run(&["--diagnostics"])?;
+    assert_eq!(failed.status.code(), Some(2));
+    let stderr = String::from_utf8(failed.stderr)?;
+    for field in [
+        format!("\n  Version: my_app {}\n", env!("CARGO_PKG_VERSION")),
+        format!("\n  OS: {} (", std::env::consts::OS),
+        format!("\n  Arch: {}\n", std::env::consts::ARCH),
+        format!("\n  Path: {} -> {}\n", input, output),
+    ] {
+        assert!(stderr.contains(&field), "missing {:?} in {}", field, stderr);
+    }
+    assert!(stderr.contains("\n  Config: Config {"), "{}", stderr);
+    assert!(!stderr.contains("private contents"), "{}", stderr);
+
+    // Only on request, and only when the run fails
+    assert!(!String::from_utf8(run(&[])?.stderr)?.contains("Diagnostics:"));
+    let succeeded = run(&["--diagnostics", "--force"])?;
+    assert!(succeeded.status.success());
+    assert!(!String::from_utf8(succeeded.stderr)?.contains("Diagnostics:"));
+    Ok(())
+}
+
+#[test]
+fn test_json_format_keeps_stdout_parseable() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "hello\n")?;
+
+    let ok = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", &input, "--format", "json"])
+        .output()?;
+    assert!(ok.status.success());
+    let record: serde_json::Value = serde_json::from_slice(&ok.stdout)?;
+    assert_eq!(record["status"], "ok");
+    assert_eq!(record["result"], "HELLO\n");
+
+    // A run that fails before touching any input still reports in JSON
+    let other = dir.path().join("other");
+    std::fs::create_dir(&other)?;
+    let duplicate = write_file(&other, "input.txt", "again\n")?;
+    let failed = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", &input, &duplicate, "--out-dir"])
+        .arg(dir.path().join("out"))
+        .args(["--format", "json"])
+        .output()?;
+    assert!(!failed.status.success());
+    let record: serde_json::Value = serde_json::from_slice(&failed.stdout)?;
+    assert!(record["error"]
+        .as_str()
+        .unwrap()
+        .contains("Multiple inputs would write to the same output"));
+    Ok(())
+}
+
+#[test]
+fn test_summary_never_reaches_stdout() -> Result<()> {
+    let dir = TempDir::new()?;
+    let inputs = [
+        write_file(dir.path(), "a.txt", "first\n")?,
+        write_file(dir.path(), "b.txt", "second\n")?,
+    ];
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_my_app"))
+            .arg("--input")
+            .args(&inputs)
+            .args(["--color", "never"])
+            .args(extra)
+            .output()
+    };
+
+    // Each input's output goes to stdout as is, one after the other
+    for summary in ["auto", "always", "never"] {
+        let output = run(&["--summary", summary])?;
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"FIRST\nSECOND\n", "--summary {}", summary);
+        let stderr = String::from_utf8(output.stderr)?;
+        assert_eq!(
+            stderr.contains("Processed 2 files"),
+            summary != "never",
+            "--summary {}: {}",
+            summary,
+            stderr
+        );
+    }
+
+    // With --format json stdout holds exactly the records, summary or not
+    let with_summary = run(&["--format", "json", "--summary", "always"])?;
+    let without = run(&["--format", "json", "--summary", "never"])?;
+    assert!(with_summary.status.success() && without.status.success());
+    assert_eq!(with_summary.stdout, without.stdout);
+    let records: serde_json::Value = serde_json::from_slice(&with_summary.stdout)?;
+    assert_eq!(records[0]["result"], "FIRST\n");
+    assert_eq!(records[1]["result"], "SECOND\n");
+    assert!(String::from_utf8(with_summary.stderr)?.contains("Processed 2 files"));
+    Ok(())
+}
+
+#[test]
+fn test_stdin_input() -> Result<()> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(b"piped\n")?;
+    let output = child.wait_with_output()?;
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "PIPED\n");
+    Ok(())
+}
+
+#[test]
+fn test_stdout_and_file_end_alike() -> Result<()> {
+    let dir = TempDir::new()?;
+    let output = dir.path().join("output.txt");
+
+    // (input, extra flags, expected output)
+    let cases: [(&str, &[&str], &str); 6] = [
+        ("a\nb\n", &[], "A\nB\n"),
+        ("a\nb", &[], "A\nB"),
+        ("a\nb\n", &["--no-trailing-newline"], "A\nB"),
+        ("a\nb", &["--no-trailing-newline"], "A\nB"),
+        // Sorting buffers the input instead of streaming it
+        ("b\na\n", &["--sort"], "A\nB\n"),
+        ("b\na", &["--sort", "--no-trailing-newline"], "A\nB"),
+    ];
+    for (text, extra, expected) in cases {
+        let input = write_file(dir.path(), "input.txt", text)?;
+        let run = |target: &[&str]| {
+            Command::new(env!("CARGO_BIN_EXE_my_app"))
+                .args(["--input", &input, "--force"])
+                .args(extra)
+                .args(target)
+                .output()
+        };
+
+        let stdout = run(&[])?;
+        assert!(stdout.status.success(), "{:?} {:?}", text, extra);
+        assert_eq!(
+            String::from_utf8(stdout.stdout)?,
+            expected,
+            "{:?} {:?}",
+            text,
+            extra
+        );
+
+        let file = run(&["--output", &output.to_string_lossy()])?;
+        assert!(file.status.success(), "{:?} {:?}", text, extra);
+        assert_eq!(
+            std::fs::read_to_string(&output)?,
+            expected,
+            "{:?} {:?}",
+            text,
+            extra
+        );
+    }
+
+    let both = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args([
+            "--input",
+            "x",
+            "--no-trailing-newline",
+            "--final-newline",
+            "ensure",
+        ])
+        .output()?;
+    assert_eq!(both.status.code(), Some(2));
+    Ok(())
+}
+
+#[test]
+fn test_gzip_stdin_is_decompressed() -> Result<()> {
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(b"piped\n")?;
+    let compressed = encoder.finish()?;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(&compressed)?;
+    let output = child.wait_with_output()?;
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "PIPED\n");
+    Ok(())
+}
+
+#[test]
+fn test_split_needs_an_output_file() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "one\ntwo\n")?;
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_my_app"))
+            .args(["--input", &input])
+            .args(extra)
+            .output()
+    };
+
+    // Parts are files, so stdout cannot be split
+    let to_stdout = run(&["--split-lines", "1"])?;
+    assert_eq!(to_stdout.status.code(), Some(2));
+    assert!(to_stdout.stdout.is_empty());
+    let stderr = String::from_utf8(to_stdout.stderr)?;
+    assert!(stderr.contains("--output"), "{}", stderr);
+
+    let both = run(&[
+        "--split-lines",
+        "1",
+        "--split-bytes",
+        "1KB",
+        "--out-dir",
+        "out",
+    ])?;
+    assert_eq!(both.status.code(), Some(2));
+    assert!(String::from_utf8(both.stderr)?.contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn test_sort_then_dedupe() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "file10\nFILE2\nfile1\nfile2\n")?;
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_my_app"))
+            .args(["--input", &input, "--mode", "lower", "--color", "never"])
+            .args(extra)
+            .output()
+    };
+
+    // Both stages see the lowercased lines, and dedupe runs after sorting
+    let sorted = run(&["--sort", "--sort-mode", "natural", "--dedupe"])?;
+    assert!(sorted.status.success());
+    assert_eq!(String::from_utf8(sorted.stdout)?, "file1\nfile2\nfile10\n");
+
+    let capped = run(&[
+        "--dedupe",
+        "--dedupe-mode",
+        "global",
+        "--dedupe-memory",
+        "32",
+    ])?;
+    assert_eq!(capped.status.code(), Some(1));
+    let stderr = String::from_utf8(capped.stderr)?;
+    assert!(
+        stderr.contains("--dedupe-memory limit of 32 bytes"),
+        "{}",
+        stderr
+    );
+    Ok(())
+}
+
+#[test]
+fn test_stats_json_schema() -> Result<()> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "naïve café\r\nsecond line\n")?;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["stats", "--format", "json", &input, "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(b"from stdin")?;
+    let output = child.wait_with_output()?;
+    assert!(output.status.success());
+
+    // Several inputs give an array of flat records, in input order
+    let records: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let records = records.as_array().expect("an array of records");
+    assert_eq!(records.len(), 2);
+    for record in records {
+        let record = record.as_object().expect("record is an object");
+        let mut keys: Vec<_> = record.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            [
+                "bytes",
+                "chars",
+                "encoding",
+                "input",
+                "lines",
+                "longest_line",
+                "words"
+            ]
+        );
+        for key in ["bytes", "chars", "lines", "longest_line", "words"] {
+            assert!(record[key].is_u64(), "{} is {}", key, record[key]);
+        }
+        assert!(record["encoding"].is_string());
+    }
+    assert_eq!(
+        records[0],
+        serde_json::json!({
+            "input": input,
+            "lines": 2,
+            "words": 4,
+            "chars": 24,
+            "bytes": 26,
+            "longest_line": 11,
+            "encoding": "utf-8",
+        })
+    );
+    assert_eq!(records[1]["input"], "-");
+    assert_eq!(records[1]["words"], 2);
+    assert_eq!(records[1]["encoding"], "ascii");
+    Ok(())
+}
+
+#[test]
+fn test_stats_table() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "one two\nthree\n")?;
+    let output = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["stats", &input])
+        .output()?;
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert_eq!(
+        lines[0].split_whitespace().collect::<Vec<_>>(),
+        ["lines", "words", "chars", "bytes", "longest", "encoding", "input"]
+    );
+    assert_eq!(
+        lines[1].split_whitespace().collect::<Vec<_>>(),
+        ["2", "3", "14", "14", "7", "ascii", input.as_str()]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_diff_exit_status() -> Result<()> {
+    let dir = TempDir::new()?;
+    let same = write_file(dir.path(), "same.txt", "UPPER\n")?;
+    let changed = write_file(dir.path(), "changed.txt", "lower\n")?;
+
+    let identical = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", &same, "--diff"])
+        .output()?;
+    assert_eq!(identical.status.code(), Some(0));
+    assert!(identical.stdout.is_empty());
+
+    let different = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", &changed, "--diff", "--color=never"])
+        .output()?;
+    assert_eq!(different.status.code(), Some(1));
+    let stdout = String::from_utf8(different.stdout)?;
+    assert!(
+        stdout.contains("@@ -1 +1 @@\n-lower\n+LOWER\n"),
+        "{}",
+        stdout
+    );
+    assert!(!String::from_utf8_lossy(&different.stderr).contains("Error:"));
+    assert_eq!(std::fs::read_to_string(&changed)?, "lower\n");
+    Ok(())
+}
+
+#[test]
+fn test_large_in_place_run_needs_yes_without_terminal() -> Result<()> {
+    use std::process::Stdio;
+
+    let dir = TempDir::new()?;
+    let inputs = ["a.txt", "b.txt", "c.txt"]
+        .iter()
+        .map(|name| write_file(dir.path(), name, "hello\n"))
+        .collect::<Result<Vec<_>>>()?;
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_my_app"))
+            .args(["--in-place", "--confirm-threshold", "2", "--input"])
+            .args(&inputs)
+            .args(extra)
+            .stdin(Stdio::null())
+            .output()
+    };
+
+    let refused = run(&[])?;
+    assert_eq!(refused.status.code(), Some(2));
+    assert!(refused.stdout.is_empty());
+    let stderr = String::from_utf8(refused.stderr)?;
+    assert!(
+        stderr.contains("Error: not modifying 3 files in place without confirmation"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("Hint: pass --yes"), "{}", stderr);
+    assert_eq!(std::fs::read_to_string(&inputs[0])?, "hello\n");
+
+    let confirmed = run(&["--yes"])?;
+    assert!(confirmed.status.success());
+    for input in &inputs {
+        assert_eq!(std::fs::read_to_string(input)?, "HELLO\n");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_missing_input_error_presentation() -> Result<()> {
+    let dir = TempDir::new()?;
+    let missing = dir.path().join("missing.txt");
+
+    let friendly = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .arg("--input")
+        .arg(&missing)
+        .output()?;
+    assert_eq!(friendly.status.code(), Some(1));
+    let stderr = String::from_utf8(friendly.stderr)?;
+    assert!(
+        stderr.contains("Error: input file not found: "),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Hint: check the --input path"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("Caused by"), "{}", stderr);
+
+    let raw = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .arg("--input")
+        .arg(&missing)
+        .arg("--debug-errors")
+        .output()?;
+    assert_eq!(raw.status.code(), Some(1));
+    let stderr = String::from_utf8(raw.stderr)?;
+    assert!(
+        stderr.contains("Error: Application execution failed"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("Caused by:"), "{}", stderr);
+    assert!(!stderr.contains("Hint:"), "{}", stderr);
+    Ok(())
+}
+
+#[test]
+fn test_missing_output_dir_error() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "hello\n")?;
+    let output = dir.path().join("missing/output.txt");
+
+    let refused = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", &input, "--output"])
+        .arg(&output)
+        .output()?;
+    assert_eq!(refused.status.code(), Some(1));
+    let stderr = String::from_utf8(refused.stderr)?;
+    let expected = format!(
+        "Error: output directory does not exist: {} (use --create-dirs)",
+        dir.path().join("missing").display()
+    );
+    assert!(stderr.contains(&expected), "{}", stderr);
+    assert!(!stderr.contains("os error"), "{}", stderr);
+
+    let created = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", &input, "--create-dirs", "--output"])
+        .arg(&output)
+        .status()?;
+    assert!(created.success());
+    assert_eq!(std::fs::read_to_string(&output)?, "HELLO\n");
+    Ok(())
+}
+
+#[test]
+fn test_stdout_checksum_goes_to_stderr() -> Result<()> {
+    use sha2::{Digest, Sha256};
+
+    let dir = TempDir::new()?;
+    write_file(dir.path(), "input.txt", "hello\n")?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .current_dir(dir.path())
+        .args(["--input", "input.txt", "--emit-checksum"])
+        .output()?;
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"HELLO\n");
+    let digest = format!("{:x}", Sha256::digest(&output.stdout));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains(&format!("{}  -\n", digest)), "{}", stderr);
+    let files: Vec<_> = std::fs::read_dir(dir.path())?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<std::io::Result<_>>()?;
+    assert_eq!(files, ["input.txt"]);
+    Ok(())
+}
+
+#[test]
+fn test_panic_is_logged_through_tracing() -> Result<()> {
+    let unwound = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", "unused.txt", "--debug-panic"])
+        .output()?;
+    assert_eq!(unwound.status.code(), Some(101));
+    let stderr = String::from_utf8(unwound.stderr)?;
+    assert!(stderr.contains("ERROR"), "{}", stderr);
+    assert!(stderr.contains("Application panicked"), "{}", stderr);
+    assert!(stderr.contains("--debug-panic was given"), "{}", stderr);
+    assert!(stderr.contains("main.rs:"), "{}", stderr);
+    // The default hook's message is replaced, not printed as well
+    assert!(!stderr.contains("thread 'main' panicked"), "{}", stderr);
+
+    let aborted = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", "unused.txt", "--debug-panic", "--abort-on-panic"])
+        .output()?;
+    assert!(!aborted.status.success());
+    assert_ne!(aborted.status.code(), Some(101));
+    assert!(String::from_utf8(aborted.stderr)?.contains("Application panicked"));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sigint_aborts_cleanly() -> Result<()> {
+    use std::io::Write;
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    let dir = TempDir::new()?;
+    let output = dir.path().join("out.txt");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", "-", "--output"])
+        .arg(&output)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"first line\n")?;
+
+    // The temp file appears once the output is open, which is after the
+    // Ctrl-C handler is installed
+    let started = Instant::now();
+    let is_temp = |name: &str| name.ends_with(".tmp");
+    while !file_names(dir.path())?.iter().any(|name| is_temp(name)) {
+        assert!(started.elapsed() < Duration::from_secs(10), "no temp file");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()?;
+    // The next line is where the run notices the interrupt
+    std::thread::sleep(Duration::from_millis(100));
+    stdin.write_all(b"second line\n")?;
+    drop(stdin);
+
+    let result = child.wait_with_output()?;
+    assert_eq!(result.status.code(), Some(130));
+    let stderr = String::from_utf8(result.stderr)?;
+    assert!(
+        stderr.contains("interrupted: 0 file(s) completed, 1 aborted"),
+        "{}",
+        stderr
+    );
+    assert_eq!(file_names(dir.path())?, Vec::<String>::new());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_checkpoint_resumes_after_kill() -> Result<()> {
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    let dir = TempDir::new()?;
+    let out_dir = dir.path().join("out");
+    std::fs::create_dir(&out_dir)?;
+    let checkpoint = dir.path().join("run.checkpoint");
+    let mut inputs = vec![
+        write_file(dir.path(), "a.txt", "first\n")?,
+        write_file(dir.path(), "b.txt", "second\n")?,
+    ];
+    // Reading a FIFO with no writer blocks, holding the run on its third input
+    let fifo = dir.path().join("c.txt");
+    assert!(Command::new("mkfifo").arg(&fifo).status()?.success());
+    inputs.push(fifo.to_string_lossy().into_owned());
+    inputs.push(write_file(dir.path(), "d.txt", "fourth\n")?);
+
+    let run = |report: &Path| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_my_app"));
+        command
+            .arg("--input")
+            .args(&inputs)
+            .args(["--jobs", "1", "--out-dir"])
+            .arg(&out_dir)
+            .arg("--checkpoint")
+            .arg(&checkpoint)
+            .arg("--report")
+            .arg(report);
+        command
+    };
+
+    let mut child = run(&dir.path().join("killed.json"))
+        .stderr(Stdio::null())
+        .spawn()?;
+    let started = Instant::now();
+    while std::fs::read_to_string(&checkpoint).map_or(0, |text| text.lines().count()) < 2 {
+        assert!(started.elapsed() < Duration::from_secs(10), "no checkpoint");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    child.kill()?;
+    child.wait()?;
+
+    std::fs::remove_file(&fifo)?;
+    std::fs::write(&fifo, "third\n")?;
+    let report_path = dir.path().join("report.json");
+    let status = run(&report_path).stderr(Stdio::null()).status()?;
+    assert!(status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&std::fs::read(&report_path)?)?;
+    let statuses: Vec<_> = report["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, ["resumed", "resumed", "ok", "ok"]);
+    assert_eq!(report["summary"]["resumed"], 2);
+    assert_eq!(std::fs::read_to_string(out_dir.join("c.txt"))?, "THIRD\n");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_timeout_is_reported() -> Result<()> {
+    use std::io::Write;
+    use std::time::Duration;
+
+    let dir = TempDir::new()?;
+    // Lines written to a FIFO arrive when the test decides, so the run
+    // stalls part way through its only input
+    let input = dir.path().join("slow.txt");
+    assert!(Command::new("mkfifo").arg(&input).status()?.success());
+    let output = dir.path().join("output.txt");
+    let report_path = dir.path().join("report.json");
+
+    let child = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .arg("--input")
+        .arg(&input)
+        .arg("--output")
+        .arg(&output)
+        .args(["--timeout", "200ms", "--report"])
+        .arg(&report_path)
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    // Opening blocks until the app opens its end
+    let mut fifo = std::fs::OpenOptions::new().write(true).open(&input)?;
+    fifo.write_all(b"first line\n")?;
+    std::thread::sleep(Duration::from_millis(400));
+    // The app may already have given up and closed its end
+    let _ = fifo.write_all(b"second line\n");
+    drop(fifo);
+
+    let result = child.wait_with_output()?;
+    assert_eq!(result.status.code(), Some(124));
+    let stderr = String::from_utf8(result.stderr)?;
+    assert!(
+        stderr.contains("Error: timed out after 200ms"),
+        "{}",
+        stderr
+    );
+
+    let report: serde_json::Value = serde_json::from_slice(&std::fs::read(&report_path)?)?;
+    assert_eq!(report["summary"]["failed"], 1);
+    assert_eq!(report["summary"]["exit_code"], 124);
+    let file = &report["files"][0];
+    assert_eq!(file["status"], "failed");
+    assert_eq!(file["code"], 124);
+    assert!(file["error"]
+        .as_str()
+        .unwrap()
+        .contains("timed out after 200ms"));
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn test_corrupt_checkpoint_error() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "hello\n")?;
+    let checkpoint = write_file(dir.path(), "run.checkpoint", "garbage\n")?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", &input, "--checkpoint", &checkpoint, "--output"])
+        .arg(dir.path().join("output.txt"))
+        .output()?;
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains(&format!(
+            "Error: checkpoint file is corrupt: {} (line 1)",
+            checkpoint
+        )),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("Hint: pass --no-resume"), "{}", stderr);
+    assert!(!dir.path().join("output.txt").exists());
+    Ok(())
+}
+
+fn file_names(dir: &Path) -> Result<Vec<String>> {
+    Ok(std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<std::io::Result<_>>()?)
+}
+
+#[test]
+fn test_init_config_drives_a_run() -> Result<()> {
+    let dir = TempDir::new()?;
+    let config = dir.path().join("app.toml");
+    let init = || {
+        Command::new(env!("CARGO_BIN_EXE_my_app"))
+            .args(["init", "--full", "--path"])
+            .arg(&config)
+            .output()
+    };
+    assert!(init()?.status.success());
+
+    // Settings fill in flags that were not given
+    let text = std::fs::read_to_string(&config)?;
+    std::fs::write(
+        &config,
+        text.replace("mode = \"upper\"", "mode = \"lower\""),
+    )?;
+    let input = write_file(dir.path(), "input.txt", "ABC\n")?;
+    let run = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .arg("--config")
+        .arg(&config)
+        .args(["--input", &input, "--color", "never"])
+        .output()?;
+    assert!(run.status.success());
+    assert_eq!(String::from_utf8(run.stdout)?, "abc\n");
+    let stderr = String::from_utf8(run.stderr)?;
+    assert!(!stderr.contains("WARN"), "{}", stderr);
+
+    let again = init()?;
+    assert_eq!(again.status.code(), Some(2));
+    assert!(String::from_utf8(again.stderr)?.contains("config file exists"));
+    Ok(())
+}
+
+#[test]
+fn test_completions_and_man_page() -> Result<()> {
+    // Run in an empty directory to show nothing is written to disk
+    let dir = TempDir::new()?;
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_my_app"))
+            .current_dir(dir.path())
+            .args(args)
+            .output()
+    };
+
+    let bash = run(&["completions", "bash"])?;
+    assert!(bash.status.success());
+    let script = String::from_utf8(bash.stdout)?;
+    for word in [
+        "completions",
+        "man",
+        "--input",
+        "--mode",
+        "--grep",
+        "trim-lines",
+    ] {
+        assert!(script.contains(word), "missing {}", word);
+    }
+
+    let man = run(&["man"])?;
+    assert!(man.status.success());
+    let page = String::from_utf8(man.stdout)?;
+    let command = Args::command();
+    let about = command.get_about().expect("about text").to_string();
+    assert!(page.contains(&about), "missing about text {:?}", about);
+    for arg in command.get_arguments().filter(|arg| !arg.is_hide_set()) {
+        if let Some(long) = arg.get_long() {
+            // roff escapes every hyphen
+            let flag = format!("--{}", long).replace('-', "\\-");
+            assert!(page.contains(&flag), "missing {}", flag);
+        }
+    }
+
+    assert_eq!(std::fs::read_dir(dir.path())?.count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_report_lists_failures() -> Result<()> {
+    let dir = TempDir::new()?;
+    let good = write_file(dir.path(), "good.txt", "fine\n")?;
+    let bad = dir.path().join("bad.txt");
+    std::fs::write(&bad, [0xff, 0xfe, b'\n'])?;
+    let out_dir = dir.path().join("out");
+    std::fs::create_dir(&out_dir)?;
+    let report_path = dir.path().join("report.json");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .arg("--input")
+        .arg(&good)
+        .arg(&bad)
+        .arg("--out-dir")
+        .arg(&out_dir)
+        .arg("--report")
+        .arg(&report_path)
+        .output()?;
+
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&std::fs::read(&report_path)?)?;
+    assert_eq!(report["summary"]["succeeded"], 1);
+    assert_eq!(report["summary"]["failed"], 1);
+    assert_eq!(report["summary"]["exit_code"], 1);
+
+    let stderr = String::from_utf8(output.stderr)?;
+    let failed: Vec<_> = report["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|file| file["status"] == "failed")
+        .collect();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0]["code"], 1);
+    let line = format!(
+        "{}: {}",
+        failed[0]["input"].as_str().unwrap(),
+        failed[0]["error"].as_str().unwrap()
+    );
+    assert!(stderr.contains(&line), "{} not in {}", line, stderr);
+    // The summary names the run the report belongs to
+    let run_id = report["run_id"].as_str().unwrap();
+    assert!(
+        stderr.contains(&format!("failed (run {})", run_id)),
+        "{}",
+        stderr
+    );
+    Ok(())
+}
+
+#[test]
+fn test_report_to_stdout() -> Result<()> {
+    let dir = TempDir::new()?;
+    let input = write_file(dir.path(), "input.txt", "hello\n")?;
+    let out_dir = dir.path().join("out");
+    std::fs::create_dir(&out_dir)?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_my_app"))
+        .args(["--input", &input, "--report", "-", "--out-dir"])
+        .arg(&out_dir)
+        .output()?;
+
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(report["summary"]["total"], 1);
+    assert_eq!(report["summary"]["exit_code"], 0);
+    assert_eq!(report["files"][0]["status"], "ok");
+    assert_eq!(report["files"][0]["bytes_in"], 6);
+    Ok(())
+}
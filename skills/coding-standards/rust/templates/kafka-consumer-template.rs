@@ -0,0 +1,343 @@
+//! Kafka consumer template for `MyLib`
+//!
+//! Demonstrates:
+//! - Consuming a topic with rdkafka's async `StreamConsumer`
+//! - At-least-once delivery: offsets are committed only once a message has
+//!   been handled
+//! - Routing permanently failing messages to a dead-letter topic, so one
+//!   bad message cannot block its partition
+//! - Tests against rdkafka's in-process mock cluster
+//!
+//! Add to Cargo.toml:
+//! [dependencies]
+//! anyhow = "1.0"
+//! my_lib = { path = "../my_lib" }
+//! rdkafka = { version = "0.36", features = ["tokio"] }
+//! tokio = { version = "1.0", features = ["full"] }
+//! tracing = "0.1"
+//!
+//! [dev-dependencies]
+//! my_lib = { path = "../my_lib", features = ["testing"] }
+//!
+//! rdkafka compiles a bundled librdkafka, which needs a C compiler and
+//! `make` on the build machine.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use my_lib::{LibError, MyLib, Processor};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::{BorrowedMessage, Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::Message;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+/// Appended to the input topic to name its dead-letter topic
+pub const DLQ_SUFFIX: &str = ".dlq";
+
+/// How long a dead-letter publish may wait in the producer queue
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What happened to a message that was handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    /// Processed successfully and committed
+    Committed,
+    /// Failed for good, forwarded to the dead-letter topic and committed
+    DeadLettered,
+    /// A tombstone (no payload), committed without processing
+    Skipped,
+}
+
+/// Consumes a topic and feeds each message through `MyLib`
+///
+/// A message that would fail the same way on every delivery (a payload
+/// that is not UTF-8, or an error [`LibError::to_api_error`] does not mark
+/// retryable) goes to `<topic>.dlq` with the error in an `error` header
+/// and is committed, so the partition moves on. A retryable failure stops
+/// the consumer without committing, so the message is delivered again
+/// after a restart. Tombstones are committed and skipped.
+pub struct KafkaProcessor {
+    consumer: StreamConsumer,
+    producer: FutureProducer,
+    topic: String,
+    dlq_topic: String,
+}
+
+impl KafkaProcessor {
+    /// Connects to `brokers` and subscribes to `topic` as part of `group_id`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client configuration is rejected
+    pub fn new(brokers: &str, topic: &str, group_id: &str) -> Result<Self> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("group.id", group_id)
+            // Offsets are committed by hand once a message is settled
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .context("Failed to create Kafka consumer")?;
+        consumer
+            .subscribe(&[topic])
+            .context(format!("Cannot subscribe to {}", topic))?;
+
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create()
+            .context("Failed to create Kafka producer")?;
+
+        Ok(Self {
+            consumer,
+            producer,
+            topic: topic.to_string(),
+            dlq_topic: format!("{}{}", topic, DLQ_SUFFIX),
+        })
+    }
+
+    /// Name of the dead-letter topic
+    pub fn dlq_topic(&self) -> &str {
+        &self.dlq_topic
+    }
+
+    /// Processes messages until one fails in a way that must not be skipped
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that stopped consumption; the failing message's
+    /// offset is left uncommitted
+    pub async fn run(&self, lib: &MyLib) -> Result<()> {
+        info!(
+            "Consuming {} (dead letters go to {})",
+            self.topic, self.dlq_topic
+        );
+        loop {
+            self.process_next(lib).await?;
+        }
+    }
+
+    /// Waits for the next message, processes it and settles its offset
+    pub async fn process_next<P: Processor>(&self, lib: &P) -> Result<Disposition> {
+        let message = self
+            .consumer
+            .recv()
+            .await
+            .context("Failed to receive message")?;
+        let span = info_span!(
+            "message",
+            partition = message.partition(),
+            offset = message.offset()
+        );
+        self.handle(&message, lib).instrument(span).await
+    }
+
+    async fn handle<P: Processor>(
+        &self,
+        message: &BorrowedMessage<'_>,
+        lib: &P,
+    ) -> Result<Disposition> {
+        let result = match message.payload_view::<str>() {
+            Some(Ok(text)) => lib.process(text),
+            Some(Err(_)) => Err(LibError::InvalidInput(
+                "payload is not valid UTF-8".to_string(),
+            )),
+            // Tombstones mark deleted keys; there is nothing to process
+            None => {
+                debug!("Skipping tombstone");
+                return self.commit(message, Disposition::Skipped);
+            }
+        };
+
+        let disposition = match result {
+            Ok(output) => {
+                debug!("Processed message into {} bytes", output.len());
+                Disposition::Committed
+            }
+            Err(e) if e.to_api_error().retryable => {
+                return Err(e).context(format!(
+                    "Message at offset {} failed; not committed",
+                    message.offset()
+                ));
+            }
+            Err(e) => {
+                warn!("Processing failed, sending to {}: {}", self.dlq_topic, e);
+                self.dead_letter(message, &e).await?;
+                Disposition::DeadLettered
+            }
+        };
+        self.commit(message, disposition)
+    }
+
+    /// Commits the message's offset once it is settled as `disposition`
+    fn commit(
+        &self,
+        message: &BorrowedMessage<'_>,
+        disposition: Disposition,
+    ) -> Result<Disposition> {
+        self.consumer
+            .commit_message(message, CommitMode::Sync)
+            .context("Failed to commit offset")?;
+        Ok(disposition)
+    }
+
+    /// Publishes the original message to the dead-letter topic
+    async fn dead_letter(&self, message: &BorrowedMessage<'_>, err: &LibError) -> Result<()> {
+        let error = err.to_string();
+        let offset = message.offset().to_string();
+        let headers = OwnedHeaders::new()
+            .insert(Header {
+                key: "error",
+                value: Some(&error),
+            })
+            .insert(Header {
+                key: "source_offset",
+                value: Some(&offset),
+            });
+
+        let mut record = FutureRecord::to(&self.dlq_topic)
+            .payload(message.payload().unwrap_or_default())
+            .headers(headers);
+        if let Some(key) = message.key() {
+            record = record.key(key);
+        }
+
+        self.producer
+            .send(record, SEND_TIMEOUT)
+            .await
+            .map_err(|(e, _)| e)
+            .context(format!("Failed to publish to {}", self.dlq_topic))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use my_lib::testing::AlwaysFails;
+    use rdkafka::message::Headers;
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::producer::DefaultProducerContext;
+    use rdkafka::{Offset, TopicPartitionList};
+
+    const TOPIC: &str = "input";
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn cluster() -> MockCluster<'static, DefaultProducerContext> {
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic(TOPIC, 1, 1).unwrap();
+        cluster
+            .create_topic(&format!("{}{}", TOPIC, DLQ_SUFFIX), 1, 1)
+            .unwrap();
+        cluster
+    }
+
+    /// Publishes each payload in order; `None` is a tombstone
+    async fn produce(brokers: &str, payloads: &[Option<&[u8]>]) {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .create()
+            .unwrap();
+        for payload in payloads {
+            let mut record = FutureRecord::<(), [u8]>::to(TOPIC);
+            if let Some(payload) = payload {
+                record = record.payload(payload);
+            }
+            producer.send(record, SEND_TIMEOUT).await.unwrap();
+        }
+    }
+
+    /// Committed offset of partition 0, or `None` if nothing was committed
+    fn committed_offset(processor: &KafkaProcessor) -> Option<i64> {
+        let mut partitions = TopicPartitionList::new();
+        partitions.add_partition(TOPIC, 0);
+        let committed = processor
+            .consumer
+            .committed_offsets(partitions, TIMEOUT)
+            .unwrap();
+        match committed.find_partition(TOPIC, 0).unwrap().offset() {
+            Offset::Offset(offset) => Some(offset),
+            _ => None,
+        }
+    }
+
+    async fn next<P: Processor>(processor: &KafkaProcessor, lib: &P) -> Result<Disposition> {
+        tokio::time::timeout(TIMEOUT, processor.process_next(lib))
+            .await
+            .expect("no message received")
+    }
+
+    #[tokio::test]
+    async fn test_success_commits_offset() {
+        let cluster = cluster();
+        produce(&cluster.bootstrap_servers(), &[Some(b"hello")]).await;
+        let processor = KafkaProcessor::new(&cluster.bootstrap_servers(), TOPIC, "ok").unwrap();
+
+        let lib = MyLib::new("config").unwrap();
+        assert_eq!(
+            next(&processor, &lib).await.unwrap(),
+            Disposition::Committed
+        );
+        assert_eq!(committed_offset(&processor), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_operation_failed_goes_to_dlq() {
+        let cluster = cluster();
+        produce(&cluster.bootstrap_servers(), &[Some(b"hello")]).await;
+        let processor =
+            KafkaProcessor::new(&cluster.bootstrap_servers(), TOPIC, "failing").unwrap();
+        // Fails the way a downstream outage would
+        let unavailable = AlwaysFails::new("downstream unavailable");
+
+        assert_eq!(
+            next(&processor, &unavailable).await.unwrap(),
+            Disposition::DeadLettered
+        );
+        assert_eq!(committed_offset(&processor), Some(1));
+
+        let dlq: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", cluster.bootstrap_servers())
+            .set("group.id", "dlq-reader")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .unwrap();
+        dlq.subscribe(&[processor.dlq_topic()]).unwrap();
+        let message = tokio::time::timeout(TIMEOUT, dlq.recv())
+            .await
+            .expect("nothing on the dead-letter topic")
+            .unwrap();
+
+        assert_eq!(message.payload(), Some(&b"hello"[..]));
+        let error = message.headers().unwrap().get(0);
+        assert_eq!(error.key, "error");
+        assert!(String::from_utf8_lossy(error.value.unwrap()).contains("downstream unavailable"));
+    }
+
+    #[tokio::test]
+    async fn test_bad_messages_do_not_block_the_partition() {
+        let cluster = cluster();
+        let payloads: [Option<&[u8]>; 4] = [None, Some(b""), Some(b"\xFF"), Some(b"hello")];
+        produce(&cluster.bootstrap_servers(), &payloads).await;
+        let processor = KafkaProcessor::new(&cluster.bootstrap_servers(), TOPIC, "bad").unwrap();
+
+        let lib = MyLib::new("config").unwrap();
+        let mut dispositions = Vec::new();
+        for _ in &payloads {
+            dispositions.push(next(&processor, &lib).await.unwrap());
+        }
+
+        assert_eq!(
+            dispositions,
+            [
+                Disposition::Skipped,
+                // Empty input, then a payload that is not UTF-8
+                Disposition::DeadLettered,
+                Disposition::DeadLettered,
+                Disposition::Committed,
+            ]
+        );
+        assert_eq!(committed_offset(&processor), Some(4));
+    }
+}
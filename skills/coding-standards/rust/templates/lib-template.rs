@@ -2,15 +2,68 @@
 //!
 //! This template demonstrates:
 //! - Public API design
-//! - Error handling with thiserror
+//! - Error handling with thiserror, non-exhaustive so variants can be added
+//! - Stable machine-readable error codes for API responses
 //! - Documentation with examples
+//! - Composable processors behind a sealed `Processor` trait
+//! - Chunked processing that streams when the processor allows it
 //! - Unit testing
+//! - Build metadata generated by `build-template.rs` (saved as `build.rs`)
+//! - Optional HTTP server behind the `server` feature
+//! - Processors loaded from shared libraries behind the `plugins` feature
+//! - Test doubles for downstream crates behind the `testing` feature
+//! - `no_std` builds (with `alloc`) when the default `std` feature is off
+//! - `unsafe` code that lints require to justify, tested under Miri
+//!   (`ci/miri.sh`)
+//!
+//! Add to Cargo.toml for the `std`, `server`, `testing` and `plugins`
+//! features:
+//! [features]
+//! default = ["std"]
+//! std = ["thiserror/std"]
+//! server = ["std", "dep:axum", "dep:serde", "dep:tokio"]
+//! testing = []
+//! plugins = ["std", "dep:libloading"]
+//!
+//! [dependencies]
+//! thiserror = { version = "2", default-features = false }
+//! axum = { version = "0.7", optional = true }
+//! serde = { version = "1.0", features = ["derive"], optional = true }
+//! tokio = { version = "1.0", features = ["net", "rt-multi-thread"], optional = true }
+//! libloading = { version = "0.8", optional = true }
+//!
+//! [dev-dependencies]
+//! http-body-util = "0.1"
+//! serde_json = "1.0"
+//! tempfile = "3"
+//! tokio = { version = "1.0", features = ["macros", "rt"] }
+//! tower = { version = "0.4", features = ["util"] }
+//! trybuild = "1"
+//!
+//! Embedded targets build with
+//! `cargo build --target thumbv7m-none-eabi --no-default-features`;
+//! `ci/rust-ci.yml` runs that alongside the `std` build.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+// Every unsafe operation gets its own block and `// SAFETY:` comment, even
+// inside an `unsafe fn`
+#![deny(unsafe_op_in_unsafe_fn, clippy::undocumented_unsafe_blocks)]
+
+extern crate alloc;
 
-use std::fmt;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
 use thiserror::Error;
 
 /// Custom error types for this library
+///
+/// New variants may arrive in minor releases, so a `match` outside this
+/// crate needs a wildcard arm.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum LibError {
     #[error("Invalid input: {0}")]
     InvalidInput(String),
@@ -18,12 +71,63 @@ pub enum LibError {
     #[error("Operation failed: {0}")]
     OperationFailed(String),
 
+    /// A processor gave up on an input after this long
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+
+    /// Only with the `std` feature
+    #[cfg(feature = "std")]
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 }
 
+impl LibError {
+    /// Describes this error for an API response
+    ///
+    /// `code` is stable across releases, so clients can branch on it where
+    /// the message may change; it matches the codes `http-server-template.rs`
+    /// sends. Only I/O errors and timeouts are worth retrying: the others
+    /// fail the same way on every attempt.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use my_lib::LibError;
+    ///
+    /// let api = LibError::InvalidInput("empty".to_string()).to_api_error();
+    /// assert_eq!(api.code, "invalid_input");
+    /// assert_eq!(api.message, "Invalid input: empty");
+    /// assert!(!api.retryable);
+    /// ```
+    pub fn to_api_error(&self) -> ApiError {
+        let (code, retryable) = match self {
+            LibError::InvalidInput(_) => ("invalid_input", false),
+            LibError::OperationFailed(_) => ("operation_failed", false),
+            LibError::Timeout(_) => ("timeout", true),
+            #[cfg(feature = "std")]
+            LibError::Io(_) => ("io_error", true),
+        };
+        ApiError {
+            code,
+            message: self.to_string(),
+            retryable,
+        }
+    }
+}
+
+/// A [`LibError`] as an API reports it, from [`LibError::to_api_error`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    /// Stable machine-readable code, e.g. `invalid_input`
+    pub code: &'static str,
+    /// Human-readable description; may change between releases
+    pub message: String,
+    /// Whether the same request may succeed if sent again
+    pub retryable: bool,
+}
+
 /// Type alias for Results in this library
-pub type Result<T> = std::result::Result<T, LibError>;
+pub type Result<T> = core::result::Result<T, LibError>;
 
 /// Main library struct
 ///
@@ -81,6 +185,9 @@ impl MyLib {
     /// let result = lib.process("test").unwrap();
     /// assert_eq!(result, "PROCESSED: test");
     /// ```
+    ///
+    /// Requires the `std` feature; the result is formatted on the heap.
+    #[cfg(feature = "std")]
     pub fn process(&self, input: &str) -> Result<String> {
         if input.is_empty() {
             return Err(LibError::InvalidInput("input cannot be empty".to_string()));
@@ -100,19 +207,840 @@ impl fmt::Display for MyLib {
     }
 }
 
+/// Private supertrait of [`Processor`]
+///
+/// Other crates cannot name it, so they cannot implement `Processor`, and
+/// methods can be added to `Processor` without breaking anyone.
+mod sealed {
+    pub trait Sealed {}
+}
+
 /// Trait for custom behavior
-pub trait Processor {
+///
+/// Sealed: only types in this crate implement it. Compose them with
+/// [`SplitProcessor`], or use the `testing` feature's processors in tests.
+pub trait Processor: sealed::Sealed {
     /// Process a value
     fn process(&self, input: &str) -> Result<String>;
+
+    /// Processes the next chunk of a byte stream, returning the output ready
+    /// so far
+    ///
+    /// [`ChunkStream`] calls this for callers that only want to pass chunks.
+    /// `pending` belongs to the caller: start each stream with an empty
+    /// buffer and pass the same one with every chunk, setting `is_last` on
+    /// the final one (which may be empty). It is a parameter rather than
+    /// state on the processor because one processor may be part way through
+    /// several streams at once, such as a [`SplitProcessor`]'s `inner` or
+    /// one shared by many requests, and `&self` has no lock to guard a
+    /// buffer with in `no_std` builds. The default collects every chunk
+    /// in `pending` and runs [`Processor::process`] on the whole input at
+    /// the end, so output only appears once the stream is complete.
+    /// Processors whose output for a piece of input does not depend on what
+    /// follows can override this to emit output as chunks arrive.
+    ///
+    /// # Errors
+    ///
+    /// Returns `LibError::InvalidInput` if the stream is not valid UTF-8,
+    /// or whatever [`Processor::process`] returns
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// use my_lib::{MyLib, Processor};
+    ///
+    /// let lib = MyLib::new("config").unwrap();
+    /// let mut pending = Vec::new();
+    /// assert!(lib.process_chunk(&mut pending, b"te", false).unwrap().is_empty());
+    /// let output = lib.process_chunk(&mut pending, b"st", true).unwrap();
+    /// assert_eq!(output, b"PROCESSED: test");
+    /// # }
+    /// ```
+    fn process_chunk(&self, pending: &mut Vec<u8>, chunk: &[u8], is_last: bool) -> Result<Vec<u8>> {
+        pending.extend_from_slice(chunk);
+        if !is_last {
+            return Ok(Vec::new());
+        }
+        let input = String::from_utf8(core::mem::take(pending))
+            .map_err(|_| LibError::InvalidInput("input is not valid UTF-8".to_string()))?;
+        Ok(self.process(&input)?.into_bytes())
+    }
 }
 
+/// One byte stream fed through a [`Processor`] chunk by chunk
+///
+/// Keeps the carry-over buffer [`Processor::process_chunk`] needs between
+/// chunks, so callers only pass the chunks. Use a new one for each stream.
+///
+/// # Examples
+///
+/// ```
+/// use my_lib::{ChunkStream, Uppercase};
+///
+/// let mut stream = ChunkStream::new(&Uppercase);
+/// let mut output = stream.process_chunk("stra\u{df}".as_bytes(), false).unwrap();
+/// output.extend(stream.process_chunk(b"e", true).unwrap());
+/// assert_eq!(output, "STRASSE".as_bytes());
+/// ```
+pub struct ChunkStream<'a, P: Processor + ?Sized> {
+    processor: &'a P,
+    pending: Vec<u8>,
+}
+
+impl<'a, P: Processor + ?Sized> ChunkStream<'a, P> {
+    pub fn new(processor: &'a P) -> Self {
+        Self {
+            processor,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Processes the next chunk, returning the output ready so far
+    ///
+    /// Set `is_last` on the final chunk, which may be empty.
+    ///
+    /// # Errors
+    ///
+    /// Whatever [`Processor::process_chunk`] returns
+    pub fn process_chunk(&mut self, chunk: &[u8], is_last: bool) -> Result<Vec<u8>> {
+        self.processor
+            .process_chunk(&mut self.pending, chunk, is_last)
+    }
+}
+
+#[cfg(feature = "std")]
+impl sealed::Sealed for MyLib {}
+
+#[cfg(feature = "std")]
 impl Processor for MyLib {
     fn process(&self, input: &str) -> Result<String> {
         self.process(input)
     }
 }
 
+/// Uppercases text, streaming chunk by chunk
+///
+/// Each character is uppercased on its own, so output can be emitted as
+/// soon as a chunk arrives. Only a UTF-8 sequence cut off at the end of a
+/// chunk is held back in `pending` until the rest of it comes.
+///
+/// # Examples
+///
+/// ```
+/// use my_lib::{Processor, Uppercase};
+///
+/// let mut pending = Vec::new();
+/// let mut output = Uppercase.process_chunk(&mut pending, "stra\u{df}".as_bytes(), false).unwrap();
+/// output.extend(Uppercase.process_chunk(&mut pending, b"e", true).unwrap());
+/// assert_eq!(output, "STRASSE".as_bytes());
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Uppercase;
+
+impl sealed::Sealed for Uppercase {}
+
+impl Processor for Uppercase {
+    fn process(&self, input: &str) -> Result<String> {
+        Ok(input.to_uppercase())
+    }
+
+    fn process_chunk(&self, pending: &mut Vec<u8>, chunk: &[u8], is_last: bool) -> Result<Vec<u8>> {
+        pending.extend_from_slice(chunk);
+        let complete = match core::str::from_utf8(pending) {
+            Ok(text) => text.len(),
+            // A truncated sequence at the end may still be completed
+            Err(e) if e.error_len().is_none() && !is_last => e.valid_up_to(),
+            Err(_) => {
+                return Err(LibError::InvalidInput(
+                    "input is not valid UTF-8".to_string(),
+                ))
+            }
+        };
+        let rest = pending.split_off(complete);
+        let text = core::mem::replace(pending, rest);
+        // Validated above
+        let text = String::from_utf8(text).expect("valid UTF-8 prefix");
+        Ok(text.to_uppercase().into_bytes())
+    }
+}
+
+/// Runs an inner [`Processor`] on each field of a delimited record
+///
+/// The input is split on `delimiter`, each part goes through `inner`, and
+/// the results are joined with the same delimiter. The first part that
+/// fails fails the whole record. With `skip_empty`, empty parts are kept
+/// as they are instead of being passed to `inner`.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "std")] {
+/// use my_lib::{MyLib, Processor, SplitProcessor};
+///
+/// let split = SplitProcessor {
+///     delimiter: ",".to_string(),
+///     inner: Box::new(MyLib::new("config").unwrap()),
+///     skip_empty: true,
+/// };
+/// let result = split.process("a,,b").unwrap();
+/// assert_eq!(result, "PROCESSED: a,,PROCESSED: b");
+/// # }
+/// ```
+pub struct SplitProcessor {
+    pub delimiter: String,
+    pub inner: Box<dyn Processor>,
+    pub skip_empty: bool,
+}
+
+impl sealed::Sealed for SplitProcessor {}
+
+impl Processor for SplitProcessor {
+    fn process(&self, input: &str) -> Result<String> {
+        if self.delimiter.is_empty() {
+            return Err(LibError::InvalidInput(
+                "delimiter cannot be empty".to_string(),
+            ));
+        }
+        let parts = input
+            .split(self.delimiter.as_str())
+            .map(|part| {
+                if part.is_empty() && self.skip_empty {
+                    Ok(String::new())
+                } else {
+                    self.inner.process(part)
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(parts.join(&self.delimiter))
+    }
+}
+
+/// Processors for testing code built on this crate
+///
+/// [`Processor`] is sealed, so downstream tests cannot write their own
+/// failing processor. Enable with the `testing` feature, usually from
+/// `[dev-dependencies]`.
+#[cfg(any(test, feature = "testing"))]
+pub mod testing {
+    use alloc::string::String;
+
+    use super::{sealed, LibError, Processor, Result};
+
+    /// Fails every input with `LibError::OperationFailed(reason)`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "testing")] {
+    /// use my_lib::testing::AlwaysFails;
+    /// use my_lib::{LibError, Processor};
+    ///
+    /// let processor = AlwaysFails::new("downstream unavailable");
+    /// assert!(matches!(processor.process("x"), Err(LibError::OperationFailed(_))));
+    /// # }
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct AlwaysFails {
+        pub reason: String,
+    }
+
+    impl AlwaysFails {
+        pub fn new(reason: impl Into<String>) -> Self {
+            Self {
+                reason: reason.into(),
+            }
+        }
+    }
+
+    impl sealed::Sealed for AlwaysFails {}
+
+    impl Processor for AlwaysFails {
+        fn process(&self, _input: &str) -> Result<String> {
+            Err(LibError::OperationFailed(self.reason.clone()))
+        }
+    }
+}
+
+/// Raw pointer helpers for data handed over through FFI
+///
+/// Each `unsafe` block says why it is sound in a `// SAFETY:` comment, and
+/// `ci/miri.sh` runs the tests under Miri to catch the comments that are
+/// wrong.
+pub mod ffi_utils {
+    /// Reads the value behind `ptr`, leaving the memory untouched
+    ///
+    /// The value is copied out bit for bit. For a type that is not `Copy`
+    /// the caller now owns it, so the original must not be read again or
+    /// dropped; free its memory without running its destructor.
+    ///
+    /// # Safety
+    ///
+    /// - `ptr` must be non-null, aligned for `T` and valid for reads of
+    ///   `size_of::<T>()` bytes
+    /// - the memory must hold an initialized, valid `T`
+    /// - nothing may write to that memory while it is read
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use my_lib::ffi_utils::read_raw_ptr;
+    ///
+    /// let value = 42u32;
+    /// // SAFETY: `&value` points to a live, aligned, initialized `u32`
+    /// assert_eq!(unsafe { read_raw_ptr(&value) }, 42);
+    /// ```
+    pub unsafe fn read_raw_ptr<T>(ptr: *const T) -> T {
+        debug_assert!(!ptr.is_null(), "read_raw_ptr given a null pointer");
+        // SAFETY: the caller guarantees `ptr` is valid for an aligned read
+        // of an initialized `T` that nothing writes to meanwhile
+        unsafe { ptr.read() }
+    }
+}
+
+/// Constants generated by the build script
+mod version_info {
+    include!(concat!(env!("OUT_DIR"), "/version_info.rs"));
+}
+
+/// Placeholder for build metadata that was unavailable at build time
+const UNKNOWN: &str = "unknown";
+
+/// Version and provenance of this build
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuiltInfo {
+    /// Crate version from Cargo.toml
+    pub version: &'static str,
+    /// Short git commit hash, or `"unknown"` when built without git
+    pub git_hash: &'static str,
+    /// Build time in seconds since the Unix epoch
+    pub build_timestamp: u64,
+}
+
+impl BuiltInfo {
+    fn new(version: &'static str, git_hash: Option<&'static str>, build_timestamp: u64) -> Self {
+        Self {
+            version,
+            git_hash: git_hash.unwrap_or(UNKNOWN),
+            build_timestamp,
+        }
+    }
+}
+
+impl fmt::Display for BuiltInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.version, self.git_hash)
+    }
+}
+
+/// Returns version information recorded when the crate was built
+///
+/// # Examples
+///
+/// ```
+/// let info = my_lib::built_info();
+/// assert!(!info.version.is_empty());
+/// ```
+pub fn built_info() -> BuiltInfo {
+    BuiltInfo::new(
+        version_info::VERSION,
+        version_info::GIT_HASH,
+        version_info::BUILD_TIMESTAMP,
+    )
+}
+
+/// Minimal HTTP front end for `MyLib`
+///
+/// Exposes `POST /process` taking `{"input": "..."}` and returning
+/// `{"output": "..."}`. `LibError::InvalidInput` maps to 400,
+/// `LibError::Timeout` to 503 and every other error to 500. Error bodies are
+/// `{"error": "...", "code": "...", "retryable": ...}`, from
+/// [`LibError::to_api_error`](crate::LibError::to_api_error).
+#[cfg(feature = "server")]
+pub mod server {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use axum::extract::State;
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde::{Deserialize, Serialize};
+
+    use super::{LibError, MyLib, Result};
+
+    /// Request body for `POST /process`
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct ProcessRequest {
+        pub input: String,
+    }
+
+    /// Response body for `POST /process`
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct ProcessResponse {
+        pub output: String,
+    }
+
+    /// Error body returned for failed requests
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct ErrorResponse {
+        pub error: String,
+        pub code: String,
+        pub retryable: bool,
+    }
+
+    impl IntoResponse for LibError {
+        fn into_response(self) -> Response {
+            let status = match self {
+                LibError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+                LibError::Timeout(_) => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let api = self.to_api_error();
+            let body = ErrorResponse {
+                error: api.message,
+                code: api.code.to_string(),
+                retryable: api.retryable,
+            };
+            (status, Json(body)).into_response()
+        }
+    }
+
+    /// Builds the router without binding a socket
+    ///
+    /// Useful for in-process tests with `tower::ServiceExt::oneshot`.
+    pub fn router(lib: MyLib) -> Router {
+        Router::new()
+            .route("/process", post(process))
+            .with_state(Arc::new(lib))
+    }
+
+    /// Serves the router on `addr` until the process exits
+    ///
+    /// # Errors
+    ///
+    /// Returns `LibError::Io` if the address cannot be bound
+    pub async fn serve(addr: SocketAddr, lib: MyLib) -> Result<()> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        axum::serve(listener, router(lib)).await?;
+        Ok(())
+    }
+
+    async fn process(
+        State(lib): State<Arc<MyLib>>,
+        Json(request): Json<ProcessRequest>,
+    ) -> Result<Json<ProcessResponse>> {
+        let output = lib.process(&request.input)?;
+        Ok(Json(ProcessResponse { output }))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use axum::body::Body;
+        use axum::http::Request;
+        use http_body_util::BodyExt;
+        use tower::ServiceExt;
+
+        async fn post_process(input: &str) -> (StatusCode, serde_json::Value) {
+            let app = router(MyLib::new("config").unwrap());
+            let body = serde_json::json!({ "input": input }).to_string();
+            let request = Request::post("/process")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice(&bytes).unwrap())
+        }
+
+        #[tokio::test]
+        async fn test_process_valid_input() {
+            let (status, body) = post_process("hello").await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body["output"], "PROCESSED: hello");
+        }
+
+        #[tokio::test]
+        async fn test_process_empty_input() {
+            let (status, body) = post_process("").await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert!(body["error"]
+                .as_str()
+                .unwrap()
+                .contains("input cannot be empty"));
+        }
+
+        async fn error_response(err: LibError) -> (StatusCode, ErrorResponse) {
+            let response = err.into_response();
+            let status = response.status();
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice(&bytes).unwrap())
+        }
+
+        #[tokio::test]
+        async fn test_operation_failed_maps_to_500() {
+            let (status, body) =
+                error_response(LibError::OperationFailed("boom".to_string())).await;
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+            assert_eq!(body.code, "operation_failed");
+            assert!(!body.retryable);
+        }
+
+        #[tokio::test]
+        async fn test_timeout_maps_to_retryable_503() {
+            let (status, body) =
+                error_response(LibError::Timeout(std::time::Duration::from_secs(5))).await;
+            assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(body.code, "timeout");
+            assert!(body.retryable);
+            assert_eq!(body.error, "Timed out after 5s");
+        }
+    }
+}
+
+/// Processors loaded from shared libraries at run time
+///
+/// [`Processor`] is sealed, and trait objects have no stable layout across
+/// compilers, so a plugin does not implement the trait. It exports a C
+/// function, `plugin_register`, returning a [`PluginApi`] table of
+/// functions, and [`load_plugin`] wraps that table in a `Box<dyn Processor>`.
+/// `tests/plugins/shout.rs` is a complete plugin.
+///
+/// # The `plugin_register` contract
+///
+/// - `plugin_register` is exported unmangled as
+///   `extern "C" fn() -> *const PluginApi` and returns a table that lives as
+///   long as the library stays loaded
+/// - the table starts with `abi_version`, set to [`PLUGIN_ABI_VERSION`],
+///   and `size`, set to `size_of::<PluginApi>()`; nothing else is read
+///   unless both match this build
+/// - `name` is a NUL-terminated UTF-8 string, and no function is null
+/// - `create` returns the state of one instance (null is fine), which the
+///   host passes to every `process` call and finally to `destroy`, once
+/// - `process` gets `len` bytes of UTF-8 at `input`, writes its output, or
+///   an error message, to `*output`, and returns 0 on success and anything
+///   else on failure. The host copies the buffer and hands it back to
+///   `free_buffer`, so the plugin's own allocator frees it
+/// - no function unwinds into the host: a Rust plugin catches panics with
+///   `std::panic::catch_unwind` and reports them as errors
+///
+/// Any change to the contract bumps `PLUGIN_ABI_VERSION`.
+#[cfg(feature = "plugins")]
+pub mod plugins {
+    use std::ffi::{c_char, c_void, CStr, OsStr};
+    use std::{mem, ptr, slice};
+
+    use libloading::Library;
+
+    use super::{sealed, LibError, Processor, Result};
+
+    /// Version of the plugin contract this build loads
+    pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+    /// Signature of the `plugin_register` function every plugin exports
+    pub type RegisterFn = unsafe extern "C" fn() -> *const PluginApi;
+
+    const REGISTER_SYMBOL: &[u8] = b"plugin_register\0";
+
+    /// Bytes returned by a plugin's `process`, freed by its `free_buffer`
+    #[repr(C)]
+    #[derive(Debug)]
+    pub struct PluginBuffer {
+        pub ptr: *mut u8,
+        pub len: usize,
+        pub capacity: usize,
+    }
+
+    /// Function table returned by a plugin's `plugin_register`
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct PluginApi {
+        /// Must be [`PLUGIN_ABI_VERSION`]
+        pub abi_version: u32,
+        /// Must be `size_of::<PluginApi>()`
+        pub size: usize,
+        /// Shown in error messages
+        pub name: *const c_char,
+        pub create: Option<unsafe extern "C" fn() -> *mut c_void>,
+        pub process:
+            Option<unsafe extern "C" fn(*mut c_void, *const u8, usize, *mut PluginBuffer) -> i32>,
+        pub free_buffer: Option<unsafe extern "C" fn(PluginBuffer)>,
+        pub destroy: Option<unsafe extern "C" fn(*mut c_void)>,
+    }
+
+    /// The fields every version of [`PluginApi`] starts with
+    #[repr(C)]
+    struct PluginHeader {
+        abi_version: u32,
+        size: usize,
+    }
+
+    /// A checked [`PluginApi`]
+    struct Functions {
+        name: String,
+        create: unsafe extern "C" fn() -> *mut c_void,
+        process: unsafe extern "C" fn(*mut c_void, *const u8, usize, *mut PluginBuffer) -> i32,
+        free_buffer: unsafe extern "C" fn(PluginBuffer),
+        destroy: unsafe extern "C" fn(*mut c_void),
+    }
+
+    /// Loads the plugin at `path` as a processor
+    ///
+    /// The plugin's table is checked against this build before any of its
+    /// functions run. The library stays loaded until the processor is
+    /// dropped.
+    ///
+    /// # Safety
+    ///
+    /// Loading a library runs its initialisers, and nothing can check that
+    /// its functions keep the [contract](self). Only load plugins trusted
+    /// as much as this crate's own code.
+    ///
+    /// # Errors
+    ///
+    /// Returns `LibError::OperationFailed` if the library cannot be loaded
+    /// or does not export `plugin_register`, and `LibError::InvalidInput`
+    /// if it was built for another ABI version or its table is incomplete
+    pub unsafe fn load_plugin(path: impl AsRef<OsStr>) -> Result<Box<dyn Processor>> {
+        let path = path.as_ref();
+        let failed = |reason: String| {
+            LibError::OperationFailed(format!("plugin {}: {reason}", path.to_string_lossy()))
+        };
+        // SAFETY: the caller trusts the library, initialisers included
+        let library = unsafe { Library::new(path) }.map_err(|err| failed(err.to_string()))?;
+        // SAFETY: the contract gives `plugin_register` this signature
+        let register = unsafe { library.get::<RegisterFn>(REGISTER_SYMBOL) }
+            .map(|symbol| *symbol)
+            .map_err(|err| failed(err.to_string()))?;
+        // SAFETY: the library is loaded, and `plugin_register` takes nothing
+        let api = unsafe { register() };
+        // SAFETY: the contract makes `api` point to a table starting with a
+        // `PluginHeader`
+        let functions = unsafe { check_api(api) }.map_err(|reason| {
+            LibError::InvalidInput(format!("plugin {}: {reason}", path.to_string_lossy()))
+        })?;
+        // SAFETY: `create` takes nothing, and the library is loaded
+        let state = unsafe { (functions.create)() };
+        Ok(Box::new(PluginProcessor {
+            functions,
+            state,
+            _library: library,
+        }))
+    }
+
+    /// Reads the table at `api`, rejecting one built for another ABI
+    ///
+    /// # Safety
+    ///
+    /// A non-null, aligned `api` must point to a `PluginHeader`, followed
+    /// by the rest of a `PluginApi` when the header matches this build
+    unsafe fn check_api(api: *const PluginApi) -> core::result::Result<Functions, String> {
+        if api.is_null() || !api.is_aligned() {
+            return Err("plugin_register returned an invalid pointer".to_string());
+        }
+        // SAFETY: `api` is non-null and aligned, and every version of the
+        // table starts with the header
+        let header = unsafe { &*api.cast::<PluginHeader>() };
+        if header.abi_version != PLUGIN_ABI_VERSION {
+            return Err(format!(
+                "built for ABI version {}, this build needs {PLUGIN_ABI_VERSION}",
+                header.abi_version
+            ));
+        }
+        if header.size != mem::size_of::<PluginApi>() {
+            return Err(format!(
+                "function table is {} bytes, expected {}",
+                header.size,
+                mem::size_of::<PluginApi>()
+            ));
+        }
+        // SAFETY: the version and size match, so `api` points to a whole
+        // `PluginApi`
+        let api = unsafe { api.read() };
+        if api.name.is_null() {
+            return Err("plugin has no name".to_string());
+        }
+        // SAFETY: the contract makes a non-null `name` a NUL-terminated
+        // string that lives as long as the library
+        let name = unsafe { CStr::from_ptr(api.name) }
+            .to_str()
+            .map_err(|_| "plugin name is not valid UTF-8".to_string())?;
+        let (Some(create), Some(process), Some(free_buffer), Some(destroy)) =
+            (api.create, api.process, api.free_buffer, api.destroy)
+        else {
+            return Err(format!("plugin {name} leaves a function null"));
+        };
+        Ok(Functions {
+            name: name.to_string(),
+            create,
+            process,
+            free_buffer,
+            destroy,
+        })
+    }
+
+    /// One instance of a loaded plugin
+    struct PluginProcessor {
+        functions: Functions,
+        state: *mut c_void,
+        /// Keeps the functions loaded; dropped after `destroy` has run
+        _library: Library,
+    }
+
+    impl sealed::Sealed for PluginProcessor {}
+
+    impl Processor for PluginProcessor {
+        fn process(&self, input: &str) -> Result<String> {
+            let name = &self.functions.name;
+            let mut output = PluginBuffer {
+                ptr: ptr::null_mut(),
+                len: 0,
+                capacity: 0,
+            };
+            // SAFETY: `state` came from `create` and lives until drop,
+            // `input` is `input.len()` bytes of UTF-8, and `output` is
+            // writable
+            let status = unsafe {
+                (self.functions.process)(self.state, input.as_ptr(), input.len(), &mut output)
+            };
+            let bytes = if output.ptr.is_null() {
+                Vec::new()
+            } else {
+                // SAFETY: the plugin wrote `len` initialized bytes at a
+                // non-null `ptr`, which it frees only in `free_buffer`
+                let bytes = unsafe { slice::from_raw_parts(output.ptr, output.len) }.to_vec();
+                // SAFETY: the buffer came from this plugin's `process` and
+                // is handed back once, after the copy
+                unsafe { (self.functions.free_buffer)(output) };
+                bytes
+            };
+            let text = String::from_utf8(bytes).map_err(|_| {
+                LibError::OperationFailed(format!("plugin {name}: output is not valid UTF-8"))
+            })?;
+            if status == 0 {
+                Ok(text)
+            } else {
+                Err(LibError::OperationFailed(format!("plugin {name}: {text}")))
+            }
+        }
+    }
+
+    impl Drop for PluginProcessor {
+        fn drop(&mut self) {
+            // SAFETY: `state` came from `create`, this is the only call to
+            // `destroy`, and the library unloads only after it returns
+            unsafe { (self.functions.destroy)(self.state) };
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+        use std::path::{Path, PathBuf};
+        use std::process::Command;
+
+        use super::*;
+
+        /// Compiles `tests/plugins/shout.rs` into `out_dir` as the library
+        /// `name`, passing `args` on to rustc, and returns the library's path
+        fn build_plugin(out_dir: &Path, name: &str, args: &[&str]) -> PathBuf {
+            let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/plugins/shout.rs");
+            let status = Command::new("rustc")
+                .args(["--edition", "2021", "--crate-type", "cdylib"])
+                .args(["--crate-name", name])
+                .args(args)
+                .arg("--out-dir")
+                .arg(out_dir)
+                .arg(&source)
+                .status()
+                .unwrap();
+            assert!(status.success(), "building {} failed", source.display());
+            out_dir.join(format!("{DLL_PREFIX}{name}{DLL_SUFFIX}"))
+        }
+
+        fn load_error(path: &Path) -> LibError {
+            // SAFETY: the sample plugin keeps the contract
+            match unsafe { load_plugin(path) } {
+                Ok(_) => panic!("{} loaded", path.display()),
+                Err(err) => err,
+            }
+        }
+
+        #[test]
+        #[cfg_attr(miri, ignore = "runs rustc and loads a shared library")]
+        fn test_load_sample_plugin() {
+            // Declared first, so the library is unloaded before it is deleted
+            let dir = tempfile::TempDir::new().unwrap();
+            let path = build_plugin(dir.path(), "shout", &[]);
+            // SAFETY: the sample plugin keeps the contract
+            let plugin = unsafe { load_plugin(&path) }.unwrap();
+
+            assert_eq!(plugin.process("hello").unwrap(), "HELLO!");
+            match plugin.process("") {
+                Err(LibError::OperationFailed(message)) => {
+                    assert_eq!(message, "plugin shout: input cannot be empty");
+                }
+                other => panic!("expected OperationFailed, got {other:?}"),
+            }
+
+            let mut pending = Vec::new();
+            assert!(plugin
+                .process_chunk(&mut pending, b"str", false)
+                .unwrap()
+                .is_empty());
+            let output = plugin.process_chunk(&mut pending, b"eam", true).unwrap();
+            assert_eq!(output, b"STREAM!");
+        }
+
+        #[test]
+        #[cfg_attr(miri, ignore = "runs rustc and loads a shared library")]
+        fn test_rejects_other_abi_version() {
+            let dir = tempfile::TempDir::new().unwrap();
+            let path = build_plugin(dir.path(), "shout_old_abi", &["--cfg", "old_abi"]);
+            match load_error(&path) {
+                LibError::InvalidInput(message) => {
+                    assert!(message.contains("built for ABI version 0"), "{message}");
+                }
+                other => panic!("expected InvalidInput, got {other:?}"),
+            }
+        }
+
+        #[test]
+        #[cfg_attr(miri, ignore = "runs rustc and loads a shared library")]
+        fn test_rejects_missing_register_function() {
+            let dir = tempfile::TempDir::new().unwrap();
+            let path = build_plugin(dir.path(), "shout_no_register", &["--cfg", "no_register"]);
+            match load_error(&path) {
+                LibError::OperationFailed(message) => {
+                    assert!(message.contains("plugin_register"), "{message}");
+                }
+                other => panic!("expected OperationFailed, got {other:?}"),
+            }
+        }
+    }
+}
+
+/// Asserts that `result` is `Err(LibError::$variant(..))`, ignoring the
+/// payload, and panics with what it got instead otherwise
 #[cfg(test)]
+macro_rules! assert_lib_err {
+    ($result:expr, $variant:ident) => {
+        match $result {
+            Err(LibError::$variant { .. }) => {}
+            other => panic!(
+                "expected Err(LibError::{}), got {:?}",
+                stringify!($variant),
+                other
+            ),
+        }
+    };
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -125,12 +1053,7 @@ mod tests {
 
     #[test]
     fn test_new_empty_config() {
-        let lib = MyLib::new("");
-        assert!(lib.is_err());
-        match lib {
-            Err(LibError::InvalidInput(_)) => (),
-            _ => panic!("Expected InvalidInput error"),
-        }
+        assert_lib_err!(MyLib::new(""), InvalidInput);
     }
 
     #[test]
@@ -144,8 +1067,70 @@ mod tests {
     #[test]
     fn test_process_empty_input() {
         let lib = MyLib::new("config").unwrap();
-        let result = lib.process("");
-        assert!(result.is_err());
+        assert_lib_err!(lib.process(""), InvalidInput);
+    }
+
+    #[test]
+    fn test_assert_lib_err_matches_each_variant() {
+        assert_lib_err!(
+            Err::<(), _>(LibError::InvalidInput("x".to_string())),
+            InvalidInput
+        );
+        assert_lib_err!(
+            Err::<(), _>(LibError::OperationFailed("x".to_string())),
+            OperationFailed
+        );
+        assert_lib_err!(
+            Err::<(), _>(LibError::Timeout(Duration::from_secs(1))),
+            Timeout
+        );
+        assert_lib_err!(
+            Err::<(), _>(LibError::Io(std::io::ErrorKind::NotFound.into())),
+            Io
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "expected Err(LibError::InvalidInput), got Ok(\"fine\")")]
+    fn test_assert_lib_err_rejects_ok() {
+        assert_lib_err!(Ok::<_, LibError>("fine"), InvalidInput);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected Err(LibError::InvalidInput), got Err(OperationFailed(")]
+    fn test_assert_lib_err_rejects_other_variant() {
+        assert_lib_err!(
+            Err::<(), _>(LibError::OperationFailed("x".to_string())),
+            InvalidInput
+        );
+    }
+
+    #[test]
+    fn test_api_error_codes() {
+        let cases = [
+            (
+                LibError::InvalidInput("x".to_string()),
+                "invalid_input",
+                false,
+            ),
+            (
+                LibError::OperationFailed("x".to_string()),
+                "operation_failed",
+                false,
+            ),
+            (LibError::Timeout(Duration::from_secs(30)), "timeout", true),
+            (
+                LibError::Io(std::io::ErrorKind::TimedOut.into()),
+                "io_error",
+                true,
+            ),
+        ];
+        for (err, code, retryable) in cases {
+            let api = err.to_api_error();
+            assert_eq!(api.code, code);
+            assert_eq!(api.retryable, retryable, "{}", code);
+            assert_eq!(api.message, err.to_string());
+        }
     }
 
     #[test]
@@ -161,4 +1146,246 @@ mod tests {
         let result = processor.process("trait");
         assert!(result.is_ok());
     }
+
+    fn split(inner: Box<dyn Processor>, skip_empty: bool) -> SplitProcessor {
+        SplitProcessor {
+            delimiter: ",".to_string(),
+            inner,
+            skip_empty,
+        }
+    }
+
+    #[test]
+    fn test_split_processor_processes_each_part() {
+        let processor = split(Box::new(Uppercase), false);
+        assert_eq!(processor.process("a,b,c").unwrap(), "A,B,C");
+    }
+
+    #[test]
+    fn test_split_processor_propagates_first_error() {
+        use std::sync::{Arc, Mutex};
+
+        /// Fails on parts starting with `!`, recording every part it sees
+        struct Picky(Arc<Mutex<Vec<String>>>);
+
+        impl sealed::Sealed for Picky {}
+
+        impl Processor for Picky {
+            fn process(&self, input: &str) -> Result<String> {
+                self.0.lock().unwrap().push(input.to_string());
+                match input.strip_prefix('!') {
+                    Some(reason) => Err(LibError::OperationFailed(reason.to_string())),
+                    None => Ok(input.to_string()),
+                }
+            }
+        }
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let processor = split(Box::new(Picky(Arc::clone(&seen))), false);
+
+        match processor.process("a,!first,!second,d") {
+            Err(LibError::OperationFailed(reason)) => assert_eq!(reason, "first"),
+            other => panic!("Expected OperationFailed, got {:?}", other),
+        }
+        assert_eq!(*seen.lock().unwrap(), ["a", "!first"]);
+    }
+
+    #[test]
+    fn test_split_processor_empty_parts() {
+        let lib = || Box::new(MyLib::new("config").unwrap());
+
+        let skipping = split(lib(), true);
+        assert_eq!(skipping.process(",a,").unwrap(), ",PROCESSED: a,");
+
+        // MyLib rejects empty input, so passing empty parts through fails
+        let strict = split(lib(), false);
+        assert_lib_err!(strict.process("a,,b"), InvalidInput);
+    }
+
+    #[test]
+    fn test_split_processor_rejects_empty_delimiter() {
+        let processor = SplitProcessor {
+            delimiter: String::new(),
+            inner: Box::new(Uppercase),
+            skip_empty: false,
+        };
+        assert_lib_err!(processor.process("abc"), InvalidInput);
+    }
+
+    // Each case in tests/ui/lib_error is built against this crate as a
+    // downstream user would; TRYBUILD=overwrite refreshes the expected errors
+    #[test]
+    #[cfg_attr(miri, ignore = "trybuild spawns cargo")]
+    fn test_lib_error_matches_need_wildcard() {
+        let cases = trybuild::TestCases::new();
+        cases.compile_fail("tests/ui/lib_error/exhaustive_match.rs");
+        cases.pass("tests/ui/lib_error/wildcard_match.rs");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "trybuild spawns cargo")]
+    fn test_unhandled_lib_result_is_rejected() {
+        trybuild::TestCases::new().compile_fail("tests/ui/lib_error/unused_result.rs");
+    }
+
+    /// Feeds `input` to `processor` in chunks of `size` bytes
+    fn process_in_chunks(processor: &dyn Processor, input: &[u8], size: usize) -> Result<Vec<u8>> {
+        let mut stream = ChunkStream::new(processor);
+        let mut output = Vec::new();
+        for chunk in input.chunks(size) {
+            output.extend(stream.process_chunk(chunk, false)?);
+        }
+        output.extend(stream.process_chunk(&[], true)?);
+        Ok(output)
+    }
+
+    #[test]
+    fn test_chunks_match_whole_input() {
+        // Multi-byte characters land on chunk boundaries for most sizes
+        let input = "grüße, Ωmega: ﬁne straße ǆ\nzweite Zeile 🦀";
+        let lib = MyLib::new("config").unwrap();
+        for size in 1..=input.len() {
+            let streamed = process_in_chunks(&Uppercase, input.as_bytes(), size).unwrap();
+            assert_eq!(
+                streamed,
+                Uppercase.process(input).unwrap().as_bytes(),
+                "size {}",
+                size
+            );
+            let buffered = process_in_chunks(&lib, input.as_bytes(), size).unwrap();
+            assert_eq!(
+                buffered,
+                lib.process(input).unwrap().as_bytes(),
+                "size {}",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn test_uppercase_streams_before_last_chunk() {
+        let mut pending = Vec::new();
+        // "é" is 0xC3 0xA9; the first byte alone has to wait
+        let output = Uppercase
+            .process_chunk(&mut pending, b"caf\xC3", false)
+            .unwrap();
+        assert_eq!(output, b"CAF");
+        assert_eq!(pending, b"\xC3");
+        let output = Uppercase
+            .process_chunk(&mut pending, b"\xA9", true)
+            .unwrap();
+        assert_eq!(output, "É".as_bytes());
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_chunks_reject_invalid_utf8() {
+        for processor in [&Uppercase as &dyn Processor, &MyLib::new("config").unwrap()] {
+            assert_lib_err!(process_in_chunks(processor, b"ok \xFF", 2), InvalidInput);
+            // A sequence still open when the stream ends is invalid too
+            assert_lib_err!(process_in_chunks(processor, b"ok \xC3", 2), InvalidInput);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "trybuild spawns cargo")]
+    fn test_processor_is_sealed() {
+        // rustc lists the implementors, and the features decide which exist
+        let case = match (cfg!(feature = "testing"), cfg!(feature = "plugins")) {
+            (false, false) => "tests/ui/processor/external_impl.rs",
+            (true, false) => "tests/ui/processor/external_impl_testing.rs",
+            (false, true) => "tests/ui/processor/external_impl_plugins.rs",
+            (true, true) => "tests/ui/processor/external_impl_all.rs",
+        };
+        trybuild::TestCases::new().compile_fail(case);
+    }
+
+    #[test]
+    fn test_always_fails() {
+        let split = split(Box::new(testing::AlwaysFails::new("offline")), true);
+        assert!(matches!(
+            split.process("a,,b"),
+            Err(LibError::OperationFailed(reason)) if reason == "offline"
+        ));
+    }
+
+    #[test]
+    fn test_read_raw_ptr_copies_value() {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        #[repr(C)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        let point = Point { x: 1, y: -2 };
+        // SAFETY: `&point` points to a live, aligned, initialized `Point`
+        let copy = unsafe { ffi_utils::read_raw_ptr(&point) };
+        assert_eq!(copy, point);
+    }
+
+    #[test]
+    fn test_read_raw_ptr_takes_ownership() {
+        // Handed over as a raw pointer, as a C caller would
+        let raw = Box::into_raw(Box::new(String::from("owned")));
+
+        // SAFETY: `raw` comes from `Box::into_raw`, so it is valid, aligned
+        // and initialized, and nothing else uses it
+        let value = unsafe { ffi_utils::read_raw_ptr(raw) };
+        // The string now lives in `value`; free the box without dropping
+        // it a second time, which Miri would report as a double free
+        // SAFETY: `raw` still owns the allocation made by `Box::new`, and
+        // `MaybeUninit` has the same layout as the `String` in it
+        drop(unsafe { Box::from_raw(raw.cast::<core::mem::MaybeUninit<String>>()) });
+
+        assert_eq!(value, "owned");
+    }
+
+    #[test]
+    fn test_built_info_version() {
+        let info = built_info();
+        assert!(!info.version.is_empty());
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.git_hash.is_empty());
+    }
+
+    #[test]
+    fn test_built_info_without_git_hash() {
+        let info = BuiltInfo::new("1.2.3", None, 0);
+        assert_eq!(info.git_hash, "unknown");
+        assert_eq!(info.to_string(), "1.2.3 (unknown)");
+    }
+}
+
+/// What remains of the API without `std`, checked by
+/// `cargo test --no-default-features`
+#[cfg(all(test, not(feature = "std")))]
+mod no_std_tests {
+    use super::*;
+
+    #[test]
+    fn test_error_display_without_std() {
+        let err = LibError::InvalidInput("bad".to_string());
+        assert_eq!(err.to_string(), "Invalid input: bad");
+    }
+
+    #[test]
+    fn test_new_without_std() {
+        assert_eq!(MyLib::new("embedded").unwrap().config(), "embedded");
+        assert_lib_err!(MyLib::new(""), InvalidInput);
+    }
+
+    #[test]
+    fn test_uppercase_without_std() {
+        let mut pending = Vec::new();
+        let output = Uppercase
+            .process_chunk(&mut pending, b"no_std", true)
+            .unwrap();
+        assert_eq!(output, b"NO_STD");
+    }
+
+    #[test]
+    fn test_built_info_without_std() {
+        assert_eq!(built_info().version, env!("CARGO_PKG_VERSION"));
+    }
 }
@@ -2,126 +2,60 @@
 //!
 //! Demonstrates:
 //! - CLI argument parsing with clap
-//! - Structured logging with tracing
+//! - Structured logging with tracing, as text or JSON (`--log-format`)
 //! - Error handling with anyhow
 //! - Clean main function
+//! - Error-specific exit codes (see `AppError`)
+//! - Short error messages with hints; `--debug-errors` shows the full chain
+//! - An environment report for bug triage when a run fails (`--diagnostics`)
+//! - `--color` and `NO_COLOR` control styling of errors and logs on stderr
+//! - Panics logged through tracing, optionally aborting (`--abort-on-panic`)
+//! - Ctrl-C stops a run cleanly and exits 130; a second Ctrl-C exits at once
+//! - `completions <SHELL>`, `man`, `init` and `config validate` subcommands
+//! - Config files that fill in the flags not given on the command line,
+//!   with per-environment profiles (`--profile prod` or `APP_PROFILE=prod`)
+//!
+//! The application logic lives in the library half of the crate
+//! (`app-lib-template.rs`, saved as `src/lib.rs`); this file only wires the
+//! command line and logging to it.
 
-use anyhow::{Context, Result};
-use clap::Parser;
-use tracing::{info, warn, error};
-use tracing_subscriber;
-
-/// CLI application
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// Input file path
-    #[arg(short, long)]
-    input: String,
-
-    /// Output file path
-    #[arg(short, long)]
-    output: Option<String>,
-
-    /// Verbose mode
-    #[arg(short, long)]
-    verbose: bool,
-
-    /// Configuration file
-    #[arg(short, long, default_value = "config.toml")]
-    config: String,
-}
-
-/// Application configuration
-#[derive(Debug)]
-struct Config {
-    input: String,
-    output: Option<String>,
-    config_path: String,
-}
-
-impl Config {
-    fn from_args(args: Args) -> Self {
-        Self {
-            input: args.input,
-            output: args.output,
-            config_path: args.config,
-        }
-    }
-}
-
-/// Main application logic
-struct App {
-    config: Config,
-}
-
-impl App {
-    fn new(config: Config) -> Self {
-        Self { config }
-    }
-
-    /// Run the application
-    fn run(&self) -> Result<()> {
-        info!("Starting application");
-
-        // Read input
-        let input = self.read_input()
-            .context("Failed to read input file")?;
-
-        info!("Read {} bytes from input", input.len());
-
-        // Process data
-        let output = self.process(&input)
-            .context("Failed to process data")?;
-
-        // Write output
-        self.write_output(&output)
-            .context("Failed to write output")?;
-
-        info!("Application completed successfully");
-        Ok(())
-    }
-
-    fn read_input(&self) -> Result<String> {
-        info!("Reading from: {}", self.config.input);
-        std::fs::read_to_string(&self.config.input)
-            .context(format!("Cannot read file: {}", self.config.input))
-    }
-
-    fn process(&self, input: &str) -> Result<String> {
-        info!("Processing input");
-
-        if input.is_empty() {
-            warn!("Input is empty, returning unchanged");
-            return Ok(input.to_string());
-        }
-
-        // Example processing: convert to uppercase
-        let output = input.to_uppercase();
-
-        info!("Processed {} bytes", output.len());
-        Ok(output)
-    }
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::Ordering;
 
-    fn write_output(&self, data: &str) -> Result<()> {
-        match &self.config.output {
-            Some(path) => {
-                info!("Writing to: {}", path);
-                std::fs::write(path, data)
-                    .context(format!("Cannot write file: {}", path))?;
-            }
-            None => {
-                info!("Writing to stdout");
-                println!("{}", data);
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use my_app::{App, Args, Config, LogFormat, Settings};
+use tracing::{info, warn};
+
+fn main() -> ExitCode {
+    // Parse command line arguments, keeping the matches to tell flags that
+    // were given from defaults
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let debug_errors = args.debug_errors;
+    let color = args.color.enabled_for(&io::stderr());
+
+    match run(args, &matches, color) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if !my_app::is_quiet(&e) {
+                eprintln!("{}", my_app::render_error(&e, debug_errors, color));
             }
+            ExitCode::from(my_app::exit_code(&e))
         }
-        Ok(())
     }
 }
 
-fn main() -> Result<()> {
-    // Parse command line arguments
-    let args = Args::parse();
+/// `color` is `--color` resolved for stderr, where logs and errors go
+fn run(args: Args, matches: &ArgMatches, color: bool) -> Result<()> {
+    // Subcommands only print to stdout; no logging or config needed
+    if let Some(command) = args.command {
+        return command.run(&mut io::stdout().lock());
+    }
 
     // Setup logging
     let log_level = if args.verbose {
@@ -130,84 +64,90 @@ fn main() -> Result<()> {
         tracing::Level::INFO
     };
 
-    tracing_subscriber::fmt()
-        .with_max_level(log_level)
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_file(true)
-        .with_line_number(true)
-        .init();
-
-    info!("Application started");
+    let log_format = args.log_format;
 
     // Create configuration
-    let config = Config::from_args(args);
-
-    // Run application
+    let (websocket, http_port) = (args.websocket, args.http_port);
+    let (abort_on_panic, debug_panic) = (args.abort_on_panic, args.debug_panic);
+    let diagnostics = args.diagnostics;
+    let mut config = Config::from_args(args);
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    // The default config.toml is optional; files named with --config are not
+    if !from_cli("config") {
+        config.config_paths.retain(|path| Path::new(path).exists());
+    }
+    let mut table = config.load_settings()?;
+    config.apply_profile(&mut table)?;
+    let settings = Settings::from_table(table)?;
+    config.apply_settings(&settings, from_cli)?;
+    // Logged once logging is set up
+    let profile = config.profile.clone().map(|name| {
+        format!(
+            "{} (overrides: {})",
+            name,
+            config.profile_overrides.join(", ")
+        )
+    });
     let app = App::new(config);
-    app.run().context("Application execution failed")?;
-
-    Ok(())
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::NamedTempFile;
-    use std::io::Write;
-
-    #[test]
-    fn test_process_empty_input() {
-        let config = Config {
-            input: "test.txt".to_string(),
-            output: None,
-            config_path: "config.toml".to_string(),
-        };
-        let app = App::new(config);
-
-        let result = app.process("");
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "");
+    // Logs go to stderr so stdout carries only results, and through the
+    // app so they don't tear its progress bars
+    let logs = tracing_subscriber::fmt()
+        .with_writer(app.log_writer())
+        .with_max_level(log_level)
+        .with_target(false)
+        .with_thread_ids(false)
+        .with_file(true)
+        .with_line_number(true);
+    match log_format {
+        LogFormat::Text => logs.with_ansi(color).init(),
+        // Each event lists its spans, so it carries the run ID and file
+        LogFormat::Json => logs.json().init(),
     }
 
-    #[test]
-    fn test_process_uppercase() {
-        let config = Config {
-            input: "test.txt".to_string(),
-            output: None,
-            config_path: "config.toml".to_string(),
-        };
-        let app = App::new(config);
-
-        let result = app.process("hello world");
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "HELLO WORLD");
+    // Installed after the subscriber so panics reach the same output
+    my_app::install_panic_hook(abort_on_panic);
+    if debug_panic {
+        panic!("--debug-panic was given");
     }
 
-    #[test]
-    fn test_read_write_integration() -> Result<()> {
-        // Create temporary input file
-        let mut input_file = NamedTempFile::new()?;
-        writeln!(input_file, "test content")?;
-
-        // Create temporary output file
-        let output_file = NamedTempFile::new()?;
-
-        let config = Config {
-            input: input_file.path().to_string_lossy().to_string(),
-            output: Some(output_file.path().to_string_lossy().to_string()),
-            config_path: "config.toml".to_string(),
-        };
-
-        let app = App::new(config);
-        let result = app.run();
-
-        assert!(result.is_ok());
+    info!("Application started");
+    for key in settings.unknown_keys() {
+        warn!("Ignoring unknown config key: {}", key);
+    }
+    if let Some(profile) = profile {
+        info!("Using config profile {}", profile);
+    }
 
-        // Verify output
-        let output = std::fs::read_to_string(output_file.path())?;
-        assert_eq!(output, "TEST CONTENT\n");
+    let result = run_app(&app, websocket, http_port);
+    if result.is_err() && diagnostics {
+        eprintln!("{}", app.diagnostics());
+    }
+    result
+}
 
-        Ok(())
+/// Runs `app` as the server asked for, or over its inputs
+fn run_app(app: &App, websocket: Option<SocketAddr>, http_port: Option<u16>) -> Result<()> {
+    if let Some(addr) = websocket {
+        tokio::runtime::Runtime::new()?
+            .block_on(app.run_websocket_server(addr))
+            .context("WebSocket server failed")?;
+    } else if let Some(port) = http_port {
+        tokio::runtime::Runtime::new()?
+            .block_on(app.run_http_server(port))
+            .context("HTTP server failed")?;
+    } else {
+        // The servers keep the default handler, so Ctrl-C still stops them
+        let interrupted = app.interrupt_flag();
+        ctrlc::set_handler(move || {
+            // A second Ctrl-C skips the cleanup and exits immediately
+            if interrupted.swap(true, Ordering::Relaxed) {
+                std::process::exit(130);
+            }
+        })
+        .context("Failed to install Ctrl-C handler")?;
+        app.run().context("Application execution failed")?;
     }
+
+    Ok(())
 }
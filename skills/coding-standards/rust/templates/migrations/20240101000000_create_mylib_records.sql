@@ -0,0 +1,7 @@
+-- Results of MyLib::process, written by postgres-repository-template.rs
+CREATE TABLE IF NOT EXISTS mylib_records (
+    id         BIGSERIAL   PRIMARY KEY,
+    input      TEXT        NOT NULL,
+    output     TEXT        NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,163 @@
+//! Redis cache template using redis-rs
+//!
+//! Demonstrates:
+//! - A storage-agnostic async `Cache` trait
+//! - An implementation over redis-rs's auto-reconnecting `ConnectionManager`
+//! - Namespaced keys, so several services can share one Redis
+//! - Expiry through `SETEX`
+//! - Tests against a throwaway Redis started with testcontainers
+//!
+//! Add to Cargo.toml:
+//! [dependencies]
+//! async-trait = "0.1"
+//! redis = { version = "0.27", features = ["tokio-comp", "connection-manager"] }
+//!
+//! [dev-dependencies]
+//! testcontainers-modules = { version = "0.11", features = ["redis"] }
+//! tokio = { version = "1.0", features = ["macros", "rt-multi-thread", "time"] }
+//!
+//! The tests need a running Docker daemon and are ignored by default; run
+//! them with `cargo test -- --ignored`.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Client, RedisError};
+
+/// String key-value cache with optional expiry
+#[async_trait]
+pub trait Cache {
+    type Error;
+
+    /// Returns the value stored under `key`, if present and not expired
+    async fn get(&self, key: &str) -> Result<Option<String>, Self::Error>;
+
+    /// Stores `value` under `key`, expiring after `ttl` if given
+    async fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), Self::Error>;
+
+    /// Removes `key`; returns false if it was not present
+    async fn delete(&self, key: &str) -> Result<bool, Self::Error>;
+}
+
+/// [`Cache`] backed by Redis, with every key stored as `<namespace>:<key>`
+///
+/// Entries outlive the process, so a restarted client sees what an earlier
+/// one stored under the same namespace.
+#[derive(Clone)]
+pub struct RedisCache {
+    conn: ConnectionManager,
+    namespace: String,
+}
+
+impl RedisCache {
+    /// Connects to the Redis server at `url` (e.g. `redis://127.0.0.1/`)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL is invalid or the server is unreachable
+    pub async fn new(url: &str, namespace: &str) -> Result<Self, RedisError> {
+        let client = Client::open(url)?;
+        let conn = ConnectionManager::new(client).await?;
+        Ok(Self {
+            conn,
+            namespace: namespace.to_string(),
+        })
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}:{}", self.namespace, key)
+    }
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    type Error = RedisError;
+
+    async fn get(&self, key: &str) -> Result<Option<String>, RedisError> {
+        // The manager is a cheap handle onto one shared connection
+        self.conn.clone().get(self.key(key)).await
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Option<Duration>) -> Result<(), RedisError> {
+        let mut conn = self.conn.clone();
+        match ttl {
+            // SETEX counts whole seconds and rejects 0, so round up
+            Some(ttl) => {
+                let seconds = ttl.as_secs_f64().ceil().max(1.0) as u64;
+                conn.set_ex(self.key(key), value, seconds).await
+            }
+            None => conn.set(self.key(key), value).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool, RedisError> {
+        let removed: u64 = self.conn.clone().del(self.key(key)).await?;
+        Ok(removed > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testcontainers_modules::redis::{Redis, REDIS_PORT};
+    use testcontainers_modules::testcontainers::runners::AsyncRunner;
+    use testcontainers_modules::testcontainers::ContainerAsync;
+
+    async fn server() -> (ContainerAsync<Redis>, String) {
+        let node = Redis::default().start().await.unwrap();
+        let port = node.get_host_port_ipv4(REDIS_PORT).await.unwrap();
+        (node, format!("redis://127.0.0.1:{}/", port))
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_value_survives_client_restart() {
+        let (_node, url) = server().await;
+
+        let cache = RedisCache::new(&url, "app").await.unwrap();
+        cache.set("greeting", "hello", None).await.unwrap();
+        drop(cache);
+
+        let restarted = RedisCache::new(&url, "app").await.unwrap();
+        assert_eq!(
+            restarted.get("greeting").await.unwrap().as_deref(),
+            Some("hello")
+        );
+        assert!(restarted.delete("greeting").await.unwrap());
+        assert_eq!(restarted.get("greeting").await.unwrap(), None);
+        assert!(!restarted.delete("greeting").await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_keys_are_namespaced() {
+        let (_node, url) = server().await;
+        let app = RedisCache::new(&url, "app").await.unwrap();
+        let other = RedisCache::new(&url, "other").await.unwrap();
+
+        app.set("key", "value", None).await.unwrap();
+
+        assert_eq!(other.get("key").await.unwrap(), None);
+        let mut raw = app.conn.clone();
+        let stored: Option<String> = raw.get("app:key").await.unwrap();
+        assert_eq!(stored.as_deref(), Some("value"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs Docker"]
+    async fn test_ttl_expires_entry() {
+        let (_node, url) = server().await;
+        let cache = RedisCache::new(&url, "app").await.unwrap();
+
+        cache
+            .set("short", "lived", Some(Duration::from_millis(300)))
+            .await
+            .unwrap();
+        let ttl: i64 = cache.conn.clone().ttl("app:short").await.unwrap();
+        assert_eq!(ttl, 1);
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(cache.get("short").await.unwrap(), None);
+    }
+}
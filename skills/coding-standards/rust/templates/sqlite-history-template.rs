@@ -0,0 +1,111 @@
+//! SQLite persistence template for `Calculator` operation history
+//!
+//! Demonstrates:
+//! - Embedding SQLite with rusqlite
+//! - Creating the schema on open, so a fresh file is ready to use
+//! - Parameterized queries
+//! - Tests against a temporary database file
+//!
+//! Add to Cargo.toml:
+//! [dependencies]
+//! rusqlite = { version = "0.32", features = ["bundled"] }
+//!
+//! [dev-dependencies]
+//! tempfile = "3.0"
+//!
+//! The `bundled` feature compiles SQLite from source, so no system library
+//! is needed.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, Result};
+
+/// Operation history kept in a SQLite database
+///
+/// Each row holds the operation as text (e.g. `"1.5 + 2.3"`), its result,
+/// and when it was saved, in seconds since the Unix epoch.
+pub struct SqliteHistoryStore {
+    conn: Connection,
+}
+
+impl SqliteHistoryStore {
+    /// Opens the database at `path`, creating the file and table if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Opens a private database that lives only as long as the store
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS history (
+                id        INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp INTEGER NOT NULL,
+                operation TEXT    NOT NULL,
+                result    REAL    NOT NULL
+            )",
+            [],
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Appends an operation and its result to the history
+    pub fn save_operation(&self, op: &str, result: f64) -> Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+        self.conn.execute(
+            "INSERT INTO history (timestamp, operation, result) VALUES (?1, ?2, ?3)",
+            params![timestamp, op, result],
+        )?;
+        Ok(())
+    }
+
+    /// Returns every saved operation, oldest first
+    pub fn load_history(&self) -> Result<Vec<(String, f64)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT operation, result FROM history ORDER BY id")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_history_persists_across_reopen() -> Result<()> {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("history.db");
+
+        {
+            let store = SqliteHistoryStore::open(&path)?;
+            store.save_operation("1.5 + 2.3", 3.8)?;
+            store.save_operation("10 / 4", 2.5)?;
+        }
+
+        let store = SqliteHistoryStore::open(&path)?;
+        assert_eq!(
+            store.load_history()?,
+            vec![("1.5 + 2.3".to_string(), 3.8), ("10 / 4".to_string(), 2.5)]
+        );
+
+        store.save_operation("2 * 3", 6.0)?;
+        assert_eq!(store.load_history()?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_store_is_empty() -> Result<()> {
+        let store = SqliteHistoryStore::open_in_memory()?;
+        assert!(store.load_history()?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Sample plugin for `my_lib::plugins`: uppercases its input and appends
+//! `!`
+//!
+//! Build it as a shared library and load it with `load_plugin`:
+//!
+//! ```text
+//! rustc --edition 2021 --crate-type cdylib tests/plugins/shout.rs
+//! ```
+//!
+//! The `repr(C)` types copy the ones in `my_lib::plugins` rather than
+//! depending on the crate: the contract is the C ABI, so a plugin needs
+//! nothing from `my_lib` to follow it. Empty input fails, to show an error
+//! crossing the boundary.
+//!
+//! The tests also build it broken on purpose: `--cfg old_abi` claims ABI
+//! version 0, and `--cfg no_register` leaves out the entry point.
+
+// Without the entry point nothing reaches the table
+#![cfg_attr(no_register, allow(dead_code))]
+
+use std::ffi::{c_char, c_void};
+use std::mem::ManuallyDrop;
+use std::panic::{self, AssertUnwindSafe};
+
+const ABI_VERSION: u32 = if cfg!(old_abi) { 0 } else { 1 };
+
+#[repr(C)]
+pub struct PluginBuffer {
+    ptr: *mut u8,
+    len: usize,
+    capacity: usize,
+}
+
+#[repr(C)]
+pub struct PluginApi {
+    abi_version: u32,
+    size: usize,
+    name: *const c_char,
+    create: Option<unsafe extern "C" fn() -> *mut c_void>,
+    process: Option<unsafe extern "C" fn(*mut c_void, *const u8, usize, *mut PluginBuffer) -> i32>,
+    free_buffer: Option<unsafe extern "C" fn(PluginBuffer)>,
+    destroy: Option<unsafe extern "C" fn(*mut c_void)>,
+}
+
+// SAFETY: the table is never written, and only points to a string literal
+// and functions, which any thread may use
+unsafe impl Sync for PluginApi {}
+
+static API: PluginApi = PluginApi {
+    abi_version: ABI_VERSION,
+    size: std::mem::size_of::<PluginApi>(),
+    name: c"shout".as_ptr(),
+    create: Some(create),
+    process: Some(process),
+    free_buffer: Some(free_buffer),
+    destroy: Some(destroy),
+};
+
+/// The entry point `load_plugin` looks up
+#[cfg(not(no_register))]
+#[no_mangle]
+pub extern "C" fn plugin_register() -> *const PluginApi {
+    &API
+}
+
+/// State of one loaded instance
+struct Shout {
+    suffix: String,
+}
+
+unsafe extern "C" fn create() -> *mut c_void {
+    let state = Box::new(Shout {
+        suffix: "!".to_string(),
+    });
+    Box::into_raw(state).cast()
+}
+
+unsafe extern "C" fn process(
+    state: *mut c_void,
+    input: *const u8,
+    len: usize,
+    output: *mut PluginBuffer,
+) -> i32 {
+    // A panic must not unwind into the host
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        // SAFETY: the host passes the state `create` returned, not yet
+        // destroyed
+        let state = unsafe { &*state.cast::<Shout>() };
+        // SAFETY: the host passes `len` readable bytes at `input`
+        let input = unsafe { std::slice::from_raw_parts(input, len) };
+        match std::str::from_utf8(input) {
+            Ok("") => Err("input cannot be empty".to_string()),
+            Ok(text) => Ok(text.to_uppercase() + &state.suffix),
+            Err(_) => Err("input is not valid UTF-8".to_string()),
+        }
+    }));
+    let (status, text) = match result {
+        Ok(Ok(text)) => (0, text),
+        Ok(Err(message)) => (1, message),
+        Err(_) => (1, "plugin panicked".to_string()),
+    };
+    // Freed by `free_buffer` once the host has copied it
+    let mut bytes = ManuallyDrop::new(text.into_bytes());
+    let buffer = PluginBuffer {
+        ptr: bytes.as_mut_ptr(),
+        len: bytes.len(),
+        capacity: bytes.capacity(),
+    };
+    // SAFETY: the host passes a writable `PluginBuffer`
+    unsafe { output.write(buffer) };
+    status
+}
+
+unsafe extern "C" fn free_buffer(buffer: PluginBuffer) {
+    // SAFETY: the host only hands back buffers `process` made from a `Vec`
+    drop(unsafe { Vec::from_raw_parts(buffer.ptr, buffer.len, buffer.capacity) });
+}
+
+unsafe extern "C" fn destroy(state: *mut c_void) {
+    // SAFETY: the host passes the state `create` returned, exactly once
+    drop(unsafe { Box::from_raw(state.cast::<Shout>()) });
+}
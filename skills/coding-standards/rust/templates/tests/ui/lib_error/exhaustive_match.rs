@@ -0,0 +1,15 @@
+// Naming every variant is not enough: LibError is #[non_exhaustive], so a
+// variant added in a later release would otherwise break this match
+use my_lib::LibError;
+
+fn status(err: &LibError) -> u16 {
+    match err {
+        LibError::InvalidInput(_) => 400,
+        LibError::OperationFailed(_) => 500,
+        LibError::Io(_) => 500,
+    }
+}
+
+fn main() {
+    status(&LibError::InvalidInput("empty".to_string()));
+}
@@ -0,0 +1,10 @@
+// Dropping the result of `process` would hide an InvalidInput error;
+// handle it with `?`, `match`, or `unwrap` in tests
+#![deny(unused_must_use)]
+
+use my_lib::MyLib;
+
+fn main() {
+    let lib = MyLib::new("config").unwrap();
+    lib.process("");
+}
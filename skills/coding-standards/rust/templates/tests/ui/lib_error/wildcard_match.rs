@@ -0,0 +1,15 @@
+// The migration for exhaustive_match.rs: keep the arms that matter and let
+// a wildcard cover the rest, including variants added later
+use my_lib::LibError;
+
+fn status(err: &LibError) -> u16 {
+    match err {
+        LibError::InvalidInput(_) => 400,
+        _ => 500,
+    }
+}
+
+fn main() {
+    assert_eq!(status(&LibError::InvalidInput("empty".to_string())), 400);
+    assert_eq!(status(&LibError::OperationFailed("boom".to_string())), 500);
+}
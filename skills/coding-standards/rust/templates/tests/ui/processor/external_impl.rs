@@ -0,0 +1,12 @@
+// Processor is sealed, so a crate depending on my_lib cannot implement it
+use my_lib::{Processor, Result};
+
+struct Reverse;
+
+impl Processor for Reverse {
+    fn process(&self, input: &str) -> Result<String> {
+        Ok(input.chars().rev().collect())
+    }
+}
+
+fn main() {}
@@ -0,0 +1,3 @@
+// external_impl.rs built with the `testing` and `plugins` features, whose
+// `AlwaysFails` and `PluginProcessor` join the implementors rustc lists
+include!("external_impl.rs");
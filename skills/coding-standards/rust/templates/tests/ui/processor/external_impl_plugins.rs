@@ -0,0 +1,3 @@
+// external_impl.rs built with the `plugins` feature, whose
+// `PluginProcessor` joins the implementors rustc lists
+include!("external_impl.rs");
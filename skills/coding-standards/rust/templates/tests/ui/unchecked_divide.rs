@@ -0,0 +1,9 @@
+// Dropping the result of `divide` would hide a division by zero
+#![deny(unused_must_use)]
+
+use calculator::Calculator;
+
+fn main() {
+    let calc = Calculator::new(2);
+    calc.divide(1.0, 0.0);
+}
@@ -0,0 +1,323 @@
+//! Unix domain socket transport template
+//!
+//! Demonstrates:
+//! - Local IPC with tokio's `UnixListener` / `UnixStream`
+//! - Line-delimited request/response framing
+//! - Graceful shutdown with socket file cleanup
+//!
+//! Add to Cargo.toml:
+//! [dependencies]
+//! my_lib = { path = "../my_lib" }
+//! tokio = { version = "1.0", features = ["full"] }
+//! tracing = "0.1"
+//!
+//! [dev-dependencies]
+//! tempfile = "3.0"
+//!
+//! Wire protocol: one request per line; the server answers each line with
+//! `OK <output>` or `ERR <code> <message>`, where `code` is the error's
+//! [`LibError::to_api_error`] code.
+
+#![cfg(unix)]
+
+use std::future::Future;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use my_lib::{LibError, Processor};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{debug, info, warn};
+
+/// Server exposing a `Processor` over a Unix domain socket
+pub struct UnixProcessorServer {
+    listener: UnixListener,
+    path: PathBuf,
+    processor: Arc<dyn Processor + Send + Sync>,
+}
+
+impl UnixProcessorServer {
+    /// Binds the server to `path`
+    ///
+    /// A socket file nothing accepts connections on, left behind by a crashed
+    /// server, is removed before binding. A socket a server still listens
+    /// on, or any other kind of file at `path`, is left alone and reported
+    /// as `AddrInUse`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an IO error if the socket cannot be bound
+    pub fn bind<P>(path: impl AsRef<Path>, processor: P) -> io::Result<Self>
+    where
+        P: Processor + Send + Sync + 'static,
+    {
+        let path = path.as_ref().to_path_buf();
+        match std::fs::symlink_metadata(&path) {
+            Ok(meta) if meta.file_type().is_socket() => {
+                match std::os::unix::net::UnixStream::connect(&path) {
+                    Ok(_) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AddrInUse,
+                            format!("a server is already listening on {}", path.display()),
+                        ));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                        warn!("Removing stale socket: {}", path.display());
+                        std::fs::remove_file(&path)?;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} exists and is not a socket", path.display()),
+                ));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        info!("Listening on {}", path.display());
+
+        Ok(Self {
+            listener,
+            path,
+            processor: Arc::new(processor),
+        })
+    }
+
+    /// Gets the socket path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accepts connections until `shutdown` completes
+    ///
+    /// The socket file is removed when the server stops.
+    ///
+    /// # Errors
+    ///
+    /// Returns an IO error if accepting a connection fails
+    pub async fn run_until(self, shutdown: impl Future<Output = ()>) -> io::Result<()> {
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => {
+                    info!("Shutting down socket server");
+                    return Ok(());
+                }
+                accepted = self.listener.accept() => {
+                    let (stream, _) = accepted?;
+                    let processor = Arc::clone(&self.processor);
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(stream, processor).await {
+                            warn!("Connection error: {}", e);
+                        }
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl Drop for UnixProcessorServer {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Failed to remove socket {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+async fn handle_connection(
+    stream: UnixStream,
+    processor: Arc<dyn Processor + Send + Sync>,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        debug!("Received {} bytes", line.len());
+        let response = match processor.process(&line) {
+            Ok(output) => format!("OK {}\n", output),
+            Err(e) => {
+                let code = e.to_api_error().code;
+                match e {
+                    // The client rebuilds these, so send only their message
+                    LibError::InvalidInput(message) | LibError::OperationFailed(message) => {
+                        format!("ERR {} {}\n", code, message)
+                    }
+                    other => format!("ERR {} {}\n", code, other),
+                }
+            }
+        };
+        writer.write_all(response.as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+/// Client for `UnixProcessorServer`
+pub struct UnixProcessorClient {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl UnixProcessorClient {
+    /// Connects to the server listening on `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns an IO error if the socket cannot be reached
+    pub async fn connect(path: impl AsRef<Path>) -> io::Result<Self> {
+        let (reader, writer) = UnixStream::connect(path).await?.into_split();
+        Ok(Self {
+            reader: BufReader::new(reader),
+            writer,
+        })
+    }
+
+    /// Sends one input and waits for the processed result
+    ///
+    /// # Errors
+    ///
+    /// Returns `LibError::InvalidInput` if the input contains a newline or
+    /// the server rejected it as invalid, `LibError::OperationFailed` for
+    /// any other error on the server, and `LibError::Io` on transport
+    /// failure
+    pub async fn send(&mut self, input: &str) -> my_lib::Result<String> {
+        if input.contains('\n') {
+            return Err(LibError::InvalidInput(
+                "input cannot contain newlines".to_string(),
+            ));
+        }
+
+        self.writer.write_all(input.as_bytes()).await?;
+        self.writer.write_all(b"\n").await?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            return Err(LibError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let line = line.trim_end_matches('\n');
+
+        if let Some(output) = line.strip_prefix("OK ") {
+            Ok(output.to_string())
+        } else if let Some(error) = line.strip_prefix("ERR ") {
+            let (code, message) = error.split_once(' ').unwrap_or((error, ""));
+            let message = message.to_string();
+            match code {
+                "invalid_input" => Err(LibError::InvalidInput(message)),
+                _ => Err(LibError::OperationFailed(message)),
+            }
+        } else {
+            Err(LibError::OperationFailed(format!(
+                "malformed response: {}",
+                line
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use my_lib::MyLib;
+    use tempfile::TempDir;
+    use tokio::sync::oneshot;
+
+    fn start_server(path: &Path) -> (oneshot::Sender<()>, tokio::task::JoinHandle<io::Result<()>>) {
+        let lib = MyLib::new("config").unwrap();
+        let server = UnixProcessorServer::bind(path, lib).unwrap();
+        let (tx, rx) = oneshot::channel();
+        let handle = tokio::spawn(server.run_until(async {
+            let _ = rx.await;
+        }));
+        (tx, handle)
+    }
+
+    #[tokio::test]
+    async fn test_exchange_messages() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("processor.sock");
+        let (shutdown, handle) = start_server(&path);
+
+        let mut client = UnixProcessorClient::connect(&path).await.unwrap();
+        assert_eq!(client.send("hello").await.unwrap(), "PROCESSED: hello");
+        assert_eq!(client.send("world").await.unwrap(), "PROCESSED: world");
+
+        shutdown.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_server_error_is_reported() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("processor.sock");
+        let (shutdown, handle) = start_server(&path);
+
+        let mut client = UnixProcessorClient::connect(&path).await.unwrap();
+        match client.send("").await {
+            Err(LibError::InvalidInput(msg)) => assert_eq!(msg, "input cannot be empty"),
+            other => panic!("Expected InvalidInput, got {:?}", other),
+        }
+
+        shutdown.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_socket_removed_on_shutdown() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("processor.sock");
+        let (shutdown, handle) = start_server(&path);
+        assert!(path.exists());
+
+        shutdown.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_bind_replaces_stale_socket() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("processor.sock");
+        let stale = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        drop(stale);
+        assert!(path.exists());
+
+        let (shutdown, handle) = start_server(&path);
+        let mut client = UnixProcessorClient::connect(&path).await.unwrap();
+        assert!(client.send("again").await.is_ok());
+
+        shutdown.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bind_refuses_live_socket() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("processor.sock");
+        let (shutdown, handle) = start_server(&path);
+
+        let lib = MyLib::new("config").unwrap();
+        match UnixProcessorServer::bind(&path, lib) {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::AddrInUse),
+            Ok(_) => panic!("Second server bound a live socket"),
+        }
+
+        // The first server still owns the path
+        let mut client = UnixProcessorClient::connect(&path).await.unwrap();
+        assert!(client.send("still here").await.is_ok());
+
+        shutdown.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+//! WebSocket server mode for the application library
+//!
+//! Save as `src/websocket.rs` and declare it with `pub mod websocket;` in
+//! `src/lib.rs`. Enabled at runtime with `--websocket <ADDR>`.
+//!
+//! Demonstrates:
+//! - Accepting WebSocket connections with tokio-tungstenite
+//! - Processing text frames through `MyLib`
+//! - Rejecting binary frames with a close frame
+//!
+//! Add to Cargo.toml:
+//! [dependencies]
+//! futures-util = "0.3"
+//! my_lib = { path = "../my_lib" }
+//! tokio = { version = "1.0", features = ["full"] }
+//! tokio-tungstenite = "0.24"
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use my_lib::MyLib;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::App;
+
+impl App {
+    /// Serves `MyLib::process` over WebSocket on `addr`
+    ///
+    /// Each text frame is processed and answered with the result, or with
+    /// `error: <message>` if processing fails. Binary frames close the
+    /// connection with status 1003 (unsupported data).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address cannot be bound
+    pub async fn run_websocket_server(&self, addr: SocketAddr) -> Result<()> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .context(format!("Cannot bind {}", addr))?;
+        self.serve_websocket(listener).await
+    }
+
+    /// Accepts WebSocket connections on an already-bound listener
+    pub async fn serve_websocket(&self, listener: TcpListener) -> Result<()> {
+        let lib = Arc::new(MyLib::new(self.config.config_paths.join(","))?);
+        info!("WebSocket server listening on {}", listener.local_addr()?);
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let lib = Arc::clone(&lib);
+            tokio::spawn(
+                async move {
+                    if let Err(e) = handle_connection(stream, lib).await {
+                        warn!("Connection error: {:#}", e);
+                    }
+                }
+                .instrument(info_span!("websocket", %peer)),
+            );
+        }
+    }
+}
+
+async fn handle_connection(stream: TcpStream, lib: Arc<MyLib>) -> Result<()> {
+    let mut ws = tokio_tungstenite::accept_async(stream)
+        .await
+        .context("WebSocket handshake failed")?;
+    debug!("Connection opened");
+
+    while let Some(message) = ws.next().await {
+        match message? {
+            Message::Text(text) => {
+                let reply = match lib.process(&text) {
+                    Ok(output) => output,
+                    Err(e) => format!("error: {}", e),
+                };
+                ws.send(Message::Text(reply)).await?;
+            }
+            Message::Binary(_) => {
+                debug!("Rejecting binary frame");
+                let frame = CloseFrame {
+                    code: CloseCode::Unsupported,
+                    reason: "binary frames are not supported".into(),
+                };
+                ws.send(Message::Close(Some(frame))).await?;
+            }
+            Message::Close(_) => break,
+            // Ping/pong replies are handled by tungstenite
+            _ => {}
+        }
+    }
+
+    debug!("Connection closed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use tokio_tungstenite::connect_async;
+
+    async fn start_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = App::new(Config {
+            config_paths: vec!["config.toml".to_string()],
+            ..Config::default()
+        });
+        tokio::spawn(async move { app.serve_websocket(listener).await });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_text_messages_are_processed() {
+        let addr = start_server().await;
+        let (mut ws, _) = connect_async(format!("ws://{}", addr)).await.unwrap();
+
+        for input in ["hello", "world", "third"] {
+            ws.send(Message::Text(input.into())).await.unwrap();
+            let reply = ws.next().await.unwrap().unwrap();
+            assert_eq!(reply.into_text().unwrap(), format!("PROCESSED: {}", input));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_processing_error_is_reported() {
+        let addr = start_server().await;
+        let (mut ws, _) = connect_async(format!("ws://{}", addr)).await.unwrap();
+
+        ws.send(Message::Text(String::new())).await.unwrap();
+        let reply = ws.next().await.unwrap().unwrap();
+        assert!(reply.into_text().unwrap().starts_with("error: "));
+    }
+
+    #[tokio::test]
+    async fn test_binary_frame_is_rejected() {
+        let addr = start_server().await;
+        let (mut ws, _) = connect_async(format!("ws://{}", addr)).await.unwrap();
+
+        ws.send(Message::Text("before".into())).await.unwrap();
+        let reply = ws.next().await.unwrap().unwrap();
+        assert_eq!(reply.into_text().unwrap(), "PROCESSED: before");
+
+        ws.send(Message::Binary(vec![1, 2, 3])).await.unwrap();
+        match ws.next().await.unwrap().unwrap() {
+            Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Unsupported),
+            other => panic!("Expected close frame, got {:?}", other),
+        }
+    }
+}